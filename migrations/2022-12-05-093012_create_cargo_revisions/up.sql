@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "cargo_revisions" (
+  "key" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "cargo_key" VARCHAR NOT NULL references cargoes("key"),
+  "revision" BIGINT NOT NULL,
+  "config" JSON NOT NULL,
+  "replicas" BIGINT NOT NULL,
+  "dns_entry" VARCHAR,
+  "environnements" TEXT[] NOT NULL,
+  "created_at" timestamptz NOT NULL DEFAULT now(),
+  UNIQUE ("cargo_key", "revision")
+);
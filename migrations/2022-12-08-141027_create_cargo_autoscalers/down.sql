@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "cargo_scale_events";
+DROP TABLE "cargo_autoscalers";
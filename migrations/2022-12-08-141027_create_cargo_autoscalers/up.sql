@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE "cargo_autoscalers" (
+  "cargo_key" VARCHAR NOT NULL UNIQUE PRIMARY KEY references cargoes("key"),
+  "min_replicas" BIGINT NOT NULL CHECK (min_replicas >= 0),
+  "max_replicas" BIGINT NOT NULL CHECK (max_replicas >= min_replicas),
+  "target_cpu" BIGINT CHECK (target_cpu > 0),
+  "target_memory" BIGINT CHECK (target_memory > 0)
+);
+
+CREATE TABLE "cargo_scale_events" (
+  "key" UUID NOT NULL PRIMARY KEY,
+  "cargo_key" VARCHAR NOT NULL references cargoes("key"),
+  "from_replicas" BIGINT NOT NULL,
+  "to_replicas" BIGINT NOT NULL,
+  "cpu" FLOAT8,
+  "memory" FLOAT8,
+  "reason" VARCHAR NOT NULL,
+  "created_at" timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE "proxy_routes" (
+  "key" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "name" VARCHAR NOT NULL,
+  "namespace_name" VARCHAR NOT NULL references namespaces("name"),
+  "mode" proxy_template_modes NOT NULL,
+  "host" VARCHAR,
+  "path" VARCHAR NOT NULL DEFAULT '/',
+  "cargo_key" VARCHAR NOT NULL references cargoes("key"),
+  "target_port" BIGINT NOT NULL CHECK (target_port > 0 AND target_port < 65536),
+  "stream_port" BIGINT CHECK (stream_port > 0 AND stream_port < 65536),
+  "ssl_certificate" VARCHAR,
+  "ssl_certificate_key" VARCHAR,
+  "ssl_redirect" BOOLEAN NOT NULL DEFAULT FALSE,
+  UNIQUE ("host", "path"),
+  UNIQUE ("stream_port")
+);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE "proxy_template_revisions" (
+  "key" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "template_name" VARCHAR NOT NULL references proxy_templates("name"),
+  "revision" BIGINT NOT NULL,
+  "mode" proxy_template_modes NOT NULL,
+  "content" TEXT NOT NULL,
+  "created_at" timestamptz NOT NULL DEFAULT now(),
+  UNIQUE ("template_name", "revision")
+);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE "certificates" (
+  "name" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "sans" TEXT[] NOT NULL,
+  "not_after" timestamptz NOT NULL,
+  "created_at" timestamptz NOT NULL DEFAULT now()
+);
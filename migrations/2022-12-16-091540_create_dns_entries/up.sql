@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE "dns_entries" (
+  "cargo_instance_key" VARCHAR NOT NULL UNIQUE PRIMARY KEY references cargo_instances("key"),
+  "domain" VARCHAR NOT NULL,
+  "ip_address" VARCHAR NOT NULL
+);
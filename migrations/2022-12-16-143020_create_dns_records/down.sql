@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "dns_records";
+DROP TYPE "dns_record_kinds";
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TYPE "dns_record_kinds" AS ENUM ('a', 'aaaa', 'cname', 'srv', 'txt');
+
+CREATE TABLE "dns_records" (
+  "key" VARCHAR NOT NULL UNIQUE PRIMARY KEY,
+  "name" VARCHAR NOT NULL,
+  "namespace_name" VARCHAR NOT NULL references namespaces("name"),
+  "kind" dns_record_kinds NOT NULL,
+  "domain" VARCHAR NOT NULL,
+  "value" VARCHAR NOT NULL,
+  "port" BIGINT CHECK (port > 0 AND port < 65536),
+  "priority" BIGINT CHECK (priority >= 0 AND priority < 65536),
+  "weight" BIGINT CHECK (weight >= 0 AND weight < 65536)
+);
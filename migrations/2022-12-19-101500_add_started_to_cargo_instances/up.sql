@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "cargo_instances" ADD COLUMN "started" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "cargo_autoscalers"
+  DROP CONSTRAINT "cargo_autoscalers_min_replicas_check",
+  ADD CONSTRAINT "cargo_autoscalers_min_replicas_check" CHECK (min_replicas >= 0);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+UPDATE "cargo_autoscalers"
+  SET "min_replicas" = 1, "max_replicas" = GREATEST("max_replicas", 1)
+  WHERE "min_replicas" < 1;
+ALTER TABLE "cargo_autoscalers"
+  DROP CONSTRAINT "cargo_autoscalers_min_replicas_check",
+  ADD CONSTRAINT "cargo_autoscalers_min_replicas_check" CHECK (min_replicas >= 1);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "nginx_logs" ALTER COLUMN "status" TYPE INT;
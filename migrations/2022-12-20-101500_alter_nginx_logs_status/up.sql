@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE "nginx_logs" ALTER COLUMN "status" TYPE BIGINT;
@@ -25,6 +25,20 @@ pub struct Cli {
   /// Config directory
   #[clap(long, default_value = "/etc/nanocl")]
   pub(crate) config_dir: String,
+  /// Number of days the proxy access logs are kept, 0 to keep them forever
+  /// [default: 7]
+  #[clap(long)]
+  pub(crate) nginx_log_retention: Option<u64>,
+  /// Email of the ACME account, certificates are issued only when it's set
+  #[clap(long)]
+  pub(crate) acme_email: Option<String>,
+  /// Directory url of the ACME server
+  /// [default: https://acme-v02.api.letsencrypt.org/directory]
+  #[clap(long)]
+  pub(crate) acme_directory: Option<String>,
+  /// Don't verify the tls certificate of the ACME server, for test servers like pebble
+  #[clap(long)]
+  pub(crate) acme_insecure: bool,
 }
 
 /// Cli arguments unit test
@@ -41,6 +55,10 @@ mod tests {
     assert_eq!(args.docker_host, None);
     assert_eq!(args.state_dir, None);
     assert_eq!(args.config_dir, String::from("/etc/nanocl"));
+    assert_eq!(args.nginx_log_retention, None);
+    assert_eq!(args.acme_email, None);
+    assert_eq!(args.acme_directory, None);
+    assert!(!args.acme_insecure);
   }
 
   /// Test cli arguments with custom values
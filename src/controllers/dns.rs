@@ -1,17 +1,20 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use regex::Regex;
 use ntex::http::StatusCode;
 use tokio::{fs, io::AsyncWriteExt};
 use bollard::Docker;
 
 use thiserror::Error;
-use regex::Error as RegexError;
 use std::io::Error as IoError;
 use bollard::errors::Error as DockerError;
 
 use crate::{utils, repositories};
-use crate::models::{ArgState, CargoPartial};
+use crate::utils::metrics::DockerErrorMetric;
+use crate::models::{
+  Pool, ArgState, CargoPartial, DaemonConfig, DnsEntryItem, DnsRecordItem,
+  DnsRecordKinds, DnsSettings, DnsSettingsPartial,
+};
 use crate::errors::{HttpResponseError, IntoHttpResponseError, DaemonError};
 
 use crate::utils::errors::docker_error_ref;
@@ -20,10 +23,10 @@ use crate::utils::errors::docker_error_ref;
 pub enum DnsError {
   #[error("dnsmasq io error")]
   Io(#[from] IoError),
-  #[error("dnsmasq regex error")]
-  Regex(#[from] RegexError),
   #[error("dnsmasq docker_api error")]
   Docker(#[from] DockerError),
+  #[error("dnsmasq settings error")]
+  Settings(#[from] serde_json::Error),
 }
 
 impl IntoHttpResponseError for DnsError {
@@ -33,11 +36,11 @@ impl IntoHttpResponseError for DnsError {
         msg: format!("dnsmasq io error {:#?}", err),
         status: StatusCode::INTERNAL_SERVER_ERROR,
       },
-      DnsError::Regex(err) => HttpResponseError {
-        msg: format!("dnsmasq regex error {:#?}", err),
+      DnsError::Docker(err) => docker_error_ref(err),
+      DnsError::Settings(err) => HttpResponseError {
+        msg: format!("dnsmasq settings error {:#?}", err),
         status: StatusCode::INTERNAL_SERVER_ERROR,
       },
-      DnsError::Docker(err) => docker_error_ref(err),
     }
   }
 }
@@ -57,65 +60,423 @@ async fn write_dns_entry_conf(
   Ok(())
 }
 
-/// Write default dns config if not exists.
-async fn write_dns_default_conf(path: &PathBuf) -> std::io::Result<()> {
-  if path.exists() {
-    return Ok(());
+/// Generate the content of the dnsmasq config from the dns settings
+///
+/// ## Arguments
+/// - [settings](DnsSettings) The dns settings
+///
+/// ## Return
+/// - [String](String) The dnsmasq config
+fn gen_dns_conf(settings: &DnsSettings) -> String {
+  let mut content = String::from("bind-interfaces\n");
+  for interface in &settings.interfaces {
+    content += &format!("interface={}\n", interface);
   }
-  let content = "bind-interfaces\n \
-interface=nanoclinternal0\n \
-server=8.8.8.8\n \
-server=8.8.4.4\n \
-conf-dir=/etc/dnsmasq.d/,*.conf\n"
-    .to_owned();
-  let mut f = fs::File::create(path).await?;
-  f.write_all(content.as_bytes()).await?;
-  f.sync_data().await?;
-  Ok(())
+  for server in &settings.servers {
+    content += &format!("server={}\n", server);
+  }
+  content += &format!("cache-size={}\n", settings.cache_size);
+  content += "conf-dir=/etc/dnsmasq.d/,*.conf\n";
+  content
+}
+
+/// Path of the last dnsmasq config generated from the dns settings
+/// Used to know if the dnsmasq config has been edited manually
+fn gen_generated_conf_path(path: &Path) -> PathBuf {
+  let mut generated_path = path.as_os_str().to_owned();
+  generated_path.push(".generated");
+  PathBuf::from(generated_path)
+}
+
+/// Write the dnsmasq config when it changed
+/// The file is truncated in place to keep it mounted in the dns controller.
+/// A config edited manually is kept unless `is_forced` is true,
+/// in this case it's saved with a `.bak` extension before being replaced
+///
+/// ## Arguments
+/// - [path](PathBuf) The path of the dnsmasq config
+/// - [settings](DnsSettings) The dns settings
+/// - [is_forced](bool) Replace a config edited manually
+///
+/// ## Return
+/// - [Result](bool) True when the config changed
+async fn write_dns_conf(
+  path: &PathBuf,
+  settings: &DnsSettings,
+  is_forced: bool,
+) -> std::io::Result<bool> {
+  let content = gen_dns_conf(settings);
+  let generated_path = gen_generated_conf_path(path);
+  let generated = fs::read_to_string(&generated_path).await.ok();
+  let current = fs::read_to_string(path).await.ok();
+  if current.as_deref() == Some(content.as_str()) {
+    if generated.as_deref() != Some(content.as_str()) {
+      write_dns_entry_conf(&generated_path, &content).await?;
+    }
+    return Ok(false);
+  }
+  let edited =
+    current.filter(|current| generated.as_deref() != Some(current.as_str()));
+  if let Some(current) = edited {
+    if !is_forced {
+      log::warn!(
+        "{} has been edited manually, remove it to use the dns settings",
+        path.display()
+      );
+      return Ok(false);
+    }
+    let backup_path = path.with_extension("conf.bak");
+    write_dns_entry_conf(&backup_path, &current).await?;
+    log::warn!(
+      "{} has been edited manually, it's saved as {}",
+      path.display(),
+      backup_path.display()
+    );
+  }
+  write_dns_entry_conf(path, &content).await?;
+  write_dns_entry_conf(&generated_path, &content).await?;
+  Ok(true)
+}
+
+/// Override the dns settings with the given ones
+fn merge_dns_settings(
+  settings: DnsSettings,
+  partial: &DnsSettingsPartial,
+) -> DnsSettings {
+  DnsSettings {
+    servers: partial.servers.to_owned().unwrap_or(settings.servers),
+    interfaces: partial.interfaces.to_owned().unwrap_or(settings.interfaces),
+    cache_size: partial.cache_size.unwrap_or(settings.cache_size),
+    search_domains: partial
+      .search_domains
+      .to_owned()
+      .unwrap_or(settings.search_domains),
+  }
+}
+
+fn gen_settings_path(state_dir: &str) -> PathBuf {
+  Path::new(state_dir).join("dnsmasq/settings.json")
+}
+
+/// Read the dns settings updated with the api
+async fn read_settings_partial(
+  state_dir: &str,
+) -> Result<DnsSettingsPartial, DnsError> {
+  let path = gen_settings_path(state_dir);
+  if !path.exists() {
+    return Ok(DnsSettingsPartial::default());
+  }
+  let content = fs::read_to_string(&path).await?;
+  Ok(serde_json::from_str(&content)?)
+}
+
+/// Get the dns settings of the dns controller
+/// Settings updated with the api override the ones of the config file
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+///
+/// ## Return
+/// - [Result](DnsSettings) The dns settings
+/// - [Result](HttpResponseError) An http response error if the settings can't be read
+pub async fn get_settings(
+  config: &DaemonConfig,
+) -> Result<DnsSettings, HttpResponseError> {
+  let partial = read_settings_partial(&config.state_dir)
+    .await
+    .map_err(|err| err.to_http_error())?;
+  let settings = merge_dns_settings(DnsSettings::default(), &config.dns);
+  Ok(merge_dns_settings(settings, &partial))
+}
+
+/// Update the dns settings and regenerate the dnsmasq config
+/// The dns controller must be restarted to use it
+///
+/// ## Arguments
+/// - [payload](DnsSettingsPartial) The settings to change
+/// - [config](DaemonConfig) Daemon config
+///
+/// ## Return
+/// - [Result](DnsSettings) The updated dns settings
+/// - [Result](HttpResponseError) An http response error if the settings are not valid
+pub async fn update_settings(
+  payload: &DnsSettingsPartial,
+  config: &DaemonConfig,
+) -> Result<DnsSettings, HttpResponseError> {
+  let prev = read_settings_partial(&config.state_dir)
+    .await
+    .map_err(|err| err.to_http_error())?;
+  let partial = DnsSettingsPartial {
+    servers: payload.servers.to_owned().or(prev.servers),
+    interfaces: payload.interfaces.to_owned().or(prev.interfaces),
+    cache_size: payload.cache_size.or(prev.cache_size),
+    search_domains: payload.search_domains.to_owned().or(prev.search_domains),
+  };
+  let settings = merge_dns_settings(DnsSettings::default(), &config.dns);
+  let settings = merge_dns_settings(settings, &partial);
+  utils::dns_setting::validate(&settings)?;
+  let res = async {
+    let dir_path = Path::new(&config.state_dir).join("dnsmasq");
+    fs::create_dir_all(&dir_path).await?;
+    let content = serde_json::to_string_pretty(&partial)?;
+    write_dns_entry_conf(&gen_settings_path(&config.state_dir), &content)
+      .await?;
+    write_dns_conf(&dir_path.join("dnsmasq.conf"), &settings, true).await?;
+    Ok::<_, DnsError>(())
+  }
+  .await;
+  res.map_err(|err| err.to_http_error())?;
+  Ok(settings)
+}
+
+/// Generate the content of the dns entry config
+/// A domain resolve every of his subdomains too
+///
+/// ## Arguments
+/// - [entries](DnsEntryItem) The dns entries to write
+///
+/// ## Return
+/// - [String](String) The dnsmasq config
+fn gen_dns_entry_conf(entries: &[DnsEntryItem]) -> String {
+  entries
+    .iter()
+    .map(|entry| format!("address=/.{}/{}\n", entry.domain, entry.ip_address))
+    .collect()
 }
 
-/// Add or Update a dns entry
+/// Write the dns entry config
 ///
 /// ## Arguments
-/// - [domain_name](str) The domain name to add
-/// - [ip_address](str) The ip address the domain target
+/// - [entries](DnsEntryItem) The dns entries to write
 /// - [state_dir](str) Daemon state dir to know where to store the information
-pub async fn add_dns_entry(
-  domain_name: &str,
-  ip_address: &str,
+async fn write_dns_entries(
+  entries: &[DnsEntryItem],
   state_dir: &str,
 ) -> Result<(), DnsError> {
   let file_path = Path::new(state_dir).join("dnsmasq/dnsmasq.d/dns_entry.conf");
-  if !file_path.exists() {
-    fs::create_dir_all(file_path.parent().ok_or_else(|| {
-      std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "Parent directory not found".to_string(),
-      )
-    })?)
-    .await?;
-    fs::File::create(&file_path).await?;
+  fs::create_dir_all(file_path.parent().ok_or_else(|| {
+    std::io::Error::new(
+      std::io::ErrorKind::NotFound,
+      "Parent directory not found".to_string(),
+    )
+  })?)
+  .await?;
+  write_dns_entry_conf(&file_path, &gen_dns_entry_conf(entries)).await?;
+  Ok(())
+}
+
+/// Regenerate the dns entry config from the dns entries in store
+/// The dns controller must be restarted to use it
+///
+/// ## Arguments
+/// - [state_dir](str) Daemon state dir to know where to store the information
+/// - [pool](Pool) Database pool
+pub async fn sync_dns_entries(
+  state_dir: &str,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let entries = repositories::dns_entry::list(pool).await?;
+  write_dns_entries(&entries, state_dir)
+    .await
+    .map_err(|err| err.to_http_error())?;
+  Ok(())
+}
+
+/// Generate the dnsmasq option of a dns record
+///
+/// ## Arguments
+/// - [record](DnsRecordItem) The dns record
+///
+/// ## Return
+/// - [String](String) The dnsmasq option
+fn gen_dns_record_line(record: &DnsRecordItem) -> String {
+  match record.kind {
+    DnsRecordKinds::A | DnsRecordKinds::Aaaa => {
+      format!("host-record={},{}\n", record.domain, record.value)
+    }
+    DnsRecordKinds::Cname => {
+      format!("cname={},{}\n", record.domain, record.value)
+    }
+    DnsRecordKinds::Srv => format!(
+      "srv-host={},{},{},{},{}\n",
+      record.domain,
+      record.value,
+      record.port.unwrap_or_default(),
+      record.priority.unwrap_or_default(),
+      record.weight.unwrap_or_default()
+    ),
+    DnsRecordKinds::Txt => {
+      format!("txt-record={},\"{}\"\n", record.domain, record.value)
+    }
   }
-  let content = fs::read_to_string(&file_path).await?;
-  let reg_expr = r"address=/.".to_owned() + domain_name + "/.*\\n";
-  let reg = Regex::new(&reg_expr)?;
-  let new_dns_entry =
-    "address=/.".to_owned() + domain_name + "/" + ip_address + "\n";
-  if reg.is_match(&content) {
-    // If entry exist we just update it by replacing the ip address
-    let res = reg.replace_all(&content, &new_dns_entry);
-    let new_content = res.to_string();
-    write_dns_entry_conf(&file_path, &new_content).await?;
-  } else {
-    // else we just add it at end of file.
-    let mut file = fs::OpenOptions::new()
-      .write(true)
-      .append(true)
-      .open(file_path)
-      .await?;
-    file.write_all(new_dns_entry.as_bytes()).await?;
+}
+
+/// Regenerate the dns record config from the dns records in store
+/// Records of every namespaces are served by the dns controller,
+/// the dns controller must be restarted to use it
+///
+/// ## Arguments
+/// - [state_dir](str) Daemon state dir to know where to store the information
+/// - [pool](Pool) Database pool
+pub async fn sync_dns_records(
+  state_dir: &str,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let records = repositories::dns_record::list(pool).await?;
+  let content = records.iter().map(gen_dns_record_line).collect::<String>();
+  let dir_path = Path::new(state_dir).join("dnsmasq/dnsmasq.d");
+  let res = async {
+    fs::create_dir_all(&dir_path).await?;
+    write_dns_entry_conf(&dir_path.join("dns_records.conf"), &content).await
   }
+  .await;
+  res.map_err(|err| DnsError::Io(err).to_http_error())?;
+  Ok(())
+}
+
+/// Domain of the service discovery records of the cargo instances
+const SERVICE_DOMAIN: &str = "nanocl.internal";
+
+/// A running cargo instance resolved by the service discovery records
+#[derive(Debug, Clone)]
+struct ServiceInstance {
+  namespace: String,
+  cluster: String,
+  cargo: String,
+  /// Name of the container of the instance
+  name: String,
+  ip_address: String,
+  /// Exposed ports of the container with their protocol
+  ports: Vec<(u16, String)>,
+}
+
+/// Generate the service discovery records of the running cargo instances
+/// - `{cargo}.{cluster}.{namespace}.nanocl.internal` resolve every replicas
+/// - `{container}.nanocl.internal` resolve a replica
+/// - `_{cargo}._{protocol}.{cluster}.{namespace}.nanocl.internal` give the replicas and their exposed ports
+///
+/// ## Arguments
+/// - [instances](ServiceInstance) The running cargo instances
+///
+/// ## Return
+/// - [String](String) The dnsmasq config
+fn gen_service_records(instances: &[ServiceInstance]) -> String {
+  let mut lines = Vec::new();
+  for instance in instances {
+    let cluster = format!(
+      "{}.{}.{}",
+      instance.cluster, instance.namespace, SERVICE_DOMAIN
+    );
+    let host = format!("{}.{}", instance.name, SERVICE_DOMAIN);
+    lines.push(format!(
+      "host-record={}.{},{}\n",
+      instance.cargo, cluster, instance.ip_address
+    ));
+    lines.push(format!("host-record={},{}\n", host, instance.ip_address));
+    for (port, protocol) in &instance.ports {
+      lines.push(format!(
+        "srv-host=_{}._{}.{},{},{},0,0\n",
+        instance.cargo, protocol, cluster, host, port
+      ));
+    }
+  }
+  lines.sort();
+  lines.dedup();
+  lines.concat()
+}
+
+/// List the running cargo instances of every clusters
+/// Replicas leaving their cluster are left out
+async fn list_service_instances(
+  docker_api: &Docker,
+) -> Result<Vec<ServiceInstance>, HttpResponseError> {
+  let mut filters = HashMap::new();
+  filters.insert("label", vec!["cargo", "cluster"]);
+  filters.insert("status", vec!["running"]);
+  let options = Some(bollard::container::ListContainersOptions {
+    filters,
+    ..Default::default()
+  });
+  let containers = docker_api.list_containers(options).await.record_error()?;
+  let instances = containers
+    .into_iter()
+    .filter(|container| !utils::cluster::is_draining(container))
+    .filter_map(|container| {
+      let labels = container.labels.as_ref()?;
+      let namespace = labels.get("namespace")?;
+      let prefix = format!("{}-", namespace);
+      // Cargoes of a cluster are in the namespace of the cluster
+      let cargo = labels.get("cargo")?.strip_prefix(&prefix)?;
+      let cluster = labels.get("cluster")?.strip_prefix(&prefix)?;
+      let ip_address = container
+        .network_settings
+        .as_ref()?
+        .networks
+        .as_ref()?
+        .iter()
+        .filter_map(|(name, network)| {
+          let ip_address = network.ip_address.as_ref()?;
+          (!ip_address.is_empty()).then_some((name, ip_address))
+        })
+        .min()?
+        .1
+        .to_owned();
+      let mut ports = container
+        .ports
+        .to_owned()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|port| {
+          let protocol = port
+            .typ
+            .map(|protocol| protocol.to_string())
+            .unwrap_or_else(|| String::from("tcp"));
+          (port.private_port, protocol)
+        })
+        .collect::<Vec<_>>();
+      ports.sort();
+      ports.dedup();
+      Some(ServiceInstance {
+        namespace: namespace.to_owned(),
+        cluster: cluster.to_owned(),
+        cargo: cargo.to_owned(),
+        name: utils::cargo_instance::get_container_name(&container),
+        ip_address,
+        ports,
+      })
+    })
+    .collect();
+  Ok(instances)
+}
 
+/// Regenerate the service discovery records from the running cargo instances
+/// The dns controller is restarted when they changed
+/// Called once the containers of a cargo are created, scaled or removed
+///
+/// ## Arguments
+/// - [state_dir](str) Daemon state dir to know where to store the information
+/// - [docker_api](Docker) Docker api reference
+pub async fn sync_service_records(
+  state_dir: &str,
+  docker_api: &Docker,
+) -> Result<(), HttpResponseError> {
+  let instances = list_service_instances(docker_api).await?;
+  let content = gen_service_records(&instances);
+  let dir_path = Path::new(state_dir).join("dnsmasq/dnsmasq.d");
+  let file_path = dir_path.join("service_records.conf");
+  if fs::read_to_string(&file_path).await.ok().as_deref()
+    == Some(content.as_str())
+  {
+    return Ok(());
+  }
+  let res = async {
+    fs::create_dir_all(&dir_path).await?;
+    write_dns_entry_conf(&file_path, &content).await
+  }
+  .await;
+  res.map_err(|err| DnsError::Io(err).to_http_error())?;
+  // Ignore error if we can't restart the dns server
+  let _ = restart(docker_api).await;
   Ok(())
 }
 
@@ -126,7 +487,8 @@ pub async fn add_dns_entry(
 pub async fn restart(docker_api: &Docker) -> Result<(), DnsError> {
   docker_api
     .restart_container("system-nano-dns", None)
-    .await?;
+    .await
+    .record_error()?;
   Ok(())
 }
 
@@ -138,21 +500,30 @@ pub async fn restart(docker_api: &Docker) -> Result<(), DnsError> {
 pub async fn register(arg: &ArgState) -> Result<(), DaemonError> {
   let key = utils::key::gen_key(&arg.sys_namespace, "dns");
 
-  if repositories::cargo::find_by_key(key, &arg.pool)
-    .await
-    .is_ok()
-  {
-    return Ok(());
-  }
-
   let dir_path = Path::new(&arg.config.state_dir).join("dnsmasq");
 
   if !dir_path.exists() {
     fs::create_dir_all(&dir_path).await?;
   }
 
+  // The settings of the config file may have changed since the last start
+  let settings = get_settings(&arg.config).await?;
+  utils::dns_setting::validate(&settings)?;
   let config_file_path = Path::new(&dir_path).join("dnsmasq.conf");
-  write_dns_default_conf(&config_file_path).await?;
+  // A config edited manually is kept, the daemon only warn about it
+  let is_changed = write_dns_conf(&config_file_path, &settings, false).await?;
+
+  if repositories::cargo::find_by_key(key, &arg.pool)
+    .await
+    .is_ok()
+  {
+    if is_changed {
+      // Ignore error if we can't restart the dns server
+      let _ = restart(&arg.docker_api).await;
+    }
+    return Ok(());
+  }
+
   let dir_path = Path::new(&dir_path).join("dnsmasq.d/");
   let binds = Some(vec![
     format!("{}:/etc/dnsmasq.conf", config_file_path.display()),
@@ -181,6 +552,7 @@ pub async fn register(arg: &ArgState) -> Result<(), DaemonError> {
     environnements: None,
     replicas: Some(1),
     dns_entry: None,
+    healthcheck: None,
     config: serde_json::to_value(config).map_err(|e| HttpResponseError {
       msg: format!("Unable to serialize container config {}", e),
       status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -206,30 +578,137 @@ mod tests {
 
   use crate::utils::tests::*;
 
-  struct TestDomain {
-    name: String,
-    ip_address: String,
-  }
-
-  /// Test write default dns config file
+  /// Test write dns config file from the settings
   #[ntex::test]
-  async fn test_write_dns_default_conf() {
+  async fn test_write_dns_conf() {
     let config_file_path = Path::new("/tmp").join("dnsmasq.conf");
-    if config_file_path.exists() {
-      fs::remove_file(&config_file_path).await.unwrap();
+    let generated_path = gen_generated_conf_path(&config_file_path);
+    let backup_path = Path::new("/tmp").join("dnsmasq.conf.bak");
+    for path in [&config_file_path, &generated_path, &backup_path] {
+      if path.exists() {
+        fs::remove_file(path).await.unwrap();
+      }
     }
-    write_dns_default_conf(&config_file_path).await.unwrap();
-    assert!(config_file_path.exists());
+    let settings = DnsSettings::default();
+    assert!(write_dns_conf(&config_file_path, &settings, false)
+      .await
+      .unwrap());
     let content = fs::read_to_string(&config_file_path).await.unwrap();
     assert_eq!(
       content,
-      "bind-interfaces\n \
-      interface=nanoclinternal0\n \
-      server=8.8.8.8\n \
-      server=8.8.4.4\n \
+      "bind-interfaces\n\
+      interface=nanoclinternal0\n\
+      server=8.8.8.8\n\
+      server=8.8.4.4\n\
+      cache-size=150\n\
       conf-dir=/etc/dnsmasq.d/,*.conf\n"
     );
-    fs::remove_file(config_file_path).await.unwrap();
+    assert!(
+      !write_dns_conf(&config_file_path, &settings, false)
+        .await
+        .unwrap(),
+      "Expect unchanged config to not be written"
+    );
+    let partial = DnsSettingsPartial {
+      servers: Some(vec![String::from("10.0.0.53")]),
+      search_domains: Some(vec![String::from("corp.internal")]),
+      ..Default::default()
+    };
+    let settings = merge_dns_settings(settings, &partial);
+    assert!(write_dns_conf(&config_file_path, &settings, false)
+      .await
+      .unwrap());
+    let content = fs::read_to_string(&config_file_path).await.unwrap();
+    assert!(content.contains("server=10.0.0.53\n"));
+    assert!(!content.contains("server=8.8.8.8"));
+    assert!(
+      !content.contains("corp.internal"),
+      "Expect search domains to be given to the containers only"
+    );
+
+    // A config edited manually is only replaced when forced
+    let edited = format!("{}log-queries\n", content);
+    fs::write(&config_file_path, &edited).await.unwrap();
+    let settings = DnsSettings::default();
+    assert!(
+      !write_dns_conf(&config_file_path, &settings, false)
+        .await
+        .unwrap(),
+      "Expect a config edited manually to be kept"
+    );
+    let content = fs::read_to_string(&config_file_path).await.unwrap();
+    assert_eq!(content, edited);
+    assert!(write_dns_conf(&config_file_path, &settings, true)
+      .await
+      .unwrap());
+    let content = fs::read_to_string(&backup_path).await.unwrap();
+    assert_eq!(content, edited, "Expect the edited config to be saved");
+    for path in [&config_file_path, &generated_path, &backup_path] {
+      fs::remove_file(path).await.unwrap();
+    }
+  }
+
+  #[test]
+  fn gen_dns_record_line_test() {
+    let mut record = DnsRecordItem {
+      key: String::from("global-test"),
+      name: String::from("test"),
+      namespace_name: String::from("global"),
+      kind: DnsRecordKinds::A,
+      domain: String::from("api.nanocl.internal"),
+      value: String::from("10.0.0.1"),
+      port: None,
+      priority: None,
+      weight: None,
+    };
+    assert_eq!(
+      gen_dns_record_line(&record),
+      "host-record=api.nanocl.internal,10.0.0.1\n"
+    );
+    record.kind = DnsRecordKinds::Srv;
+    record.domain = String::from("_http._tcp.nanocl.internal");
+    record.value = String::from("api.nanocl.internal");
+    record.port = Some(9000);
+    record.priority = Some(0);
+    record.weight = Some(10);
+    assert_eq!(
+      gen_dns_record_line(&record),
+      "srv-host=_http._tcp.nanocl.internal,api.nanocl.internal,9000,0,10\n"
+    );
+    record.kind = DnsRecordKinds::Txt;
+    record.domain = String::from("nanocl.internal");
+    record.value = String::from("v=spf1 -all");
+    assert_eq!(
+      gen_dns_record_line(&record),
+      "txt-record=nanocl.internal,\"v=spf1 -all\"\n"
+    );
+  }
+
+  #[test]
+  fn gen_service_records_test() {
+    let instance = ServiceInstance {
+      namespace: String::from("global"),
+      cluster: String::from("dev"),
+      cargo: String::from("api"),
+      name: String::from("global-dev-api"),
+      ip_address: String::from("10.1.0.2"),
+      ports: vec![(9000, String::from("tcp"))],
+    };
+    let replica = ServiceInstance {
+      name: String::from("global-dev-api-1"),
+      ip_address: String::from("10.1.0.3"),
+      ..instance.to_owned()
+    };
+    assert_eq!(
+      gen_service_records(&[replica, instance]),
+      "host-record=api.dev.global.nanocl.internal,10.1.0.2\n\
+      host-record=api.dev.global.nanocl.internal,10.1.0.3\n\
+      host-record=global-dev-api-1.nanocl.internal,10.1.0.3\n\
+      host-record=global-dev-api.nanocl.internal,10.1.0.2\n\
+      srv-host=_api._tcp.dev.global.nanocl.internal,global-dev-api-1.nanocl.internal,9000,0,0\n\
+      srv-host=_api._tcp.dev.global.nanocl.internal,global-dev-api.nanocl.internal,9000,0,0\n"
+    );
+    assert_eq!(gen_service_records(&[]), "");
   }
 
   #[ntex::test]
@@ -237,49 +716,34 @@ mod tests {
     // Create temporary directory for the tests
     let tmp_state_dir =
       env::temp_dir().join("nanocld-unit").display().to_string();
-    let dnsmasq_conf_dir = Path::new(&tmp_state_dir).join("dnsmasq/dnsmasq.d");
-    fs::create_dir_all(&dnsmasq_conf_dir).await?;
-
-    // Create a dummy dns entry file
-    let dns_entry_path = Path::new(&dnsmasq_conf_dir).join("dns_entry.conf");
-    write_dns_entry_conf(&dns_entry_path, "").await?;
-
-    // Test to add domain test.com pointing to 141.0.0.1
-    let test_1 = TestDomain {
-      name: String::from("test.com"),
-      ip_address: String::from("141.0.0.1"),
-    };
-    add_dns_entry(&test_1.name, &test_1.ip_address, &tmp_state_dir).await?;
-    let content = fs::read_to_string(&dns_entry_path).await?;
-    let expected_content =
-      format!("address=/.{}/{}\n", &test_1.name, &test_1.ip_address);
-    assert_eq!(content, expected_content);
-
-    // Test to add another domain test2.com pointing to 122.0.0.1
-    let test_2 = TestDomain {
-      name: String::from("test2.com"),
-      ip_address: String::from("122.0.0.1"),
-    };
-    add_dns_entry(&test_2.name, &test_2.ip_address, &tmp_state_dir).await?;
+    let dns_entry_path =
+      Path::new(&tmp_state_dir).join("dnsmasq/dnsmasq.d/dns_entry.conf");
+
+    // Write domains test.com pointing to 141.0.0.1 and test2.com to 122.0.0.1
+    let mut entries = vec![
+      DnsEntryItem {
+        cargo_instance_key: String::from("global-test-global-test"),
+        domain: String::from("test.com"),
+        ip_address: String::from("141.0.0.1"),
+      },
+      DnsEntryItem {
+        cargo_instance_key: String::from("global-test-global-test2"),
+        domain: String::from("test2.com"),
+        ip_address: String::from("122.0.0.1"),
+      },
+    ];
+    write_dns_entries(&entries, &tmp_state_dir).await?;
     let content = fs::read_to_string(&dns_entry_path).await?;
-    let expected_content = format!(
-      "address=/.{}/{}\naddress=/.{}/{}\n",
-      &test_1.name, &test_1.ip_address, &test_2.name, &test_2.ip_address
+    assert_eq!(
+      content,
+      "address=/.test.com/141.0.0.1\naddress=/.test2.com/122.0.0.1\n"
     );
-    assert_eq!(content, expected_content);
 
-    // Test to update domain 2 with a new ip address 121.0.0.1
-    let test_3 = TestDomain {
-      ip_address: String::from("121.0.0.1"),
-      ..test_2
-    };
-    add_dns_entry(&test_3.name, &test_3.ip_address, &tmp_state_dir).await?;
+    // Removed entries are not kept in the config
+    entries.remove(0);
+    write_dns_entries(&entries, &tmp_state_dir).await?;
     let content = fs::read_to_string(&dns_entry_path).await?;
-    let expected_content = format!(
-      "address=/.{}/{}\naddress=/.{}/{}\n",
-      &test_1.name, &test_1.ip_address, &test_3.name, &test_3.ip_address
-    );
-    assert_eq!(content, expected_content);
+    assert_eq!(content, "address=/.test2.com/122.0.0.1\n");
 
     // Remove the dummy state directory
     fs::remove_dir_all(&tmp_state_dir).await?;
@@ -1,13 +1,16 @@
 use std::path::Path;
+use std::future::Future;
 
 use bollard::{
   Docker,
   errors::Error as DockerError,
-  exec::{CreateExecOptions, StartExecOptions},
+  exec::{CreateExecOptions, StartExecOptions, StartExecResults},
 };
+use futures::StreamExt;
 use ntex::http::StatusCode;
 
 use crate::{utils, repositories, errors::HttpResponseError};
+use crate::utils::metrics::DockerErrorMetric;
 use crate::models::{ArgState, CargoPartial};
 use crate::errors::DaemonError;
 
@@ -24,16 +27,149 @@ pub async fn reload_config(docker_api: &Docker) -> Result<(), DockerError> {
     attach_stderr: Some(true),
     ..Default::default()
   };
-  let res = docker_api.create_exec(container_name, config).await?;
+  let res = docker_api
+    .create_exec(container_name, config)
+    .await
+    .record_error()?;
   let config = StartExecOptions {
     detach: false,
     ..Default::default()
   };
-  docker_api.start_exec(&res.id, Some(config)).await?;
+  docker_api
+    .start_exec(&res.id, Some(config))
+    .await
+    .record_error()?;
+
+  Ok(())
+}
 
+/// Run a command inside the proxy container
+///
+/// ## Arguments
+/// [cmd](Vec<String>) The command and its arguments
+/// [docker_api](Docker) Docker api reference
+///
+/// ## Return
+/// - [Result]((Option<i64>, String)) The exit code and the output of the command
+/// - [Result](HttpResponseError) An http response error if the command can't be run
+pub async fn exec_command(
+  cmd: Vec<String>,
+  docker_api: &Docker,
+) -> Result<(Option<i64>, String), HttpResponseError> {
+  let container_name = "system-nano-proxy";
+  let config = CreateExecOptions {
+    cmd: Some(cmd),
+    attach_stdout: Some(true),
+    attach_stderr: Some(true),
+    ..Default::default()
+  };
+  let res = docker_api
+    .create_exec(container_name, config)
+    .await
+    .record_error()?;
+  let mut logs = String::new();
+  if let StartExecResults::Attached { mut output, .. } = docker_api
+    .start_exec(&res.id, None::<StartExecOptions>)
+    .await
+    .record_error()?
+  {
+    while let Some(output) = output.next().await {
+      logs.push_str(&output?.to_string());
+    }
+  }
+  let exec = docker_api.inspect_exec(&res.id).await.record_error()?;
+  Ok((exec.exit_code, logs))
+}
+
+/// Test proxy config
+/// Run `nginx -t` inside the proxy container
+///
+/// ## Arguments
+/// [docker_api](Docker) Docker api reference
+///
+/// ## Return
+/// - [Result](()) The config is valid
+/// - [Result](HttpResponseError) The nginx error if the config is not valid
+pub async fn test_config(docker_api: &Docker) -> Result<(), HttpResponseError> {
+  let cmd = vec![String::from("nginx"), String::from("-t")];
+  let (exit_code, logs) = exec_command(cmd, docker_api).await?;
+  if exit_code != Some(0) {
+    return Err(HttpResponseError {
+      msg: format!("Invalid proxy config: {}", logs.trim()),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
   Ok(())
 }
 
+/// Apply a proxy config file
+/// The file is written then tested with `nginx -t`, the previous file is restored if the test fail.
+/// When the proxy container can't be reached the file is kept and will be checked at the proxy start.
+///
+/// ## Arguments
+/// [file_path](Path) Path of the config file inside the state directory
+/// [content](Option<String>) The new content of the file, none to remove it
+/// [docker_api](Docker) Docker api reference
+///
+/// ## Return
+/// - [Result](()) The config is applied
+/// - [Result](HttpResponseError) The nginx error if the config is not valid
+pub async fn apply_config(
+  file_path: &Path,
+  content: Option<String>,
+  docker_api: &Docker,
+) -> Result<(), HttpResponseError> {
+  write_checked_config(file_path, content, || test_config(docker_api)).await
+}
+
+/// Write a proxy config file then check it
+/// The previous file is restored if the check refuses the config
+async fn write_checked_config<F, Fut>(
+  file_path: &Path,
+  content: Option<String>,
+  check: F,
+) -> Result<(), HttpResponseError>
+where
+  F: FnOnce() -> Fut,
+  Fut: Future<Output = Result<(), HttpResponseError>>,
+{
+  let prev = tokio::fs::read(file_path).await.ok();
+  write_config_file(file_path, content.map(String::into_bytes)).await?;
+  match check().await {
+    Ok(_) => Ok(()),
+    Err(err) if err.status == StatusCode::UNPROCESSABLE_ENTITY => {
+      write_config_file(file_path, prev).await?;
+      Err(err)
+    }
+    Err(err) => {
+      log::warn!("Unable to test proxy config {}", err.msg);
+      Ok(())
+    }
+  }
+}
+
+/// Write or remove a proxy config file
+async fn write_config_file(
+  file_path: &Path,
+  content: Option<Vec<u8>>,
+) -> Result<(), HttpResponseError> {
+  let res = match content {
+    Some(content) => tokio::fs::write(file_path, content).await,
+    None => match tokio::fs::remove_file(file_path).await {
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+      res => res,
+    },
+  };
+  res.map_err(|err| HttpResponseError {
+    msg: format!(
+      "Unable to write config file {} {}",
+      file_path.display(),
+      err
+    ),
+    status: StatusCode::INTERNAL_SERVER_ERROR,
+  })
+}
+
 /// Register our proxy controller as a cargo
 /// So it will be self managed by the system
 ///
@@ -86,6 +222,7 @@ pub async fn register(arg: &ArgState) -> Result<(), DaemonError> {
     environnements: None,
     replicas: Some(1),
     dns_entry: None,
+    healthcheck: None,
     config: serde_json::to_value(config).map_err(|err| HttpResponseError {
       msg: format!("Unable to serialize container config {} ", err),
       status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -101,3 +238,65 @@ pub async fn register(arg: &ArgState) -> Result<(), DaemonError> {
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::models::DaemonConfig;
+  use crate::utils::tests::*;
+
+  /// Test an invalid config is refused and the previous file restored
+  #[ntex::test]
+  async fn apply_invalid_config() -> TestRet {
+    // The sites of the proxy of the daemon must not be changed
+    let state_dir = std::env::temp_dir().join("nanocl-proxy-apply-test");
+    let daemon_config = DaemonConfig {
+      state_dir: state_dir.display().to_string(),
+      ..Default::default()
+    };
+    let dir_path =
+      Path::new(&daemon_config.state_dir).join("nginx/sites-enabled");
+    tokio::fs::create_dir_all(&dir_path).await?;
+    let file_path = dir_path.join("unit.apply.conf");
+    // The check of nginx is replaced by the status it would return
+    let gen_result = |status: StatusCode| async move {
+      if status.is_success() {
+        return Ok(());
+      }
+      Err(HttpResponseError {
+        msg: String::from("test"),
+        status,
+      })
+    };
+    let valid = String::from("server {\n  listen 80;\n}\n");
+    write_checked_config(&file_path, Some(valid.to_owned()), || {
+      gen_result(StatusCode::OK)
+    })
+    .await
+    .expect("Expect a valid config to be applied");
+    let invalid = String::from("server {\n  unknown_directive;\n}\n");
+    let err =
+      write_checked_config(&file_path, Some(invalid.to_owned()), || {
+        gen_result(StatusCode::UNPROCESSABLE_ENTITY)
+      })
+      .await
+      .expect_err("Expect an invalid config to be refused");
+    assert_eq!(err.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let content = tokio::fs::read_to_string(&file_path).await?;
+    assert_eq!(content, valid, "Expect the previous config to be restored");
+    write_checked_config(&file_path, Some(invalid.to_owned()), || {
+      gen_result(StatusCode::INTERNAL_SERVER_ERROR)
+    })
+    .await
+    .expect("Expect the config to be kept when the proxy is unreachable");
+    let content = tokio::fs::read_to_string(&file_path).await?;
+    assert_eq!(content, invalid);
+    write_checked_config(&file_path, None, || gen_result(StatusCode::OK))
+      .await
+      .expect("Expect the config to be removed");
+    assert!(!file_path.exists());
+    tokio::fs::remove_dir_all(&state_dir).await?;
+    Ok(())
+  }
+}
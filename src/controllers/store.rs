@@ -14,6 +14,7 @@ use bollard::{
 };
 
 use crate::{utils, repositories, models::CargoInstanceState};
+use crate::utils::metrics::DockerErrorMetric;
 use crate::errors::{DaemonError, HttpResponseError};
 use crate::models::{
   Pool, DBConn, ArgState, DaemonConfig, CargoPartial, CargoInstancePartial,
@@ -90,7 +91,8 @@ async fn create_system_store(
   let config = gen_store_config(name, config);
   docker_api
     .create_container(options, config.to_owned())
-    .await?;
+    .await
+    .record_error()?;
   Ok(())
 }
 
@@ -144,7 +146,10 @@ pub fn get_pool_conn(pool: &Pool) -> Result<DBConn, HttpResponseError> {
 pub async fn get_store_ip_addr(
   docker_api: &Docker,
 ) -> Result<String, HttpResponseError> {
-  let container = docker_api.inspect_container("store", None).await?;
+  let container = docker_api
+    .inspect_container("store", None)
+    .await
+    .record_error()?;
   let networks = container
     .network_settings
     .ok_or(HttpResponseError {
@@ -228,6 +233,7 @@ pub async fn register(arg: &ArgState) -> Result<(), DaemonError> {
     environnements: None,
     replicas: Some(1),
     dns_entry: None,
+    healthcheck: None,
     config: serde_json::to_value(config).map_err(|err| HttpResponseError {
       msg: format!("unable to serialize store config: {}", err),
       status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -7,6 +7,8 @@ use bollard::errors::Error as DockerError;
 #[cfg(feature = "dev")]
 use utoipa::ToSchema;
 
+use crate::models::StateResourceResult;
+
 /// Http response error
 #[derive(Debug, Error)]
 pub struct HttpResponseError {
@@ -52,6 +54,37 @@ impl web::WebResponseError for HttpResponseError {
   }
 }
 
+/// Error of a state file apply
+/// The resources applied before the error are returned alongside it
+#[derive(Debug, Error)]
+pub struct StateApplyError {
+  pub(crate) err: HttpResponseError,
+  pub(crate) applied: Vec<StateResourceResult>,
+}
+
+impl From<HttpResponseError> for StateApplyError {
+  fn from(err: HttpResponseError) -> Self {
+    Self {
+      err,
+      applied: Vec::new(),
+    }
+  }
+}
+
+impl std::fmt::Display for StateApplyError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.err)
+  }
+}
+
+impl web::WebResponseError for StateApplyError {
+  fn error_response(&self, _: &web::HttpRequest) -> web::HttpResponse {
+    log::error!("Error response: {}", self);
+    let err_json = json!({ "msg": self.err.msg, "applied": self.applied });
+    web::HttpResponse::build(self.err.status).json(&err_json)
+  }
+}
+
 /// Api Error Structure that server send to client
 /// Used to generate open api specification
 #[cfg(feature = "dev")]
@@ -61,6 +94,17 @@ pub struct ApiError {
   pub(crate) msg: String,
 }
 
+/// Api Error Structure that server send to client when a state file apply fail
+/// Used to generate open api specification
+#[cfg(feature = "dev")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+#[allow(dead_code)]
+pub struct ApiStateApplyError {
+  pub(crate) msg: String,
+  /// Resources applied before the error
+  pub(crate) applied: Vec<StateResourceResult>,
+}
+
 /// Generic Daemon error
 #[derive(Debug, Error)]
 pub enum DaemonError {
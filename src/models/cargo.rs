@@ -7,6 +7,7 @@ use utoipa::ToSchema;
 use crate::schema::cargoes;
 
 use super::cargo_env::CargoEnvItem;
+use super::cargo_instance::CargoContainerHealth;
 use super::namespace::NamespaceItem;
 
 /// Cargo partial
@@ -21,6 +22,47 @@ pub struct CargoPartial {
   pub(crate) dns_entry: Option<String>,
   pub(crate) replicas: Option<i64>,
   pub(crate) environnements: Option<Vec<String>>,
+  pub(crate) healthcheck: Option<CargoHealthCheck>,
+}
+
+/// Health check of the replicas of a cargo
+/// It's stored in the config as the docker HEALTHCHECK of the containers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoHealthCheck {
+  /// Command run inside the container, healthy when it exit with 0
+  pub(crate) cmd: Vec<String>,
+  /// Seconds between two checks
+  pub(crate) interval: Option<u64>,
+  /// Seconds before a check is considered failed
+  pub(crate) timeout: Option<u64>,
+  /// Consecutive failures needed to be unhealthy
+  pub(crate) retries: Option<i64>,
+  /// Seconds given to the container to start before failures are counted
+  pub(crate) start_period: Option<u64>,
+}
+
+/// Strategy used to replace the containers of a cargo when it's updated
+/// Replicas are replaced by batch of `surge + max_unavailable` containers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CargoUpdateStrategy {
+  /// Number of new replicas created before the old ones are removed
+  pub(crate) surge: i64,
+  /// Number of old replicas removed before their replacement is ready
+  pub(crate) max_unavailable: i64,
+  /// Seconds to wait for a new replica to be running or healthy
+  pub(crate) ready_timeout: i64,
+}
+
+impl Default for CargoUpdateStrategy {
+  fn default() -> Self {
+    Self {
+      surge: 1,
+      max_unavailable: 0,
+      ready_timeout: 30,
+    }
+  }
 }
 
 #[derive(Default, Serialize, Deserialize)]
@@ -30,6 +72,15 @@ pub struct CargoPatchPartial {
   pub(crate) replicas: Option<i64>,
   pub(crate) dns_entry: Option<String>,
   pub(crate) environnements: Option<Vec<String>>,
+  pub(crate) healthcheck: Option<CargoHealthCheck>,
+  pub(crate) update_strategy: Option<CargoUpdateStrategy>,
+}
+
+/// Payload body to scale a cargo
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoScaleBody {
+  pub(crate) replicas: i64,
 }
 
 #[derive(AsChangeset)]
@@ -46,6 +97,7 @@ pub struct CargoPatchItem {
 /// this structure ensure read and write in database
 #[derive(
   Debug,
+  Clone,
   Serialize,
   Deserialize,
   Queryable,
@@ -78,6 +130,7 @@ pub struct CargoItemWithRelation {
   pub(crate) dns_entry: Option<String>,
   pub(crate) environnements: Option<Vec<CargoEnvItem>>,
   pub(crate) containers: Vec<bollard::models::ContainerSummary>,
+  pub(crate) health: Vec<CargoContainerHealth>,
 }
 
 #[derive(Debug)]
@@ -87,4 +140,7 @@ pub struct CreateCargoInstanceOpts<'a> {
   pub(crate) network_key: &'a str,
   pub(crate) environnements: Vec<String>,
   pub(crate) labels: Option<&'a mut HashMap<String, String>>,
+  pub(crate) indexes: Vec<i64>,
+  /// Search domains of the dns controller used when the cargo doesn't set his own
+  pub(crate) dns_search: Vec<String>,
 }
@@ -0,0 +1,83 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::{cargo_autoscalers, cargo_scale_events};
+
+use super::cargo::CargoItem;
+
+/// Cargo autoscaler partial
+/// This structure is used as payload body to set the autoscaler of a cargo
+/// Targets are percentages of cpu and memory utilisation
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoAutoscalerPartial {
+  pub(crate) min_replicas: i64,
+  pub(crate) max_replicas: i64,
+  pub(crate) target_cpu: Option<i64>,
+  pub(crate) target_memory: Option<i64>,
+}
+
+/// Cargo autoscaler item
+/// The replicas of the cargo are scaled between min and max replicas
+/// to keep the average utilisation of his containers close to the targets
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  AsChangeset,
+  Associations,
+)]
+#[diesel(primary_key(cargo_key))]
+#[diesel(table_name = cargo_autoscalers)]
+#[diesel(belongs_to(CargoItem, foreign_key = cargo_key))]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoAutoscalerItem {
+  pub(crate) cargo_key: String,
+  pub(crate) min_replicas: i64,
+  pub(crate) max_replicas: i64,
+  pub(crate) target_cpu: Option<i64>,
+  pub(crate) target_memory: Option<i64>,
+}
+
+/// Cargo scale event partial
+pub struct CargoScaleEventPartial {
+  pub(crate) cargo_key: String,
+  pub(crate) from_replicas: i64,
+  pub(crate) to_replicas: i64,
+  pub(crate) cpu: Option<f64>,
+  pub(crate) memory: Option<f64>,
+  pub(crate) reason: String,
+}
+
+/// Cargo scale event item is a scaling decision taken by the autoscaler
+/// with the average cpu and memory utilisation that lead to it
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  Associations,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = cargo_scale_events)]
+#[diesel(belongs_to(CargoItem, foreign_key = cargo_key))]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoScaleEventItem {
+  pub(crate) key: uuid::Uuid,
+  pub(crate) cargo_key: String,
+  pub(crate) from_replicas: i64,
+  pub(crate) to_replicas: i64,
+  pub(crate) cpu: Option<f64>,
+  pub(crate) memory: Option<f64>,
+  pub(crate) reason: String,
+  pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+}
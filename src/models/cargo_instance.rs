@@ -18,6 +18,9 @@ pub struct CargoInstanceItem {
   pub(crate) cargo_key: String,
   pub(crate) cluster_key: String,
   pub(crate) network_key: String,
+  /// True once the cluster has been started with this instance
+  /// the reconciler only converge started instances
+  pub(crate) started: bool,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -27,6 +30,26 @@ pub enum CargoInstanceState {
   Stopped,
 }
 
+/// Health of a cargo instance container given by his docker HEALTHCHECK
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub enum CargoInstanceHealth {
+  /// The container has no health check
+  None,
+  Starting,
+  Healthy,
+  Unhealthy,
+}
+
+/// Health of a container of a cargo
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoContainerHealth {
+  pub(crate) name: String,
+  pub(crate) health: CargoInstanceHealth,
+}
+
 /// Structure used as body parameter to create a cluster cargo
 #[cfg_attr(feature = "dev", derive(ToSchema))]
 pub struct CargoInstancePartial {
@@ -43,6 +66,38 @@ pub struct CargoInstanceFilterQuery {
   pub(crate) namespace: Option<String>,
 }
 
+/// Query to filter the logs of cargo instances
+/// `since` and `until` are unix timestamps and `tail` a number of lines or `all`
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct CargoInstanceLogQuery {
+  pub(crate) namespace: Option<String>,
+  pub(crate) follow: Option<bool>,
+  pub(crate) since: Option<i64>,
+  pub(crate) until: Option<i64>,
+  pub(crate) tail: Option<String>,
+  pub(crate) timestamps: Option<bool>,
+}
+
+/// Stream of a cargo instance an output come from
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub enum CargoInstanceOutputKind {
+  StdIn,
+  StdOut,
+  StdErr,
+  Console,
+}
+
+/// Output of a cargo instance prefixed by the name of the instance
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoInstanceOutput {
+  pub(crate) name: String,
+  pub(crate) kind: CargoInstanceOutputKind,
+  pub(crate) data: String,
+}
+
 /// Structure used to create an exec instance inside a container
 #[cfg_attr(feature = "dev", derive(ToSchema))]
 #[derive(Serialize, Deserialize)]
@@ -58,3 +113,61 @@ pub struct CargoInstanceExecBody {
   pub(crate) user: Option<String>,
   pub(crate) working_dir: Option<String>,
 }
+
+/// Structure used to resize the tty of an exec instance
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CargoInstanceExecResizeBody {
+  pub(crate) width: u16,
+  pub(crate) height: u16,
+}
+
+/// State of an exec instance
+/// The exit code is set once the command is finished
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CargoInstanceExecInspect {
+  pub(crate) id: String,
+  pub(crate) container_id: Option<String>,
+  pub(crate) running: bool,
+  pub(crate) exit_code: Option<i64>,
+  pub(crate) pid: Option<i64>,
+}
+
+/// Query to get the stats of cargo instances
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct CargoInstanceStatsQuery {
+  pub(crate) namespace: Option<String>,
+  pub(crate) cluster: Option<String>,
+  pub(crate) stream: Option<bool>,
+}
+
+/// Resource usage of a cargo instance
+/// Memory, network and block io values are in bytes
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CargoInstanceStats {
+  pub(crate) name: String,
+  pub(crate) cpu_percent: f64,
+  pub(crate) memory_usage: u64,
+  pub(crate) memory_limit: u64,
+  pub(crate) network_rx: u64,
+  pub(crate) network_tx: u64,
+  pub(crate) block_read: u64,
+  pub(crate) block_write: u64,
+}
+
+/// Resource usage of a cargo summed across his instances
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CargoStats {
+  pub(crate) key: String,
+  pub(crate) cpu_percent: f64,
+  pub(crate) memory_usage: u64,
+  pub(crate) memory_limit: u64,
+  pub(crate) network_rx: u64,
+  pub(crate) network_tx: u64,
+  pub(crate) block_read: u64,
+  pub(crate) block_write: u64,
+  pub(crate) instances: Vec<CargoInstanceStats>,
+}
@@ -0,0 +1,56 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::cargo_revisions;
+
+use super::cargo::CargoItem;
+
+/// Cargo revision partial
+/// The revision number is given by the repository when it's stored
+pub struct CargoRevisionPartial {
+  pub(crate) cargo_key: String,
+  pub(crate) config: serde_json::Value,
+  pub(crate) replicas: i64,
+  pub(crate) dns_entry: Option<String>,
+  pub(crate) environnements: Vec<String>,
+}
+
+/// Cargo revision item is a snapshot of a cargo taken on every change
+/// Environnements are stored formated as `NAME=VALUE`
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  Associations,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = cargo_revisions)]
+#[diesel(belongs_to(CargoItem, foreign_key = cargo_key))]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CargoRevisionItem {
+  pub(crate) key: String,
+  pub(crate) cargo_key: String,
+  pub(crate) revision: i64,
+  pub(crate) config: serde_json::Value,
+  pub(crate) replicas: i64,
+  pub(crate) dns_entry: Option<String>,
+  pub(crate) environnements: Vec<String>,
+  pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Query to rollback a cargo to one of his revisions
+/// The values of the update strategy missing from the query are the default ones
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CargoRollbackQuery {
+  pub(crate) namespace: Option<String>,
+  pub(crate) revision: i64,
+  pub(crate) surge: Option<i64>,
+  pub(crate) max_unavailable: Option<i64>,
+  pub(crate) ready_timeout: Option<i64>,
+}
@@ -0,0 +1,34 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::certificates;
+
+/// Certificate partial
+/// This structure is used as payload body to upload a certificate.
+/// The certificate chain and its private key are PEM encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CertificatePartial {
+  pub(crate) name: String,
+  pub(crate) certificate: String,
+  pub(crate) certificate_key: String,
+}
+
+/// Certificate item
+/// The PEM files are stored in the ssl directory of the proxy,
+/// only the details of the certificate are stored in database
+#[derive(
+  Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Insertable,
+)]
+#[diesel(primary_key(name))]
+#[diesel(table_name = certificates)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct CertificateItem {
+  pub(crate) name: String,
+  /// Subject alternative names of the certificate
+  pub(crate) sans: Vec<String>,
+  pub(crate) not_after: chrono::DateTime<chrono::Utc>,
+  pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+}
@@ -66,3 +66,10 @@ pub struct DeleteClusterTemplatePath {
   pub(crate) cl_name: String,
   pub(crate) nt_name: String,
 }
+
+/// Struct used to parse path of render cluster template
+#[derive(Serialize, Deserialize)]
+pub struct RenderClusterTemplatePath {
+  pub(crate) cl_name: String,
+  pub(crate) nt_name: String,
+}
@@ -25,7 +25,13 @@ pub struct ClusterNetworkPartial {
 /// Cluster network item
 /// this structure ensure read and write in database
 #[derive(
-  Serialize, Deserialize, Queryable, Identifiable, Insertable, Associations,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  Associations,
 )]
 #[diesel(primary_key(key))]
 #[diesel(table_name = cluster_networks)]
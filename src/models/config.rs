@@ -1,10 +1,18 @@
 use serde::{Serialize, Deserialize};
 
+use super::dns_setting::DnsSettingsPartial;
+
 #[derive(Default, Debug, Clone)]
 pub struct DaemonConfig {
   pub(crate) hosts: Vec<String>,
   pub(crate) state_dir: String,
   pub(crate) docker_host: String,
+  pub(crate) nginx_log_retention: u64,
+  pub(crate) acme_email: Option<String>,
+  pub(crate) acme_directory: String,
+  pub(crate) acme_insecure: bool,
+  /// Dns settings of the config file, the defaults are used for the missing ones
+  pub(crate) dns: DnsSettingsPartial,
 }
 
 #[derive(Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -12,4 +20,9 @@ pub struct DaemonConfigFile {
   pub(crate) hosts: Option<Vec<String>>,
   pub(crate) docker_host: Option<String>,
   pub(crate) state_dir: Option<String>,
+  pub(crate) nginx_log_retention: Option<u64>,
+  pub(crate) acme_email: Option<String>,
+  pub(crate) acme_directory: Option<String>,
+  pub(crate) acme_insecure: Option<bool>,
+  pub(crate) dns: Option<DnsSettingsPartial>,
 }
@@ -0,0 +1,28 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::dns_entries;
+
+/// Dns entry item
+/// The domain of the dns_entry of a cargo pointing to one of his instances,
+/// it's removed with the instance that own it
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  AsChangeset,
+)]
+#[diesel(primary_key(cargo_instance_key))]
+#[diesel(table_name = dns_entries)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct DnsEntryItem {
+  pub(crate) cargo_instance_key: String,
+  pub(crate) domain: String,
+  pub(crate) ip_address: String,
+}
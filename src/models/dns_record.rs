@@ -0,0 +1,70 @@
+use diesel_derive_enum::DbEnum;
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::dns_records;
+
+/// Dns record kind
+/// # Examples
+/// ```
+/// DnsRecordKinds::A; // Ipv4 address of a domain
+/// DnsRecordKinds::Srv; // Target and port of a service
+/// ```
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, DbEnum, Clone)]
+#[DieselTypePath = "crate::schema::sql_types::DnsRecordKinds"]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub enum DnsRecordKinds {
+  A,
+  Aaaa,
+  Cname,
+  Srv,
+  Txt,
+}
+
+/// Dns record partial
+/// This structure is used as payload body to create or update a record.
+/// The value is the address of A and AAAA records,
+/// the target domain of CNAME and SRV records and the text of TXT records.
+/// Port, priority and weight are only used by SRV records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct DnsRecordPartial {
+  pub(crate) name: String,
+  pub(crate) kind: DnsRecordKinds,
+  pub(crate) domain: String,
+  pub(crate) value: String,
+  pub(crate) port: Option<i64>,
+  pub(crate) priority: Option<i64>,
+  pub(crate) weight: Option<i64>,
+}
+
+/// Dns record item
+/// The dnsmasq configuration is generated from the records by the daemon
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  AsChangeset,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = dns_records)]
+#[diesel(treat_none_as_null = true)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct DnsRecordItem {
+  pub(crate) key: String,
+  pub(crate) name: String,
+  pub(crate) namespace_name: String,
+  pub(crate) kind: DnsRecordKinds,
+  pub(crate) domain: String,
+  pub(crate) value: String,
+  pub(crate) port: Option<i64>,
+  pub(crate) priority: Option<i64>,
+  pub(crate) weight: Option<i64>,
+}
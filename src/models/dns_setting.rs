@@ -0,0 +1,43 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+/// Dns settings
+/// Settings of the dns controller written in his dnsmasq configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct DnsSettings {
+  /// Upstream resolvers as ip address with an optional port `ip#port`
+  pub(crate) servers: Vec<String>,
+  /// Interfaces the dns controller listen to
+  pub(crate) interfaces: Vec<String>,
+  /// Number of names kept in cache, 0 to disable the cache
+  pub(crate) cache_size: u32,
+  /// Search domains given to the containers of the cargoes without their own,
+  /// short names are resolved inside them like in `/etc/resolv.conf`
+  pub(crate) search_domains: Vec<String>,
+}
+
+impl Default for DnsSettings {
+  fn default() -> Self {
+    Self {
+      servers: vec![String::from("8.8.8.8"), String::from("8.8.4.4")],
+      interfaces: vec![String::from("nanoclinternal0")],
+      cache_size: 150,
+      search_domains: Vec::new(),
+    }
+  }
+}
+
+/// Dns settings partial
+/// This structure is used in the daemon config file and as payload body
+/// to update the dns settings, only the given settings are changed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct DnsSettingsPartial {
+  pub(crate) servers: Option<Vec<String>>,
+  pub(crate) interfaces: Option<Vec<String>>,
+  pub(crate) cache_size: Option<u32>,
+  pub(crate) search_domains: Option<Vec<String>>,
+}
@@ -32,15 +32,45 @@ pub use cluster_variable::*;
 mod cargo_env;
 pub use cargo_env::*;
 
+mod cargo_revision;
+pub use cargo_revision::*;
+
+mod cargo_autoscaler;
+pub use cargo_autoscaler::*;
+
 mod proxy_template;
 pub use proxy_template::*;
 
+mod proxy_template_revision;
+pub use proxy_template_revision::*;
+
+mod proxy_route;
+pub use proxy_route::*;
+
+mod certificate;
+pub use certificate::*;
+
+mod dns_entry;
+pub use dns_entry::*;
+
+mod dns_record;
+pub use dns_record::*;
+
+mod dns_setting;
+pub use dns_setting::*;
+
+mod nginx_log;
+pub use nginx_log::*;
+
 mod cargo_image;
 pub use cargo_image::*;
 
 mod system;
 pub use system::*;
 
+mod state_file;
+pub use state_file::*;
+
 #[cfg(feature = "dev")]
 mod openapi;
 #[cfg(feature = "dev")]
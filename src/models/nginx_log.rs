@@ -0,0 +1,135 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::nginx_logs;
+
+/// Nginx log partial is an access log line written by the proxy
+/// Every values are strings as written by nginx, empty when a variable is not set
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NginxLogPartial {
+  pub(crate) date_gmt: String,
+  pub(crate) uri: String,
+  pub(crate) host: String,
+  pub(crate) remote_addr: String,
+  pub(crate) realip_remote_addr: String,
+  pub(crate) server_protocol: String,
+  pub(crate) request_method: String,
+  pub(crate) content_length: String,
+  pub(crate) status: String,
+  pub(crate) request_time: String,
+  pub(crate) body_bytes_sent: String,
+  pub(crate) proxy_host: String,
+  pub(crate) upstream_addr: String,
+  pub(crate) query_string: String,
+  pub(crate) request_body: String,
+  pub(crate) content_type: String,
+  pub(crate) http_user_agent: String,
+  pub(crate) http_referrer: String,
+  pub(crate) http_accept_language: String,
+}
+
+/// Nginx log item is an http request received by the proxy
+#[derive(
+  Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Insertable,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = nginx_logs)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct NginxLogItem {
+  pub(crate) key: uuid::Uuid,
+  pub(crate) date_gmt: chrono::DateTime<chrono::Utc>,
+  pub(crate) uri: String,
+  pub(crate) host: String,
+  pub(crate) remote_addr: String,
+  pub(crate) realip_remote_addr: String,
+  pub(crate) server_protocol: String,
+  pub(crate) request_method: String,
+  pub(crate) content_length: i64,
+  pub(crate) status: i64,
+  pub(crate) request_time: f64,
+  pub(crate) body_bytes_sent: i64,
+  pub(crate) proxy_host: Option<String>,
+  pub(crate) upstream_addr: Option<String>,
+  pub(crate) query_string: Option<String>,
+  pub(crate) request_body: Option<String>,
+  pub(crate) content_type: Option<String>,
+  pub(crate) http_user_agent: Option<String>,
+  pub(crate) http_referrer: Option<String>,
+  pub(crate) http_accept_language: Option<String>,
+}
+
+/// Field the proxied http requests are grouped by over time
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub enum NginxLogGroupBy {
+  Host,
+  Uri,
+}
+
+/// Query to filter the proxied http requests
+/// `since` and `until` are unix timestamps, the last 24 hours are used by default.
+/// `cluster` and `cargo` filter the requests by the domain of the dns entry of the cargoes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NginxLogQuery {
+  pub(crate) namespace: Option<String>,
+  pub(crate) cluster: Option<String>,
+  pub(crate) cargo: Option<String>,
+  pub(crate) host: Option<String>,
+  pub(crate) since: Option<i64>,
+  pub(crate) until: Option<i64>,
+  /// Size in seconds of the time buckets
+  pub(crate) interval: Option<i64>,
+  pub(crate) group_by: Option<NginxLogGroupBy>,
+  /// Maximum number of items in a top
+  pub(crate) limit: Option<i64>,
+}
+
+/// Filter of the proxied http requests resolved from a query
+/// No request match when hosts is an empty list
+#[derive(Debug, Clone)]
+pub struct NginxLogFilter {
+  pub(crate) since: chrono::DateTime<chrono::Utc>,
+  pub(crate) until: chrono::DateTime<chrono::Utc>,
+  pub(crate) hosts: Option<Vec<String>>,
+}
+
+/// Number of proxied http requests of a host or uri in a time bucket
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct NginxLogRequestCount {
+  pub(crate) date: chrono::DateTime<chrono::Utc>,
+  pub(crate) host: String,
+  pub(crate) uri: Option<String>,
+  pub(crate) count: i64,
+}
+
+/// Number of proxied http requests answered with a status code
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct NginxLogStatusCount {
+  pub(crate) status: i64,
+  pub(crate) count: i64,
+}
+
+/// Percentiles in seconds of the request time of proxied http requests
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct NginxLogLatency {
+  pub(crate) count: i64,
+  pub(crate) p50: f64,
+  pub(crate) p95: f64,
+  pub(crate) p99: f64,
+}
+
+/// Number of proxied http requests sharing a value
+/// eg: the user agent or the remote address
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct NginxLogTopItem {
+  pub(crate) value: String,
+  pub(crate) count: i64,
+}
@@ -0,0 +1,62 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::proxy_routes;
+
+use super::cargo::CargoItem;
+use super::proxy_template::ProxyTemplateModes;
+
+/// Proxy route partial
+/// This structure is used as payload body to create or update a route.
+/// Http routes forward the requests of a host and path prefix to a cargo,
+/// stream routes forward the connections of a port of the proxy to a cargo.
+/// The ssl certificate and key are paths inside the proxy container.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct ProxyRoutePartial {
+  pub(crate) name: String,
+  pub(crate) mode: ProxyTemplateModes,
+  pub(crate) host: Option<String>,
+  pub(crate) path: Option<String>,
+  pub(crate) cargo: String,
+  pub(crate) target_port: i64,
+  pub(crate) stream_port: Option<i64>,
+  pub(crate) ssl_certificate: Option<String>,
+  pub(crate) ssl_certificate_key: Option<String>,
+  pub(crate) ssl_redirect: Option<bool>,
+}
+
+/// Proxy route item
+/// The proxy configuration is generated from the routes by the daemon
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  AsChangeset,
+  Associations,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = proxy_routes)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(belongs_to(CargoItem, foreign_key = cargo_key))]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct ProxyRouteItem {
+  pub(crate) key: String,
+  pub(crate) name: String,
+  pub(crate) namespace_name: String,
+  pub(crate) mode: ProxyTemplateModes,
+  pub(crate) host: Option<String>,
+  pub(crate) path: String,
+  pub(crate) cargo_key: String,
+  pub(crate) target_port: i64,
+  pub(crate) stream_port: Option<i64>,
+  pub(crate) ssl_certificate: Option<String>,
+  pub(crate) ssl_certificate_key: Option<String>,
+  pub(crate) ssl_redirect: bool,
+}
@@ -32,3 +32,12 @@ pub struct ProxyTemplateItem {
   pub(crate) mode: ProxyTemplateModes,
   pub(crate) content: String,
 }
+
+/// Proxy template patch partial
+/// This structure is used as payload body to update a template in place
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct ProxyTemplatePatchPartial {
+  pub(crate) mode: Option<ProxyTemplateModes>,
+  pub(crate) content: Option<String>,
+}
@@ -0,0 +1,33 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use crate::schema::proxy_template_revisions;
+
+use super::proxy_template::{ProxyTemplateItem, ProxyTemplateModes};
+
+/// Proxy template revision item is a snapshot of a template content
+/// taken when the template is created and on every update
+#[derive(
+  Debug,
+  Clone,
+  Serialize,
+  Deserialize,
+  Queryable,
+  Identifiable,
+  Insertable,
+  Associations,
+)]
+#[diesel(primary_key(key))]
+#[diesel(table_name = proxy_template_revisions)]
+#[diesel(belongs_to(ProxyTemplateItem, foreign_key = template_name))]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct ProxyTemplateRevisionItem {
+  pub(crate) key: String,
+  pub(crate) template_name: String,
+  pub(crate) revision: i64,
+  pub(crate) mode: ProxyTemplateModes,
+  pub(crate) content: String,
+  pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+}
@@ -0,0 +1,72 @@
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "dev")]
+use utoipa::ToSchema;
+
+use super::cargo::CargoPartial;
+use super::cluster::ClusterJoinBody;
+use super::cluster_network::ClusterNetworkPartial;
+use super::cluster_variable::ClusterVariablePartial;
+use super::proxy_template::ProxyTemplateItem;
+
+/// Cluster definition inside a state file
+/// Networks, variables and joined cargoes are described with the cluster
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct StateFileCluster {
+  pub(crate) name: String,
+  pub(crate) proxy_templates: Option<Vec<String>>,
+  pub(crate) variables: Option<Vec<ClusterVariablePartial>>,
+  pub(crate) networks: Option<Vec<ClusterNetworkPartial>>,
+  pub(crate) joins: Option<Vec<ClusterJoinBody>>,
+}
+
+/// State file describing the desired state of a namespace
+/// It can be submitted as yaml or json
+/// and is produced by the export of a cluster or a namespace
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct StateFile {
+  pub(crate) namespace: Option<String>,
+  pub(crate) proxy_templates: Option<Vec<ProxyTemplateItem>>,
+  pub(crate) clusters: Option<Vec<StateFileCluster>>,
+  pub(crate) cargoes: Option<Vec<CargoPartial>>,
+}
+
+/// Kind of resource managed by a state file
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub enum StateResourceKind {
+  Namespace,
+  ProxyTemplate,
+  Cluster,
+  ClusterVariable,
+  ClusterNetwork,
+  Cargo,
+  CargoInstance,
+  Container,
+}
+
+/// Action taken or planned on a resource of a state file
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub enum StateAction {
+  Create,
+  Update,
+  Delete,
+  Recreate,
+  Leave,
+}
+
+/// Result for a single resource of a state file
+/// The reason explain why the resource is not left as is
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "dev", derive(ToSchema))]
+pub struct StateResourceResult {
+  pub(crate) kind: StateResourceKind,
+  pub(crate) key: String,
+  pub(crate) action: StateAction,
+  pub(crate) reason: Option<String>,
+}
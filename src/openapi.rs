@@ -7,7 +7,7 @@ use crate::models::*;
 #[cfg(feature = "dev")]
 use crate::services::*;
 #[cfg(feature = "dev")]
-use crate::errors::ApiError;
+use crate::errors::{ApiError, ApiStateApplyError};
 #[cfg(feature = "dev")]
 use ntex_files as fs;
 
@@ -19,9 +19,36 @@ use ntex_files as fs;
     namespace::create_namespace,
     namespace::delete_namespace_by_name,
     namespace::inspect_namespace_by_name,
+    namespace::export_namespace_by_name,
 
     // proxy template
     proxy_template::list_proxy_template,
+    proxy_template::patch_proxy_template_by_name,
+    proxy_template::list_proxy_template_revisions,
+
+    // Proxy route
+    proxy_route::list_proxy_route,
+    proxy_route::create_proxy_route,
+    proxy_route::inspect_proxy_route_by_name,
+    proxy_route::update_proxy_route_by_name,
+    proxy_route::delete_proxy_route_by_name,
+
+    // Certificate
+    certificate::list_certificate,
+    certificate::create_certificate,
+    certificate::inspect_certificate_by_name,
+    certificate::delete_certificate_by_name,
+
+    // Dns record
+    dns_record::list_dns_record,
+    dns_record::create_dns_record,
+    dns_record::inspect_dns_record_by_name,
+    dns_record::update_dns_record_by_name,
+    dns_record::delete_dns_record_by_name,
+
+    // Dns settings
+    dns_setting::get_dns_settings,
+    dns_setting::patch_dns_settings,
 
     // Cargo images
     cargo_image::list_cargo_image,
@@ -34,11 +61,27 @@ use ntex_files as fs;
     cargo::create_cargo,
     cargo::delete_cargo_by_name,
     cargo::count_cargo,
+    cargo::scale_cargo_by_name,
+    cargo::logs_cargo_by_name,
+    cargo::stats_cargo_by_name,
+    cargo::list_cargo_history,
+    cargo::rollback_cargo_by_name,
+
+    // Cargo autoscaler
+    cargo_autoscaler::put_cargo_autoscaler,
+    cargo_autoscaler::inspect_cargo_autoscaler,
+    cargo_autoscaler::delete_cargo_autoscaler,
+    cargo_autoscaler::list_cargo_scale_event,
 
     // Cargo instance
     cargo_instance::list_cargo_instance,
+    cargo_instance::logs_cargo_instance,
+    cargo_instance::stats_cargo_instance,
     cargo_instance::create_cargo_instance_exec,
     cargo_instance::start_cargo_instance_exec,
+    cargo_instance::attach_cargo_instance_exec,
+    cargo_instance::resize_cargo_instance_exec,
+    cargo_instance::inspect_cargo_instance_exec,
 
     // Cluster
     cluster::list_cluster,
@@ -46,8 +89,10 @@ use ntex_files as fs;
     cluster::create_cluster,
     cluster::delete_cluster_by_name,
     cluster::inspect_cluster_by_name,
+    cluster::export_cluster_by_name,
     cluster::start_cluster_by_name,
     cluster::join_cargo_to_cluster,
+    cluster::render_cluster_template,
 
     // Cluster variable
     cluster_variable::list_cluster_variable,
@@ -60,15 +105,49 @@ use ntex_files as fs;
     cluster_network::delete_cluster_network_by_name,
     cluster_network::inspect_cluster_network_by_name,
     cluster_network::count_cluster_network_by_namespace,
+
+    // State file
+    state_file::apply_state_file,
+    state_file::plan_state_file,
+
+    // Metrics
+    metrics::get_metrics,
+
+    // Nginx log
+    nginx_log::count_nginx_log_requests,
+    nginx_log::count_nginx_log_status,
+    nginx_log::get_nginx_log_latency,
+    nginx_log::list_nginx_log_user_agents,
+    nginx_log::list_nginx_log_remote_addrs,
   ),
   components(
     schemas(ApiError),
+    schemas(ApiStateApplyError),
     schemas(GenericDelete),
     schemas(GenericCount),
 
     // Proxy template
     schemas(ProxyTemplateItem),
     schemas(ProxyTemplateModes),
+    schemas(ProxyTemplatePatchPartial),
+    schemas(ProxyTemplateRevisionItem),
+
+    // Proxy route
+    schemas(ProxyRoutePartial),
+    schemas(ProxyRouteItem),
+
+    // Certificate
+    schemas(CertificatePartial),
+    schemas(CertificateItem),
+
+    // Dns record
+    schemas(DnsRecordKinds),
+    schemas(DnsRecordPartial),
+    schemas(DnsRecordItem),
+
+    // Dns settings
+    schemas(DnsSettings),
+    schemas(DnsSettingsPartial),
 
     // Namespace
     schemas(NamespaceItem),
@@ -88,6 +167,17 @@ use ntex_files as fs;
     // Cargo
     schemas(CargoItem),
     schemas(CargoPartial),
+    schemas(CargoScaleBody),
+    schemas(CargoRevisionItem),
+    schemas(CargoHealthCheck),
+    schemas(CargoItemWithRelation),
+    schemas(CargoContainerHealth),
+    schemas(CargoInstanceHealth),
+
+    // Cargo autoscaler
+    schemas(CargoAutoscalerPartial),
+    schemas(CargoAutoscalerItem),
+    schemas(CargoScaleEventItem),
 
     // Cargo instance
     schemas(ContainerSummary),
@@ -99,6 +189,12 @@ use ntex_files as fs;
     schemas(EndpointSettings),
     schemas(PortTypeEnum),
     schemas(CargoInstanceExecBody),
+    schemas(CargoInstanceOutput),
+    schemas(CargoInstanceOutputKind),
+    schemas(CargoInstanceExecResizeBody),
+    schemas(CargoInstanceExecInspect),
+    schemas(CargoInstanceStats),
+    schemas(CargoStats),
     schemas(CreateExecResults),
 
     // Cluster
@@ -115,6 +211,21 @@ use ntex_files as fs;
     schemas(ClusterNetworkItem),
     schemas(ClusterNetworkPartial),
 
+    // State file
+    schemas(StateFile),
+    schemas(StateFileCluster),
+    schemas(StateResourceKind),
+    schemas(StateAction),
+    schemas(StateResourceResult),
+
+    // Nginx log
+    schemas(NginxLogItem),
+    schemas(NginxLogGroupBy),
+    schemas(NginxLogRequestCount),
+    schemas(NginxLogStatusCount),
+    schemas(NginxLogLatency),
+    schemas(NginxLogTopItem),
+
     // ClusterItemWithRelation,
 
     // Todo Docker network struct bindings
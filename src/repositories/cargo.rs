@@ -4,7 +4,7 @@ use diesel::prelude::*;
 use crate::controllers;
 use crate::models::{
   Pool, CargoItem, CargoPartial, GenericDelete, NamespaceItem, GenericCount,
-  CargoPatchPartial, CargoPatchItem,
+  CargoPatchPartial, CargoPatchItem, CargoRevisionPartial,
 };
 
 use crate::errors::HttpResponseError;
@@ -24,6 +24,26 @@ pub async fn find_by_namespace(
   }
 }
 
+/// List the dns entries of every cargoes formated as `ip:domain`
+pub async fn list_dns_entries(
+  pool: &Pool,
+) -> Result<Vec<String>, HttpResponseError> {
+  use crate::schema::cargoes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::cargoes
+      .filter(dsl::dns_entry.is_not_null())
+      .select(dsl::dns_entry.assume_not_null())
+      .load::<String>(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
 pub async fn create(
   nsp: String,
   item: CargoPartial,
@@ -147,3 +167,29 @@ pub async fn update_by_key(
     Ok(item) => Ok(item),
   }
 }
+
+/// Restore the config, replicas and dns entry of a cargo from a revision
+/// Unlike a patch a dns entry missing from the revision is removed
+pub async fn restore_revision(
+  revision: CargoRevisionPartial,
+  pool: &Pool,
+) -> Result<CargoItem, HttpResponseError> {
+  use crate::schema::cargoes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::update(dsl::cargoes.filter(dsl::key.eq(revision.cargo_key)))
+      .set((
+        dsl::config.eq(revision.config),
+        dsl::replicas.eq(revision.replicas),
+        dsl::dns_entry.eq(revision.dns_entry),
+      ))
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
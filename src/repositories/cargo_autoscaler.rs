@@ -0,0 +1,84 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{Pool, CargoAutoscalerItem, GenericDelete};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+/// Create or replace the autoscaler of a cargo
+pub async fn upsert(
+  item: CargoAutoscalerItem,
+  pool: &Pool,
+) -> Result<CargoAutoscalerItem, HttpResponseError> {
+  use crate::schema::cargo_autoscalers::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::insert_into(dsl::cargo_autoscalers)
+      .values(&item)
+      .on_conflict(dsl::cargo_key)
+      .do_update()
+      .set(&item)
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn find_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<CargoAutoscalerItem, HttpResponseError> {
+  use crate::schema::cargo_autoscalers::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::cargo_autoscalers
+      .filter(dsl::cargo_key.eq(cargo_key))
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn list(
+  pool: &Pool,
+) -> Result<Vec<CargoAutoscalerItem>, HttpResponseError> {
+  use crate::schema::cargo_autoscalers::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || dsl::cargo_autoscalers.load(&mut conn)).await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn delete_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::cargo_autoscalers::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::cargo_autoscalers.filter(dsl::cargo_key.eq(cargo_key)))
+      .execute(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
@@ -153,3 +153,21 @@ pub async fn list_by_cargo_key(
     Ok(items) => Ok(items),
   }
 }
+
+pub async fn delete_by_key(
+  key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::cargo_environnements::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::cargo_environnements.filter(dsl::key.eq(key)))
+      .execute(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
@@ -20,6 +20,7 @@ pub async fn create(
       network_key: item.network_key,
       cluster_key: item.cluster_key,
       cargo_key: item.cargo_key,
+      started: false,
     };
     diesel::insert_into(dsl::cargo_instances)
       .values(&item)
@@ -52,6 +53,27 @@ pub async fn get_by_cluster_key(
   }
 }
 
+pub async fn set_started_by_cluster_key(
+  cluster_key: String,
+  pool: &Pool,
+) -> Result<usize, HttpResponseError> {
+  use crate::schema::cargo_instances::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::update(
+      dsl::cargo_instances.filter(dsl::cluster_key.eq(cluster_key)),
+    )
+    .set(dsl::started.eq(true))
+    .execute(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(count) => Ok(count),
+  }
+}
+
 pub async fn delete_by_cluster_key(
   key: String,
   pool: &Pool,
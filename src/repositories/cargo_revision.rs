@@ -0,0 +1,110 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{Pool, CargoRevisionPartial, CargoRevisionItem, GenericDelete};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+/// Store a new revision of a cargo
+/// The revision number follows the last revision of the cargo
+pub async fn create(
+  item: CargoRevisionPartial,
+  pool: &Pool,
+) -> Result<CargoRevisionItem, HttpResponseError> {
+  use crate::schema::cargo_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+      let last_revision: Option<i64> = dsl::cargo_revisions
+        .filter(dsl::cargo_key.eq(&item.cargo_key))
+        .select(diesel::dsl::max(dsl::revision))
+        .first(conn)?;
+      let revision = last_revision.unwrap_or(0) + 1;
+      let item = CargoRevisionItem {
+        key: format!("{}-{}", &item.cargo_key, revision),
+        cargo_key: item.cargo_key,
+        revision,
+        config: item.config,
+        replicas: item.replicas,
+        dns_entry: item.dns_entry,
+        environnements: item.environnements,
+        created_at: chrono::Utc::now(),
+      };
+      diesel::insert_into(dsl::cargo_revisions)
+        .values(&item)
+        .execute(conn)?;
+      Ok(item)
+    })
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+/// List the revisions of a cargo, the latest first
+pub async fn list_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<Vec<CargoRevisionItem>, HttpResponseError> {
+  use crate::schema::cargo_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::cargo_revisions
+      .filter(dsl::cargo_key.eq(cargo_key))
+      .order(dsl::revision.desc())
+      .get_results(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn find_by_revision(
+  cargo_key: String,
+  revision: i64,
+  pool: &Pool,
+) -> Result<CargoRevisionItem, HttpResponseError> {
+  use crate::schema::cargo_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::cargo_revisions
+      .filter(dsl::cargo_key.eq(cargo_key))
+      .filter(dsl::revision.eq(revision))
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn delete_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::cargo_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::cargo_revisions.filter(dsl::cargo_key.eq(cargo_key)))
+      .execute(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
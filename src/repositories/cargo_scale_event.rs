@@ -0,0 +1,85 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{
+  Pool, CargoScaleEventPartial, CargoScaleEventItem, GenericDelete,
+};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+/// Maximum number of scale events returned for a cargo
+const MAX_EVENTS: i64 = 100;
+
+pub async fn create(
+  item: CargoScaleEventPartial,
+  pool: &Pool,
+) -> Result<CargoScaleEventItem, HttpResponseError> {
+  use crate::schema::cargo_scale_events::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    let item = CargoScaleEventItem {
+      key: uuid::Uuid::new_v4(),
+      cargo_key: item.cargo_key,
+      from_replicas: item.from_replicas,
+      to_replicas: item.to_replicas,
+      cpu: item.cpu,
+      memory: item.memory,
+      reason: item.reason,
+      created_at: chrono::Utc::now(),
+    };
+    diesel::insert_into(dsl::cargo_scale_events)
+      .values(&item)
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+/// List the last scale events of a cargo, the latest first
+pub async fn list_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<Vec<CargoScaleEventItem>, HttpResponseError> {
+  use crate::schema::cargo_scale_events::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::cargo_scale_events
+      .filter(dsl::cargo_key.eq(cargo_key))
+      .order(dsl::created_at.desc())
+      .limit(MAX_EVENTS)
+      .get_results(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn delete_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::cargo_scale_events::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::cargo_scale_events.filter(dsl::cargo_key.eq(cargo_key)))
+      .execute(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
@@ -0,0 +1,88 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{Pool, CertificateItem, GenericDelete};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+pub async fn create(
+  item: CertificateItem,
+  pool: &Pool,
+) -> Result<CertificateItem, HttpResponseError> {
+  use crate::schema::certificates::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::insert_into(dsl::certificates)
+      .values(&item)
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+/// List the certificates, the closest to their expiry first
+pub async fn list(
+  pool: &Pool,
+) -> Result<Vec<CertificateItem>, HttpResponseError> {
+  use crate::schema::certificates::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::certificates
+      .order(dsl::not_after.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn find_by_name(
+  name: String,
+  pool: &Pool,
+) -> Result<CertificateItem, HttpResponseError> {
+  use crate::schema::certificates::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::certificates
+      .filter(dsl::name.eq(name))
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn delete_by_name(
+  name: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::certificates::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::certificates.filter(dsl::name.eq(name)))
+      .execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
@@ -146,6 +146,31 @@ pub async fn find_by_namespace(
   }
 }
 
+/// Return the clusters using a proxy template
+///
+/// # Arguments
+///
+/// * `template_name` - Name of the proxy template
+/// * `pool` - Posgresql database pool
+pub async fn find_by_proxy_template(
+  template_name: String,
+  pool: &Pool,
+) -> Result<Vec<ClusterItem>, HttpResponseError> {
+  use crate::schema::clusters::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::clusters
+      .filter(dsl::proxy_templates.contains(vec![template_name]))
+      .load(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
 /// Return number of deleted entries
 ///
 /// # Arguments
@@ -112,3 +112,23 @@ pub async fn find_by_key(
     Ok(item) => Ok(item),
   }
 }
+
+pub async fn patch_by_key(
+  key: String,
+  value: String,
+  pool: &Pool,
+) -> Result<ClusterVariableItem, HttpResponseError> {
+  use crate::schema::cluster_variables::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::update(dsl::cluster_variables.filter(dsl::key.eq(key)))
+      .set(dsl::value.eq(value))
+      .get_result(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
@@ -0,0 +1,102 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{Pool, DnsEntryItem, GenericDelete};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+/// Create or replace the dns entry of a cargo instance
+pub async fn upsert(
+  item: DnsEntryItem,
+  pool: &Pool,
+) -> Result<DnsEntryItem, HttpResponseError> {
+  use crate::schema::dns_entries::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::insert_into(dsl::dns_entries)
+      .values(&item)
+      .on_conflict(dsl::cargo_instance_key)
+      .do_update()
+      .set(&item)
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+/// List every dns entries ordered by domain
+pub async fn list(pool: &Pool) -> Result<Vec<DnsEntryItem>, HttpResponseError> {
+  use crate::schema::dns_entries::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::dns_entries
+      .order((dsl::domain.asc(), dsl::cargo_instance_key.asc()))
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+/// Delete the dns entries of the instances of a cargo
+pub async fn delete_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::dns_entries::dsl;
+  use crate::schema::cargo_instances;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    let instance_keys = cargo_instances::table
+      .filter(cargo_instances::cargo_key.eq(cargo_key))
+      .select(cargo_instances::key);
+    diesel::delete(
+      dsl::dns_entries.filter(dsl::cargo_instance_key.eq_any(instance_keys)),
+    )
+    .execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
+
+/// Delete the dns entries of the instances of a cluster
+pub async fn delete_by_cluster_key(
+  cluster_key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::dns_entries::dsl;
+  use crate::schema::cargo_instances;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    let instance_keys = cargo_instances::table
+      .filter(cargo_instances::cluster_key.eq(cluster_key))
+      .select(cargo_instances::key);
+    diesel::delete(
+      dsl::dns_entries.filter(dsl::cargo_instance_key.eq_any(instance_keys)),
+    )
+    .execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
@@ -0,0 +1,130 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{Pool, DnsRecordItem, GenericDelete};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+pub async fn create(
+  item: DnsRecordItem,
+  pool: &Pool,
+) -> Result<DnsRecordItem, HttpResponseError> {
+  use crate::schema::dns_records::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::insert_into(dsl::dns_records)
+      .values(&item)
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+/// List every dns records whatever their namespace
+pub async fn list(
+  pool: &Pool,
+) -> Result<Vec<DnsRecordItem>, HttpResponseError> {
+  use crate::schema::dns_records::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::dns_records
+      .order((dsl::domain.asc(), dsl::key.asc()))
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn find_by_namespace(
+  namespace: String,
+  pool: &Pool,
+) -> Result<Vec<DnsRecordItem>, HttpResponseError> {
+  use crate::schema::dns_records::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::dns_records
+      .filter(dsl::namespace_name.eq(namespace))
+      .order(dsl::key.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn find_by_key(
+  key: String,
+  pool: &Pool,
+) -> Result<DnsRecordItem, HttpResponseError> {
+  use crate::schema::dns_records::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::dns_records
+      .filter(dsl::key.eq(key))
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn update_by_key(
+  key: String,
+  item: DnsRecordItem,
+  pool: &Pool,
+) -> Result<DnsRecordItem, HttpResponseError> {
+  use crate::schema::dns_records::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::update(dsl::dns_records.filter(dsl::key.eq(key)))
+      .set(&item)
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn delete_by_key(
+  key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::dns_records::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::dns_records.filter(dsl::key.eq(key))).execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
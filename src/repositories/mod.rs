@@ -4,12 +4,21 @@ pub mod errors;
 pub mod namespace;
 
 pub mod proxy_template;
+pub mod proxy_template_revision;
+pub mod proxy_route;
+pub mod certificate;
+pub mod nginx_log;
 
 pub mod cargo;
 pub mod cargo_env;
+pub mod cargo_revision;
+pub mod cargo_autoscaler;
+pub mod cargo_scale_event;
 
 pub mod cluster;
 pub mod cargo_instance;
 pub mod cluster_network;
+pub mod dns_entry;
+pub mod dns_record;
 
 pub mod cluster_variable;
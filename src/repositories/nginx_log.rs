@@ -0,0 +1,215 @@
+use ntex::web;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use diesel::dsl::count_star;
+
+use crate::controllers;
+use crate::schema::nginx_logs;
+use crate::models::{
+  Pool, NginxLogItem, GenericDelete, NginxLogFilter, NginxLogStatusCount,
+  NginxLogTopItem,
+};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+type NginxLogPredicate =
+  Box<dyn BoxableExpression<nginx_logs::table, Pg, SqlType = Bool>>;
+
+fn gen_predicate(filter: NginxLogFilter) -> NginxLogPredicate {
+  use crate::schema::nginx_logs::dsl;
+
+  let predicate = dsl::date_gmt
+    .ge(filter.since)
+    .and(dsl::date_gmt.lt(filter.until));
+  match filter.hosts {
+    None => Box::new(predicate),
+    Some(hosts) => Box::new(predicate.and(dsl::host.eq_any(hosts))),
+  }
+}
+
+/// Insert nginx logs in a single query
+pub async fn create_many(
+  items: Vec<NginxLogItem>,
+  pool: &Pool,
+) -> Result<usize, HttpResponseError> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::insert_into(dsl::nginx_logs)
+      .values(&items)
+      .execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(count) => Ok(count),
+  }
+}
+
+/// Delete the nginx logs older than a date
+pub async fn delete_older_than(
+  date: chrono::DateTime<chrono::Utc>,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::nginx_logs.filter(dsl::date_gmt.lt(date)))
+      .execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
+
+/// List the date, host and uri of the requests matching a filter
+pub async fn list_requests(
+  filter: NginxLogFilter,
+  pool: &Pool,
+) -> Result<
+  Vec<(chrono::DateTime<chrono::Utc>, String, String)>,
+  HttpResponseError,
+> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::nginx_logs
+      .filter(gen_predicate(filter))
+      .select((dsl::date_gmt, dsl::host, dsl::uri))
+      .order(dsl::date_gmt.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+/// List the request times of the requests matching a filter, the fastest first
+pub async fn list_request_times(
+  filter: NginxLogFilter,
+  pool: &Pool,
+) -> Result<Vec<f64>, HttpResponseError> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::nginx_logs
+      .filter(gen_predicate(filter))
+      .select(dsl::request_time)
+      .order(dsl::request_time.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+/// Count the requests matching a filter by status code
+pub async fn count_by_status(
+  filter: NginxLogFilter,
+  pool: &Pool,
+) -> Result<Vec<NginxLogStatusCount>, HttpResponseError> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::nginx_logs
+      .filter(gen_predicate(filter))
+      .group_by(dsl::status)
+      .select((dsl::status, count_star()))
+      .order(dsl::status.asc())
+      .load::<(i64, i64)>(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(
+      items
+        .into_iter()
+        .map(|(status, count)| NginxLogStatusCount { status, count })
+        .collect(),
+    ),
+  }
+}
+
+/// List the user agents sending the most requests matching a filter
+pub async fn top_user_agents(
+  filter: NginxLogFilter,
+  limit: i64,
+  pool: &Pool,
+) -> Result<Vec<NginxLogTopItem>, HttpResponseError> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::nginx_logs
+      .filter(gen_predicate(filter))
+      .filter(dsl::http_user_agent.is_not_null())
+      .group_by(dsl::http_user_agent)
+      .select((dsl::http_user_agent, count_star()))
+      .order(count_star().desc())
+      .limit(limit)
+      .load::<(Option<String>, i64)>(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(
+      items
+        .into_iter()
+        .map(|(value, count)| NginxLogTopItem {
+          value: value.unwrap_or_default(),
+          count,
+        })
+        .collect(),
+    ),
+  }
+}
+
+/// List the remote addresses sending the most requests matching a filter
+pub async fn top_remote_addrs(
+  filter: NginxLogFilter,
+  limit: i64,
+  pool: &Pool,
+) -> Result<Vec<NginxLogTopItem>, HttpResponseError> {
+  use crate::schema::nginx_logs::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::nginx_logs
+      .filter(gen_predicate(filter))
+      .group_by(dsl::remote_addr)
+      .select((dsl::remote_addr, count_star()))
+      .order(count_star().desc())
+      .limit(limit)
+      .load::<(String, i64)>(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(
+      items
+        .into_iter()
+        .map(|(value, count)| NginxLogTopItem { value, count })
+        .collect(),
+    ),
+  }
+}
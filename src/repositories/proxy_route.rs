@@ -0,0 +1,195 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{Pool, ProxyRouteItem, GenericDelete};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+pub async fn create(
+  item: ProxyRouteItem,
+  pool: &Pool,
+) -> Result<ProxyRouteItem, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::insert_into(dsl::proxy_routes)
+      .values(&item)
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn find_by_key(
+  key: String,
+  pool: &Pool,
+) -> Result<ProxyRouteItem, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_routes
+      .filter(dsl::key.eq(key))
+      .get_result(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn find_by_namespace(
+  namespace: String,
+  pool: &Pool,
+) -> Result<Vec<ProxyRouteItem>, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_routes
+      .filter(dsl::namespace_name.eq(namespace))
+      .order(dsl::key.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+/// List the http routes of a host whatever their namespace
+pub async fn find_by_host(
+  host: String,
+  pool: &Pool,
+) -> Result<Vec<ProxyRouteItem>, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_routes
+      .filter(dsl::host.eq(host))
+      .order(dsl::key.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+/// List the hosts of the http routes without duplicate
+pub async fn list_hosts(pool: &Pool) -> Result<Vec<String>, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_routes
+      .filter(dsl::host.is_not_null())
+      .select(dsl::host.assume_not_null())
+      .distinct()
+      .load::<String>(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn find_by_cargo_key(
+  cargo_key: String,
+  pool: &Pool,
+) -> Result<Vec<ProxyRouteItem>, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_routes
+      .filter(dsl::cargo_key.eq(cargo_key))
+      .order(dsl::key.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+/// List the routes using their own ssl certificate whatever their namespace
+pub async fn find_with_ssl_certificate(
+  pool: &Pool,
+) -> Result<Vec<ProxyRouteItem>, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_routes
+      .filter(dsl::ssl_certificate.is_not_null())
+      .order(dsl::key.asc())
+      .load(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn update_by_key(
+  key: String,
+  item: ProxyRouteItem,
+  pool: &Pool,
+) -> Result<ProxyRouteItem, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::update(dsl::proxy_routes.filter(dsl::key.eq(key)))
+      .set(&item)
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+pub async fn delete_by_key(
+  key: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::proxy_routes::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(dsl::proxy_routes.filter(dsl::key.eq(key)))
+      .execute(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
@@ -61,6 +61,31 @@ pub async fn get_by_name(
   }
 }
 
+pub async fn update_by_name(
+  name: String,
+  item: ProxyTemplateItem,
+  pool: &Pool,
+) -> Result<ProxyTemplateItem, HttpResponseError> {
+  use crate::schema::proxy_templates::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::update(dsl::proxy_templates.filter(dsl::name.eq(name)))
+      .set((
+        dsl::mode.eq(item.mode.to_owned()),
+        dsl::content.eq(item.content.to_owned()),
+      ))
+      .execute(&mut conn)?;
+    Ok(item)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
 pub async fn delete_by_name(
   name: String,
   pool: &Pool,
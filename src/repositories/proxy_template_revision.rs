@@ -0,0 +1,91 @@
+use ntex::web;
+use diesel::prelude::*;
+
+use crate::controllers;
+use crate::models::{
+  Pool, ProxyTemplateItem, ProxyTemplateRevisionItem, GenericDelete,
+};
+
+use crate::errors::HttpResponseError;
+use super::errors::db_blocking_error;
+
+/// Store a new revision of a proxy template
+/// The revision number follows the last revision of the template
+pub async fn create(
+  item: ProxyTemplateItem,
+  pool: &Pool,
+) -> Result<ProxyTemplateRevisionItem, HttpResponseError> {
+  use crate::schema::proxy_template_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+      let last_revision: Option<i64> = dsl::proxy_template_revisions
+        .filter(dsl::template_name.eq(&item.name))
+        .select(diesel::dsl::max(dsl::revision))
+        .first(conn)?;
+      let revision = last_revision.unwrap_or(0) + 1;
+      let item = ProxyTemplateRevisionItem {
+        key: format!("{}-{}", &item.name, revision),
+        template_name: item.name,
+        revision,
+        mode: item.mode,
+        content: item.content,
+        created_at: chrono::Utc::now(),
+      };
+      diesel::insert_into(dsl::proxy_template_revisions)
+        .values(&item)
+        .execute(conn)?;
+      Ok(item)
+    })
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(item) => Ok(item),
+  }
+}
+
+/// List the revisions of a proxy template, the latest first
+pub async fn list_by_template_name(
+  template_name: String,
+  pool: &Pool,
+) -> Result<Vec<ProxyTemplateRevisionItem>, HttpResponseError> {
+  use crate::schema::proxy_template_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    dsl::proxy_template_revisions
+      .filter(dsl::template_name.eq(template_name))
+      .order(dsl::revision.desc())
+      .get_results(&mut conn)
+  })
+  .await;
+
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(items) => Ok(items),
+  }
+}
+
+pub async fn delete_by_template_name(
+  template_name: String,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  use crate::schema::proxy_template_revisions::dsl;
+
+  let mut conn = controllers::store::get_pool_conn(pool)?;
+  let res = web::block(move || {
+    diesel::delete(
+      dsl::proxy_template_revisions
+        .filter(dsl::template_name.eq(template_name)),
+    )
+    .execute(&mut conn)
+  })
+  .await;
+  match res {
+    Err(err) => Err(db_blocking_error(err)),
+    Ok(result) => Ok(GenericDelete { count: result }),
+  }
+}
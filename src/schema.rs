@@ -1,6 +1,10 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+  #[derive(diesel::sql_types::SqlType)]
+  #[diesel(postgres_type(name = "dns_record_kinds"))]
+  pub struct DnsRecordKinds;
+
   #[derive(diesel::sql_types::SqlType)]
   #[diesel(postgres_type(name = "node_modes"))]
   pub struct NodeModes;
@@ -14,6 +18,16 @@ pub mod sql_types {
   pub struct SshAuthModes;
 }
 
+diesel::table! {
+    cargo_autoscalers (cargo_key) {
+        cargo_key -> Varchar,
+        min_replicas -> Int8,
+        max_replicas -> Int8,
+        target_cpu -> Nullable<Int8>,
+        target_memory -> Nullable<Int8>,
+    }
+}
+
 diesel::table! {
     cargo_environnements (key) {
         key -> Varchar,
@@ -29,6 +43,33 @@ diesel::table! {
         cargo_key -> Varchar,
         cluster_key -> Varchar,
         network_key -> Varchar,
+        started -> Bool,
+    }
+}
+
+diesel::table! {
+    cargo_revisions (key) {
+        key -> Varchar,
+        cargo_key -> Varchar,
+        revision -> Int8,
+        config -> Jsonb,
+        replicas -> Int8,
+        dns_entry -> Nullable<Varchar>,
+        environnements -> Array<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    cargo_scale_events (key) {
+        key -> Uuid,
+        cargo_key -> Varchar,
+        from_replicas -> Int8,
+        to_replicas -> Int8,
+        cpu -> Nullable<Float8>,
+        memory -> Nullable<Float8>,
+        reason -> Varchar,
+        created_at -> Timestamptz,
     }
 }
 
@@ -43,6 +84,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    certificates (name) {
+        name -> Varchar,
+        sans -> Array<Text>,
+        not_after -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     cluster_networks (key) {
         key -> Varchar,
@@ -72,6 +122,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    dns_entries (cargo_instance_key) {
+        cargo_instance_key -> Varchar,
+        domain -> Varchar,
+        ip_address -> Varchar,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DnsRecordKinds;
+
+    dns_records (key) {
+        key -> Varchar,
+        name -> Varchar,
+        namespace_name -> Varchar,
+        kind -> DnsRecordKinds,
+        domain -> Varchar,
+        value -> Varchar,
+        port -> Nullable<Int8>,
+        priority -> Nullable<Int8>,
+        weight -> Nullable<Int8>,
+    }
+}
+
 diesel::table! {
     namespaces (name) {
         name -> Varchar,
@@ -118,6 +193,40 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ProxyTemplateModes;
+
+    proxy_routes (key) {
+        key -> Varchar,
+        name -> Varchar,
+        namespace_name -> Varchar,
+        mode -> ProxyTemplateModes,
+        host -> Nullable<Varchar>,
+        path -> Varchar,
+        cargo_key -> Varchar,
+        target_port -> Int8,
+        stream_port -> Nullable<Int8>,
+        ssl_certificate -> Nullable<Varchar>,
+        ssl_certificate_key -> Nullable<Varchar>,
+        ssl_redirect -> Bool,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ProxyTemplateModes;
+
+    proxy_template_revisions (key) {
+        key -> Varchar,
+        template_name -> Varchar,
+        revision -> Int8,
+        mode -> ProxyTemplateModes,
+        content -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::ProxyTemplateModes;
@@ -129,21 +238,37 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(cargo_autoscalers -> cargoes (cargo_key));
 diesel::joinable!(cargoes -> namespaces (namespace_name));
 diesel::joinable!(cargo_instances -> cargoes (cargo_key));
 diesel::joinable!(cargo_instances -> cluster_networks (network_key));
 diesel::joinable!(cargo_instances -> clusters (cluster_key));
+diesel::joinable!(cargo_revisions -> cargoes (cargo_key));
+diesel::joinable!(cargo_scale_events -> cargoes (cargo_key));
 diesel::joinable!(cluster_networks -> clusters (cluster_key));
+diesel::joinable!(dns_entries -> cargo_instances (cargo_instance_key));
+diesel::joinable!(dns_records -> namespaces (namespace_name));
+diesel::joinable!(proxy_routes -> cargoes (cargo_key));
+diesel::joinable!(proxy_routes -> namespaces (namespace_name));
+diesel::joinable!(proxy_template_revisions -> proxy_templates (template_name));
 
 diesel::allow_tables_to_appear_in_same_query!(
+  cargo_autoscalers,
   cargo_environnements,
   cargo_instances,
+  cargo_revisions,
+  cargo_scale_events,
   cargoes,
+  certificates,
   cluster_networks,
   cluster_variables,
   clusters,
+  dns_entries,
+  dns_records,
   namespaces,
   nginx_logs,
   nodes,
+  proxy_routes,
+  proxy_template_revisions,
   proxy_templates,
 );
@@ -7,7 +7,7 @@ use ntex::web;
 // use rustls::{Certificate, PrivateKey, ServerConfig};
 // use rustls_pemfile::{certs, pkcs8_private_keys};
 
-use crate::services;
+use crate::{services, utils};
 use crate::models::DaemonState;
 
 // fn load_certs(filename: &str) -> Vec<rustls::Certificate> {
@@ -63,6 +63,8 @@ pub async fn start<'a>(
       .state(daemon_state.docker_api.clone())
       // Default logger middleware
       .wrap(web::middleware::Logger::default())
+      // Http requests metrics middleware
+      .wrap(utils::metrics::HttpMetrics)
       // Set Json body max size
       .state(web::types::JsonConfig::default().limit(4096))
       // configure system service
@@ -81,8 +83,26 @@ pub async fn start<'a>(
       .configure(services::cargo_instance::ntex_config)
       // configure nginx template service
       .configure(services::proxy_template::ntex_config)
+      // configure proxy route service
+      .configure(services::proxy_route::ntex_config)
+      // configure certificate service
+      .configure(services::certificate::ntex_config)
+      // configure dns record service
+      .configure(services::dns_record::ntex_config)
+      // configure dns settings service
+      .configure(services::dns_setting::ntex_config)
       // configure cargo service
-      .configure(services::cargo::ntex_config);
+      .configure(services::cargo::ntex_config)
+      // configure cargo autoscaler service
+      .configure(services::cargo_autoscaler::ntex_config)
+      // configure state file service
+      .configure(services::state_file::ntex_config)
+      // configure metrics service
+      .configure(services::metrics::ntex_config)
+      // configure nginx log service
+      .configure(services::nginx_log::ntex_config)
+      // Record the requests that didn't match any route
+      .default_service(web::route().to(utils::metrics::unmatched_route));
 
     // configure openapi if dev feature is enabled
     #[cfg(feature = "dev")]
@@ -1,12 +1,15 @@
 use ntex::web;
 use ntex::http::StatusCode;
 use futures::{stream, StreamExt};
+use futures::stream::FuturesUnordered;
 
 use crate::models::DaemonConfig;
-use crate::{repositories, utils};
+use crate::{controllers, repositories, utils};
 use crate::models::{
   Pool, GenericNspQuery, CargoPartial, CargoEnvPartial, CargoItemWithRelation,
-  CargoInstanceFilterQuery, CargoPatchPartial,
+  CargoInstanceFilterQuery, CargoPatchPartial, CargoRollbackQuery,
+  CargoUpdateStrategy, CargoContainerHealth, CargoScaleBody,
+  CargoInstanceLogQuery, CargoInstanceStatsQuery, CargoRevisionPartial,
 };
 
 use crate::errors::HttpResponseError;
@@ -78,41 +81,21 @@ async fn count_cargo(
 async fn create_cargo(
   pool: web::types::State<Pool>,
   web::types::Query(qs): web::types::Query<GenericNspQuery>,
-  web::types::Json(payload): web::types::Json<CargoPartial>,
+  web::types::Json(mut payload): web::types::Json<CargoPartial>,
 ) -> Result<web::HttpResponse, HttpResponseError> {
   let nsp = utils::key::resolve_nsp(&qs.namespace);
-  // Ensure the config is a valid json object that can be parsed as bollard::container::Config
-  let config = serde_json::from_value::<bollard::container::Config<String>>(
-    payload.config.to_owned(),
-  )
-  .map_err(|e| HttpResponseError {
-    msg: format!("config is not a valid json: {}", e),
-    status: StatusCode::UNPROCESSABLE_ENTITY,
-  })?;
-
-  // Ensure the image is present
-  if config.image.is_none() {
-    return Err(HttpResponseError {
-      msg: "config.image is required".to_owned(),
-      status: StatusCode::UNPROCESSABLE_ENTITY,
-    });
+  // Ensure the config is valid and the image is present
+  utils::cargo::parse_config(&payload.config)?;
+  // Health checks are stored in the config
+  if let Some(healthcheck) = payload.healthcheck.take() {
+    payload.config =
+      utils::cargo::merge_healthcheck(&payload.config, &healthcheck)?;
   }
 
   // Parse environnements variables and ensure they are valid
-  let environnements = payload.environnements.to_owned();
-  let mut envs: Vec<(String, String)> = Vec::new();
-  if let Some(environnements) = environnements {
-    for env in environnements {
-      let splited = env.split('=').collect::<Vec<&str>>();
-      if splited.len() != 2 {
-        return Err(HttpResponseError {
-          msg: format!("env item {} is not a valid format", env),
-          status: StatusCode::UNPROCESSABLE_ENTITY,
-        });
-      }
-      envs.push((splited[0].to_owned(), splited[1].to_owned()));
-    }
-  }
+  let envs = utils::cargo::parse_environnements(
+    &payload.environnements.to_owned().unwrap_or_default(),
+  )?;
 
   let item = repositories::cargo::create(nsp, payload, &pool).await?;
   let envs = envs
@@ -124,6 +107,7 @@ async fn create_cargo(
     })
     .collect::<Vec<CargoEnvPartial>>();
   repositories::cargo_env::create_many(envs, &pool).await?;
+  utils::cargo::create_revision(&item.key, &pool).await?;
   Ok(web::HttpResponse::Created().json(&item))
 }
 
@@ -157,6 +141,13 @@ async fn inspect_cargo_by_name(
     namespace: qs.namespace.to_owned(),
   };
   let containers = utils::cargo::list_instances(qs, &docker_api).await?;
+  let health = containers
+    .iter()
+    .map(|container| CargoContainerHealth {
+      name: utils::cargo_instance::get_container_name(container),
+      health: utils::cargo_instance::get_container_health(container),
+    })
+    .collect::<Vec<CargoContainerHealth>>();
   let environnements = if let Ok(envs) =
     repositories::cargo_env::list_by_cargo_key(key, &pool).await
   {
@@ -173,6 +164,7 @@ async fn inspect_cargo_by_name(
     environnements,
     replicas: res.replicas,
     dns_entry: res.dns_entry,
+    health,
     containers,
   };
 
@@ -182,7 +174,7 @@ async fn inspect_cargo_by_name(
 #[web::patch("/cargoes/{name}")]
 async fn patch_cargo_by_name(
   name: web::types::Path<String>,
-  web::types::Json(payload): web::types::Json<CargoPatchPartial>,
+  web::types::Json(mut payload): web::types::Json<CargoPatchPartial>,
   web::types::Query(qs): web::types::Query<GenericNspQuery>,
   daemon_config: web::types::State<DaemonConfig>,
   pool: web::types::State<Pool>,
@@ -193,53 +185,304 @@ async fn patch_cargo_by_name(
   let key = utils::key::gen_key(&nsp, &name);
 
   // Ensure cargo exists
-  let _cargo = repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
-
-  // Add environement variables
-  let mut env_stream =
-    stream::iter(payload.environnements.to_owned().unwrap_or_default());
-  while let Some(env) = env_stream.next().await {
-    let arr = env.split('=').collect::<Vec<&str>>();
-    if arr.len() != 2 {
-      return Err(HttpResponseError {
-        msg: format!("env variable formated incorrectly {}", &env),
-        status: StatusCode::UNPROCESSABLE_ENTITY,
-      });
-    }
-    let name = arr[0].to_owned();
-    let value = arr[1].to_owned();
-    let env = CargoEnvPartial {
-      cargo_key: key.to_owned(),
-      name: name.to_owned(),
-      value: value.to_owned(),
-    };
-    let env_exists = repositories::cargo_env::exist_in_cargo(
-      name.to_owned(),
-      key.to_owned(),
-      &pool,
-    )
-    .await?;
-    if env_exists {
-      // Update env variable if it exists
-      repositories::cargo_env::patch_for_cargo(
+  let cargo = repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
+
+  // Health checks are stored in the config
+  if let Some(healthcheck) = payload.healthcheck.take() {
+    let config = payload.config.as_ref().unwrap_or(&cargo.config);
+    payload.config =
+      Some(utils::cargo::merge_healthcheck(config, &healthcheck)?);
+  }
+  let envs = utils::cargo::parse_environnements(
+    &payload.environnements.to_owned().unwrap_or_default(),
+  )?;
+  let strategy = payload.update_strategy.to_owned().unwrap_or_default();
+  utils::cargo::validate_update_strategy(&strategy)?;
+
+  let _guard = utils::cargo::lock_updating(&key)?;
+  let previous = utils::cargo::gen_snapshot(&key, &pool).await?;
+  let res = async {
+    // Add environement variables
+    let mut env_stream = stream::iter(envs);
+    while let Some((name, value)) = env_stream.next().await {
+      let env = CargoEnvPartial {
+        cargo_key: key.to_owned(),
+        name: name.to_owned(),
+        value: value.to_owned(),
+      };
+      let env_exists = repositories::cargo_env::exist_in_cargo(
         name.to_owned(),
         key.to_owned(),
-        value,
         &pool,
       )
       .await?;
-    } else {
-      // Unless we create it
-      repositories::cargo_env::create(env, &pool).await?;
+      if env_exists {
+        // Update env variable if it exists
+        repositories::cargo_env::patch_for_cargo(
+          name.to_owned(),
+          key.to_owned(),
+          value,
+          &pool,
+        )
+        .await?;
+      } else {
+        // Unless we create it
+        repositories::cargo_env::create(env, &pool).await?;
+      }
     }
+    // Update entity
+    repositories::cargo::update_by_key(nsp, name, payload, &pool).await?;
+    Ok::<(), HttpResponseError>(())
+  }
+  .await;
+  if let Err(err) = res {
+    utils::cargo::restore_snapshot(previous, &pool).await?;
+    return Err(err);
   }
 
-  // Update entity
-  let _ = repositories::cargo::update_by_key(nsp, name, payload, &pool).await;
+  // Update containers, the previous config is restored if it fails
+  utils::cargo::apply_update(
+    &key,
+    previous,
+    &strategy,
+    &daemon_config,
+    &docker_api,
+    &pool,
+  )
+  .await?;
+  let cargo = repositories::cargo::find_by_key(key, &pool).await?;
+  Ok(web::HttpResponse::Accepted().json(&cargo))
+}
+
+/// Stream the logs of every instances of a cargo
+/// Each output is prefixed with the name of his instance
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/{name}/logs",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+    ("follow" = Option<bool>, Query, description = "Keep the stream open to receive new logs"),
+    ("since" = Option<i64>, Query, description = "Only logs since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only logs until this unix timestamp"),
+    ("tail" = Option<String>, Query, description = "Number of lines from the end of the logs or all"),
+    ("timestamps" = Option<bool>, Query, description = "Add timestamps to every log line"),
+  ),
+  responses(
+    (status = 200, description = "Stream of the logs of the cargo instances", content_type = "nanocl/streaming-v1", body = CargoInstanceOutput),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/{name}/logs")]
+async fn logs_cargo_by_name(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<CargoInstanceLogQuery>,
+  pool: web::types::State<Pool>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let name = name.into_inner();
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name);
+  repositories::cargo::find_by_key(key, &pool).await?;
+  let filter = CargoInstanceFilterQuery {
+    cargo: Some(name),
+    cluster: None,
+    namespace: qs.namespace.to_owned(),
+  };
+  let names = utils::cargo::list_instances(filter, &docker_api)
+    .await?
+    .iter()
+    .map(utils::cargo_instance::get_container_name)
+    .collect::<Vec<String>>();
+  let rx_body = utils::cargo_instance::logs(names, &qs, &docker_api);
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type("nanocl/streaming-v1")
+      .streaming(rx_body),
+  )
+}
+
+/// Get the resource usage of a cargo summed across his running instances
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/{name}/stats",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+    ("cluster" = Option<String>, Query, description = "Only the instances of this cluster"),
+  ),
+  responses(
+    (status = 200, description = "Resource usage of the cargo", body = CargoStats),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/{name}/stats")]
+async fn stats_cargo_by_name(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<CargoInstanceStatsQuery>,
+  pool: web::types::State<Pool>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let name = name.into_inner();
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name);
+  repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
+  let filter = CargoInstanceFilterQuery {
+    cargo: Some(name),
+    cluster: qs.cluster.to_owned(),
+    namespace: qs.namespace.to_owned(),
+  };
+  let names = utils::cargo::list_instances(filter, &docker_api)
+    .await?
+    .iter()
+    .filter(|container| container.state.as_deref() == Some("running"))
+    .map(utils::cargo_instance::get_container_name)
+    .collect::<Vec<String>>();
+  let instances = names
+    .iter()
+    .map(|name| utils::cargo_instance::get_stats(name, &docker_api))
+    .collect::<FuturesUnordered<_>>()
+    .collect::<Vec<_>>()
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, HttpResponseError>>()?;
+  let stats = utils::cargo_instance::sum_stats(&key, instances);
+  Ok(web::HttpResponse::Ok().json(&stats))
+}
+
+/// Scale a cargo to a number of replicas
+/// Only the missing replicas are created or the highest indexed removed
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  request_body = CargoScaleBody,
+  path = "/cargoes/{name}/scale",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+  ),
+  responses(
+    (status = 200, description = "Scaled cargo", body = CargoItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+    (status = 409, description = "Cargo is being updated", body = ApiError),
+    (status = 422, description = "Replicas not valid", body = ApiError),
+  ),
+))]
+#[web::post("/cargoes/{name}/scale")]
+async fn scale_cargo_by_name(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  web::types::Json(payload): web::types::Json<CargoScaleBody>,
+  daemon_config: web::types::State<DaemonConfig>,
+  pool: web::types::State<Pool>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let cargo = utils::cargo::scale_instances(
+    &key,
+    payload.replicas,
+    &daemon_config,
+    &docker_api,
+    &pool,
+  )
+  .await?;
+  utils::cargo::create_revision(&key, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&cargo))
+}
+
+/// List the revisions of a cargo, the latest first
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/{name}/history",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+  ),
+  responses(
+    (status = 200, description = "List of revision", body = [CargoRevisionItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/{name}/history")]
+async fn list_cargo_history(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
+  let items =
+    repositories::cargo_revision::list_by_cargo_key(key, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&items))
+}
 
-  // Update containers
-  utils::cargo::update_instances(
+/// Rollback a cargo to one of his revisions
+/// The rollback is recorded as a new revision once his containers are replaced
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  path = "/cargoes/{name}/rollback",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+    ("revision" = i64, Query, description = "Revision to rollback to"),
+    ("surge" = Option<i64>, Query, description = "Number of new replicas created before the old ones are removed"),
+    ("max_unavailable" = Option<i64>, Query, description = "Number of old replicas stopped before their replacement is ready"),
+    ("ready_timeout" = Option<i64>, Query, description = "Seconds to wait for a new replica to be ready"),
+  ),
+  responses(
+    (status = 202, description = "Cargo rolled back", body = CargoItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo or revision not valid", body = ApiError),
+    (status = 409, description = "Cargo is being updated", body = ApiError),
+    (status = 422, description = "Update strategy not valid", body = ApiError),
+  ),
+))]
+#[web::post("/cargoes/{name}/rollback")]
+async fn rollback_cargo_by_name(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<CargoRollbackQuery>,
+  daemon_config: web::types::State<DaemonConfig>,
+  pool: web::types::State<Pool>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let default_strategy = CargoUpdateStrategy::default();
+  let strategy = CargoUpdateStrategy {
+    surge: qs.surge.unwrap_or(default_strategy.surge),
+    max_unavailable: qs
+      .max_unavailable
+      .unwrap_or(default_strategy.max_unavailable),
+    ready_timeout: qs.ready_timeout.unwrap_or(default_strategy.ready_timeout),
+  };
+  utils::cargo::validate_update_strategy(&strategy)?;
+  repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
+  let revision = repositories::cargo_revision::find_by_revision(
     key.to_owned(),
+    qs.revision,
+    &pool,
+  )
+  .await?;
+
+  let _guard = utils::cargo::lock_updating(&key)?;
+  let previous = utils::cargo::gen_snapshot(&key, &pool).await?;
+  let snapshot = CargoRevisionPartial {
+    cargo_key: key.to_owned(),
+    config: revision.config,
+    replicas: revision.replicas,
+    dns_entry: revision.dns_entry,
+    environnements: revision.environnements,
+  };
+  if let Err(err) = utils::cargo::restore_snapshot(snapshot, &pool).await {
+    utils::cargo::restore_snapshot(previous, &pool).await?;
+    return Err(err);
+  }
+
+  // The current config is restored if the rollback fails
+  utils::cargo::apply_update(
+    &key,
+    previous,
+    &strategy,
     &daemon_config,
     &docker_api,
     &pool,
@@ -266,6 +509,7 @@ async fn patch_cargo_by_name(
 #[web::delete("/cargoes/{name}")]
 async fn delete_cargo_by_name(
   pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
   docker_api: web::types::State<bollard::Docker>,
   name: web::types::Path<String>,
   web::types::Query(qs): web::types::Query<GenericNspQuery>,
@@ -275,12 +519,29 @@ async fn delete_cargo_by_name(
   let key = utils::key::gen_key(&nsp, &name);
 
   repositories::cargo::find_by_key(key.clone(), &pool).await?;
+  let dns_entries =
+    repositories::dns_entry::delete_by_cargo_key(key.to_owned(), &pool).await?;
   repositories::cargo_instance::delete_by_cargo_key(key.to_owned(), &pool)
     .await?;
+  repositories::cargo_revision::delete_by_cargo_key(key.to_owned(), &pool)
+    .await?;
+  repositories::cargo_autoscaler::delete_by_cargo_key(key.to_owned(), &pool)
+    .await?;
+  repositories::cargo_scale_event::delete_by_cargo_key(key.to_owned(), &pool)
+    .await?;
+  utils::proxy_route::delete_cargo_routes(&key, &config, &docker_api, &pool)
+    .await?;
   let res = repositories::cargo::delete_by_key(key.to_owned(), &pool).await?;
   repositories::cargo_env::delete_by_cargo_key(key.to_owned(), &pool).await?;
   utils::cargo::delete_instances(nsp.to_owned(), name.to_owned(), &docker_api)
     .await?;
+  controllers::dns::sync_service_records(&config.state_dir, &docker_api)
+    .await?;
+  if dns_entries.count > 0 {
+    controllers::dns::sync_dns_entries(&config.state_dir, &pool).await?;
+    // Ignore error if we can't restart the dns server
+    let _ = controllers::dns::restart(&docker_api).await;
+  }
   Ok(web::HttpResponse::Ok().json(&res))
 }
 
@@ -290,6 +551,11 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(create_cargo);
   config.service(inspect_cargo_by_name);
   config.service(patch_cargo_by_name);
+  config.service(scale_cargo_by_name);
+  config.service(logs_cargo_by_name);
+  config.service(stats_cargo_by_name);
+  config.service(list_cargo_history);
+  config.service(rollback_cargo_by_name);
   config.service(delete_cargo_by_name);
 }
 
@@ -302,8 +568,11 @@ pub mod tests {
   use ntex::http::StatusCode;
 
   use crate::utils::tests::*;
-  use crate::services::cargo_image;
-  use crate::models::CargoItem;
+  use crate::services::{cargo_image, cluster, state_file};
+  use crate::models::{
+    CargoItem, CargoUpdateStrategy, CargoRevisionItem, CargoHealthCheck,
+    CargoStats,
+  };
 
   /// Test utils to list cargoes
   pub async fn list(srv: &TestServer) -> TestReqRet {
@@ -337,6 +606,45 @@ pub mod tests {
       .await
   }
 
+  /// Test utils to scale a cargo
+  pub async fn scale(
+    srv: &TestServer,
+    name: &str,
+    replicas: i64,
+  ) -> TestReqRet {
+    srv
+      .post(format!("/cargoes/{}/scale", name))
+      .send_json(&CargoScaleBody { replicas })
+      .await
+  }
+
+  /// Test utils to stream the logs of a cargo
+  pub async fn logs(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/cargoes/{}/logs", name)).send().await
+  }
+
+  /// Test utils to get the resource usage of a cargo
+  pub async fn stats(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/cargoes/{}/stats", name)).send().await
+  }
+
+  /// Test utils to list the revisions of a cargo
+  pub async fn history(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/cargoes/{}/history", name)).send().await
+  }
+
+  /// Test utils to rollback a cargo to a revision
+  pub async fn rollback(
+    srv: &TestServer,
+    name: &str,
+    revision: i64,
+  ) -> TestReqRet {
+    srv
+      .post(format!("/cargoes/{}/rollback?revision={}", name, revision))
+      .send()
+      .await
+  }
+
   /// Test utils to delete a cargo
   pub async fn delete(srv: &TestServer, name: &str) -> TestReqRet {
     srv.delete(format!("/cargoes/{}", name)).send().await
@@ -481,6 +789,103 @@ pub mod tests {
     Ok(())
   }
 
+  /// Test a rolling update with a replica that fails to start
+  /// Expect the update to abort and the old replicas to keep running
+  #[ntex::test]
+  async fn patch_rolling_update_abort() -> TestRet {
+    cargo_image::tests::ensure_test_image().await?;
+    let srv = generate_server(ntex_config).await;
+    let state_srv = generate_server(state_file::ntex_config).await;
+    let cluster_srv = generate_server(cluster::ntex_config).await;
+    let state = "clusters:\n\
+  - name: utru\n\
+    networks:\n\
+      - name: utru\n\
+    joins:\n\
+      - cargo: utru\n\
+        network: utru\n\
+cargoes:\n\
+  - name: utru\n\
+    replicas: 2\n\
+    config:\n\
+      Image: nexthat/nanocl-get-started:latest\n";
+    let res = state_file::tests::apply(&state_srv, state).await?;
+    assert!(res.status().is_success(), "Expect state file to be applied");
+
+    let config = bollard::container::Config {
+      image: Some(String::from("nexthat/nanocl-get-started:latest")),
+      cmd: Some(vec![
+        String::from("sh"),
+        String::from("-c"),
+        String::from("exit 1"),
+      ]),
+      ..Default::default()
+    };
+    let cargo = CargoPatchPartial {
+      config: Some(serde_json::to_value(&config)?),
+      update_strategy: Some(CargoUpdateStrategy {
+        max_unavailable: -1,
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+    let resp = patch(&srv, "utru", &cargo).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect negative max_unavailable to be refused"
+    );
+
+    let cargo = CargoPatchPartial {
+      config: Some(serde_json::to_value(&config)?),
+      update_strategy: Some(CargoUpdateStrategy {
+        max_unavailable: 1,
+        ready_timeout: 5,
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+    let resp = patch(&srv, "utru", &cargo).await?;
+    assert!(
+      !resp.status().is_success(),
+      "Expect rolling update with a failing replica to abort"
+    );
+
+    let mut resp = inspect(&srv, "utru").await?;
+    let item: CargoItemWithRelation = resp.json().await?;
+    assert!(
+      item.config.get("Cmd").map_or(true, |cmd| cmd.is_null()),
+      "Expect previous config to be restored, got {:#?}",
+      item.config
+    );
+    let mut resp = history(&srv, "utru").await?;
+    let revisions: Vec<CargoRevisionItem> = resp.json().await?;
+    assert_eq!(
+      revisions.len(),
+      1,
+      "Expect aborted update to not be recorded, got {:#?}",
+      revisions
+    );
+
+    let docker_api = gen_docker_client();
+    for name in ["global-utru-utru", "global-utru-utru-1"] {
+      let container = docker_api.inspect_container(name, None).await?;
+      let is_running = container.state.and_then(|state| state.running);
+      assert_eq!(
+        is_running,
+        Some(true),
+        "Expect old replica {} to keep running",
+        name
+      );
+    }
+
+    let res = cluster::tests::delete(&cluster_srv, "utru").await?;
+    assert!(res.status().is_success(), "Expect cluster to be deleted");
+    let res = delete(&srv, "utru").await?;
+    assert!(res.status().is_success(), "Expect cargo to be deleted");
+    Ok(())
+  }
+
   /// Perform CRUD Test against cargoes
   #[ntex::test]
   async fn crud() -> TestRet {
@@ -571,4 +976,288 @@ pub mod tests {
 
     Ok(())
   }
+
+  /// Test to rollback a cargo to his first revision
+  /// Expect every change to be recorded and the environnements to be restored
+  #[ntex::test]
+  async fn history_and_rollback() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let config = bollard::container::Config {
+      image: Some(String::from("nexthat/nanocl-get-started")),
+      ..Default::default()
+    };
+    let cargo = CargoPartial {
+      name: String::from("test-rollback"),
+      environnements: Some(vec![String::from("TEST=1")]),
+      config: serde_json::to_value(config).unwrap(),
+      ..Default::default()
+    };
+    let resp = create(&srv, &cargo).await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while creating cargo"
+    );
+
+    let cargo = CargoPatchPartial {
+      environnements: Some(vec![String::from("TEST=2")]),
+      replicas: Some(2),
+      ..Default::default()
+    };
+    let resp = patch(&srv, "test-rollback", &cargo).await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while patching cargo"
+    );
+
+    let mut resp = history(&srv, "test-rollback").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while listing cargo history"
+    );
+    let revisions: Vec<CargoRevisionItem> = resp.json().await?;
+    assert_eq!(
+      revisions.len(),
+      2,
+      "Expect 2 revisions, got {:#?}",
+      revisions
+    );
+    assert_eq!(
+      revisions[0].environnements,
+      vec![String::from("TEST=2")],
+      "Expect latest revision to contain the patched environnement"
+    );
+
+    let resp = rollback(&srv, "test-rollback", 99).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Expect 404 not found while rolling back to an unknown revision"
+    );
+
+    let resp = srv
+      .post("/cargoes/test-rollback/rollback?revision=1&surge=-1")
+      .send()
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect rollback with a negative surge to be refused"
+    );
+
+    let mut resp = rollback(&srv, "test-rollback", 1).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::ACCEPTED,
+      "Expect success while rolling back to revision 1"
+    );
+    let cargo: CargoItem = resp.json().await?;
+    assert_eq!(cargo.replicas, 1, "Expect replicas to be restored");
+
+    let mut resp = inspect(&srv, "test-rollback").await?;
+    let cargo: CargoItemWithRelation = resp.json().await?;
+    let test_env = cargo
+      .environnements
+      .expect("Expect environnements to be present")
+      .into_iter()
+      .find(|env| env.name == "TEST")
+      .expect("Expect environnement TEST to exist");
+    assert_eq!(
+      test_env.value, "1",
+      "Expect environnement TEST to be restored"
+    );
+
+    let mut resp = history(&srv, "test-rollback").await?;
+    let revisions: Vec<CargoRevisionItem> = resp.json().await?;
+    assert_eq!(
+      revisions.first().map(|revision| revision.revision),
+      Some(3),
+      "Expect rollback to be recorded as revision 3"
+    );
+
+    let resp = delete(&srv, "test-rollback").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while deleting cargo"
+    );
+    Ok(())
+  }
+
+  /// Test to create a cargo with a health check
+  /// Expect the health check to be stored as the docker HEALTHCHECK
+  #[ntex::test]
+  async fn create_with_healthcheck() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let config = bollard::container::Config {
+      image: Some(String::from("nexthat/nanocl-get-started")),
+      ..Default::default()
+    };
+    let cargo = CargoPartial {
+      name: String::from("test-healthcheck"),
+      config: serde_json::to_value(config).unwrap(),
+      healthcheck: Some(CargoHealthCheck {
+        cmd: vec![String::from("true")],
+        interval: Some(5),
+        timeout: None,
+        retries: Some(3),
+        start_period: None,
+      }),
+      ..Default::default()
+    };
+    let mut resp = create(&srv, &cargo).await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while creating cargo with a health check"
+    );
+    let cargo: CargoItem = resp.json().await?;
+    let config: bollard::container::Config<String> =
+      serde_json::from_value(cargo.config)?;
+    let healthcheck = config
+      .healthcheck
+      .expect("Expect config to contain the health check");
+    assert_eq!(
+      healthcheck.test,
+      Some(vec![String::from("CMD"), String::from("true")]),
+      "Expect health check command to be run with CMD"
+    );
+    assert_eq!(
+      healthcheck.interval,
+      Some(5_000_000_000),
+      "Expect health check interval to be in nanoseconds"
+    );
+
+    let mut resp = inspect(&srv, "test-healthcheck").await?;
+    let cargo: CargoItemWithRelation = resp.json().await?;
+    assert!(
+      cargo.health.is_empty(),
+      "Expect no health for a cargo without containers"
+    );
+
+    let resp = delete(&srv, "test-healthcheck").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while deleting cargo"
+    );
+    Ok(())
+  }
+
+  /// Test to scale a cargo up and down
+  /// Expect the existing replicas to be kept and the highest indexed removed
+  #[ntex::test]
+  async fn scale_up_and_down() -> TestRet {
+    cargo_image::tests::ensure_test_image().await?;
+    let srv = generate_server(ntex_config).await;
+    let state_srv = generate_server(state_file::ntex_config).await;
+    let cluster_srv = generate_server(cluster::ntex_config).await;
+    let state = "clusters:\n\
+  - name: utsc\n\
+    networks:\n\
+      - name: utsc\n\
+    joins:\n\
+      - cargo: utsc\n\
+        network: utsc\n\
+cargoes:\n\
+  - name: utsc\n\
+    config:\n\
+      Image: nexthat/nanocl-get-started:latest\n";
+    let res = state_file::tests::apply(&state_srv, state).await?;
+    assert!(res.status().is_success(), "Expect state file to be applied");
+    let docker_api = gen_docker_client();
+    let first_id = docker_api
+      .inspect_container("global-utsc-utsc", None)
+      .await?
+      .id;
+
+    let resp = scale(&srv, "utsc", -1).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect negative replicas to be refused"
+    );
+
+    let mut resp = scale(&srv, "utsc", 3).await?;
+    assert!(resp.status().is_success(), "Expect cargo to be scaled up");
+    let cargo: CargoItem = resp.json().await?;
+    assert_eq!(cargo.replicas, 3, "Expect replicas to be updated");
+    for name in ["global-utsc-utsc-1", "global-utsc-utsc-2"] {
+      let res = docker_api.inspect_container(name, None).await;
+      assert!(res.is_ok(), "Expect replica {} to be created", name);
+    }
+    let id = docker_api
+      .inspect_container("global-utsc-utsc", None)
+      .await?
+      .id;
+    assert_eq!(id, first_id, "Expect first replica to be kept");
+    let service_records_path =
+      "/var/lib/nanocl/dnsmasq/dnsmasq.d/service_records.conf";
+    let service_records =
+      tokio::fs::read_to_string(service_records_path).await?;
+    for name in [
+      "global-utsc-utsc",
+      "global-utsc-utsc-1",
+      "global-utsc-utsc-2",
+    ] {
+      assert!(
+        service_records
+          .contains(&format!("host-record={}.nanocl.internal,", name)),
+        "Expect a service record for replica {}",
+        name
+      );
+    }
+    let count = service_records
+      .matches("host-record=utsc.utsc.global.nanocl.internal,")
+      .count();
+    assert_eq!(count, 3, "Expect the cargo to resolve every replicas");
+
+    let resp = logs(&srv, "utsc").await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::OK,
+      "Expect logs of every replicas to be streamed"
+    );
+
+    let mut resp = stats(&srv, "utsc").await?;
+    assert_eq!(resp.status(), StatusCode::OK, "Expect stats of the cargo");
+    let cargo_stats: CargoStats = resp.json().await?;
+    assert_eq!(
+      cargo_stats.instances.len(),
+      3,
+      "Expect stats of every replicas to be summed"
+    );
+    let memory_usage = cargo_stats
+      .instances
+      .iter()
+      .map(|instance| instance.memory_usage)
+      .sum::<u64>();
+    assert_eq!(cargo_stats.memory_usage, memory_usage);
+
+    let resp = scale(&srv, "utsc", 1).await?;
+    assert!(resp.status().is_success(), "Expect cargo to be scaled down");
+    for name in ["global-utsc-utsc-1", "global-utsc-utsc-2"] {
+      let res = docker_api.inspect_container(name, None).await;
+      assert!(res.is_err(), "Expect replica {} to be removed", name);
+    }
+    let service_records =
+      tokio::fs::read_to_string(service_records_path).await?;
+    let count = service_records
+      .matches("host-record=utsc.utsc.global.nanocl.internal,")
+      .count();
+    assert_eq!(count, 1, "Expect the removed replicas to not be resolved");
+    let id = docker_api
+      .inspect_container("global-utsc-utsc", None)
+      .await?
+      .id;
+    assert_eq!(id, first_id, "Expect first replica to be kept");
+
+    let res = cluster::tests::delete(&cluster_srv, "utsc").await?;
+    assert!(res.status().is_success(), "Expect cluster to be deleted");
+    let res = delete(&srv, "utsc").await?;
+    assert!(res.status().is_success(), "Expect cargo to be deleted");
+    let service_records =
+      tokio::fs::read_to_string(service_records_path).await?;
+    assert!(
+      !service_records.contains("utsc.utsc.global.nanocl.internal"),
+      "Expect the service records of the cargo to be removed"
+    );
+    Ok(())
+  }
 }
@@ -0,0 +1,269 @@
+//! File to handle cargo autoscaler routes
+use ntex::web;
+
+use crate::{repositories, utils};
+use crate::models::{
+  Pool, GenericNspQuery, CargoAutoscalerPartial, CargoAutoscalerItem,
+};
+
+use crate::errors::HttpResponseError;
+
+/// Create or replace the autoscaler of a cargo
+#[cfg_attr(feature = "dev", utoipa::path(
+  put,
+  request_body = CargoAutoscalerPartial,
+  path = "/cargoes/{name}/autoscaler",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+  ),
+  responses(
+    (status = 200, description = "Autoscaler of the cargo", body = CargoAutoscalerItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+    (status = 422, description = "The autoscaler is not valid", body = ApiError),
+  ),
+))]
+#[web::put("/cargoes/{name}/autoscaler")]
+async fn put_cargo_autoscaler(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  web::types::Json(payload): web::types::Json<CargoAutoscalerPartial>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  utils::autoscaler::validate(&payload)?;
+  repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
+  let item = CargoAutoscalerItem {
+    cargo_key: key,
+    min_replicas: payload.min_replicas,
+    max_replicas: payload.max_replicas,
+    target_cpu: payload.target_cpu,
+    target_memory: payload.target_memory,
+  };
+  let item = repositories::cargo_autoscaler::upsert(item, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Inspect the autoscaler of a cargo
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/{name}/autoscaler",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+  ),
+  responses(
+    (status = 200, description = "Autoscaler of the cargo", body = CargoAutoscalerItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo has no autoscaler", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/{name}/autoscaler")]
+async fn inspect_cargo_autoscaler(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let item =
+    repositories::cargo_autoscaler::find_by_cargo_key(key, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Delete the autoscaler of a cargo
+/// The replicas of the cargo are left as they are
+#[cfg_attr(feature = "dev", utoipa::path(
+  delete,
+  path = "/cargoes/{name}/autoscaler",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+  ),
+  responses(
+    (status = 200, description = "Generic delete", body = GenericDelete),
+    (status = 400, description = "Generic database error", body = ApiError),
+  ),
+))]
+#[web::delete("/cargoes/{name}/autoscaler")]
+async fn delete_cargo_autoscaler(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let res =
+    repositories::cargo_autoscaler::delete_by_cargo_key(key, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+/// List the last scaling decisions taken for a cargo, the latest first
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/{name}/autoscaler/events",
+  params(
+    ("name" = String, Path, description = "Name of the cargo"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the cargo is stored"),
+  ),
+  responses(
+    (status = 200, description = "List of scale event", body = [CargoScaleEventItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/{name}/autoscaler/events")]
+async fn list_cargo_scale_event(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  repositories::cargo::find_by_key(key.to_owned(), &pool).await?;
+  let items =
+    repositories::cargo_scale_event::list_by_cargo_key(key, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(put_cargo_autoscaler);
+  config.service(inspect_cargo_autoscaler);
+  config.service(delete_cargo_autoscaler);
+  config.service(list_cargo_scale_event);
+}
+
+/// Cargo autoscaler unit tests
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  use ntex::http::StatusCode;
+
+  use crate::utils::tests::*;
+  use crate::services::cargo;
+  use crate::models::{CargoPartial, CargoScaleEventItem};
+
+  /// Test utils to set the autoscaler of a cargo
+  pub async fn put(
+    srv: &TestServer,
+    name: &str,
+    autoscaler: &CargoAutoscalerPartial,
+  ) -> TestReqRet {
+    srv
+      .put(format!("/cargoes/{}/autoscaler", name))
+      .send_json(autoscaler)
+      .await
+  }
+
+  /// Test utils to inspect the autoscaler of a cargo
+  pub async fn inspect(srv: &TestServer, name: &str) -> TestReqRet {
+    srv
+      .get(format!("/cargoes/{}/autoscaler", name))
+      .send()
+      .await
+  }
+
+  /// Test utils to delete the autoscaler of a cargo
+  pub async fn delete(srv: &TestServer, name: &str) -> TestReqRet {
+    srv
+      .delete(format!("/cargoes/{}/autoscaler", name))
+      .send()
+      .await
+  }
+
+  /// Test utils to list the scale events of a cargo
+  pub async fn list_events(srv: &TestServer, name: &str) -> TestReqRet {
+    srv
+      .get(format!("/cargoes/{}/autoscaler/events", name))
+      .send()
+      .await
+  }
+
+  /// Perform CRUD test against the autoscaler of a cargo
+  #[ntex::test]
+  async fn crud() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let cargo_srv = generate_server(cargo::ntex_config).await;
+    let config = bollard::container::Config {
+      image: Some(String::from("nexthat/nanocl-get-started")),
+      ..Default::default()
+    };
+    let cargo = CargoPartial {
+      name: String::from("utas"),
+      config: serde_json::to_value(config).unwrap(),
+      ..Default::default()
+    };
+    let resp = cargo::tests::create(&cargo_srv, &cargo).await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while creating cargo"
+    );
+
+    let autoscaler = CargoAutoscalerPartial {
+      min_replicas: 3,
+      max_replicas: 1,
+      target_cpu: Some(50),
+      target_memory: None,
+    };
+    let resp = put(&srv, "utas", &autoscaler).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect an autoscaler with min above max to be refused"
+    );
+
+    let autoscaler = CargoAutoscalerPartial {
+      min_replicas: 0,
+      max_replicas: 3,
+      target_cpu: Some(50),
+      target_memory: None,
+    };
+    let resp = put(&srv, "utas", &autoscaler).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect an autoscaler with 0 min replicas to be refused"
+    );
+
+    let autoscaler = CargoAutoscalerPartial {
+      min_replicas: 1,
+      max_replicas: 3,
+      target_cpu: Some(50),
+      target_memory: None,
+    };
+    let resp = put(&srv, "utas", &autoscaler).await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while setting the autoscaler"
+    );
+
+    let mut resp = inspect(&srv, "utas").await?;
+    let item: CargoAutoscalerItem = resp.json().await?;
+    assert_eq!(item.max_replicas, 3, "Expect max replicas to be stored");
+
+    let mut resp = list_events(&srv, "utas").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while listing scale events"
+    );
+    let _events: Vec<CargoScaleEventItem> = resp.json().await?;
+
+    let resp = delete(&srv, "utas").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while deleting the autoscaler"
+    );
+    let resp = inspect(&srv, "utas").await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Expect 404 not found after the autoscaler is deleted"
+    );
+
+    let resp = cargo::tests::delete(&cargo_srv, "utas").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while deleting cargo"
+    );
+    Ok(())
+  }
+}
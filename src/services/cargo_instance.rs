@@ -1,8 +1,13 @@
 use ntex::web;
+use ntex::service::fn_factory_with_config;
 
 use crate::utils;
+use crate::utils::metrics::DockerErrorMetric;
 use crate::errors::HttpResponseError;
-use crate::models::{CargoInstanceExecBody, CargoInstanceFilterQuery};
+use crate::models::{
+  CargoInstanceExecBody, CargoInstanceFilterQuery, CargoInstanceLogQuery,
+  CargoInstanceExecResizeBody, CargoInstanceStatsQuery,
+};
 
 /// Endpoint to list existing cargo instances
 #[cfg_attr(feature = "dev", utoipa::path(
@@ -27,6 +32,89 @@ async fn list_cargo_instance(
   Ok(web::HttpResponse::Ok().json(&containers))
 }
 
+/// Endpoint to stream the logs of a cargo instance
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/instances/{name}/logs",
+  params(
+    ("name" = String, Path, description = "Name of the cargo instance"),
+    ("namespace" = Option<String>, Query, description = "Namespace to search in"),
+    ("follow" = Option<bool>, Query, description = "Keep the stream open to receive new logs"),
+    ("since" = Option<i64>, Query, description = "Only logs since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only logs until this unix timestamp"),
+    ("tail" = Option<String>, Query, description = "Number of lines from the end of the logs or all"),
+    ("timestamps" = Option<bool>, Query, description = "Add timestamps to every log line"),
+  ),
+  responses(
+    (status = 200, description = "Stream of the logs of the cargo instance", content_type = "nanocl/streaming-v1", body = CargoInstanceOutput),
+    (status = 404, description = "Cargo instance name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/instances/{name}/logs")]
+async fn logs_cargo_instance(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<CargoInstanceLogQuery>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = CargoInstanceFilterQuery {
+    namespace: qs.namespace.to_owned(),
+    cluster: None,
+    cargo: None,
+  };
+  // Ensure the container is a cargo instance before streaming
+  let container =
+    utils::cargo_instance::find_instance(&name, filter, &docker_api).await?;
+  let name = utils::cargo_instance::get_container_name(&container);
+  let rx_body = utils::cargo_instance::logs(vec![name], &qs, &docker_api);
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type("nanocl/streaming-v1")
+      .streaming(rx_body),
+  )
+}
+
+/// Endpoint to get the resource usage of a cargo instance
+/// The stats are streamed when `stream` is true
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/instances/{name}/stats",
+  params(
+    ("name" = String, Path, description = "Name of the cargo instance"),
+    ("namespace" = Option<String>, Query, description = "Namespace to search in"),
+    ("cluster" = Option<String>, Query, description = "Cluster to search in"),
+    ("stream" = Option<bool>, Query, description = "Keep the stream open to receive new stats"),
+  ),
+  responses(
+    (status = 200, description = "Resource usage of the cargo instance", body = CargoInstanceStats),
+    (status = 404, description = "Cargo instance name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/instances/{name}/stats")]
+async fn stats_cargo_instance(
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<CargoInstanceStatsQuery>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = CargoInstanceFilterQuery {
+    namespace: qs.namespace.to_owned(),
+    cluster: qs.cluster.to_owned(),
+    cargo: None,
+  };
+  let container =
+    utils::cargo_instance::find_instance(&name, filter, &docker_api).await?;
+  let name = utils::cargo_instance::get_container_name(&container);
+  if qs.stream.unwrap_or(false) {
+    let rx_body = utils::cargo_instance::stream_stats(name, &docker_api);
+    return Ok(
+      web::HttpResponse::Ok()
+        .content_type("nanocl/streaming-v1")
+        .streaming(rx_body),
+    );
+  }
+  let stats = utils::cargo_instance::get_stats(&name, &docker_api).await?;
+  Ok(web::HttpResponse::Ok().json(&stats))
+}
+
 /// Endpoint to create a cargo instance command to execute
 #[cfg_attr(feature = "dev", utoipa::path(
   post,
@@ -65,7 +153,7 @@ async fn create_cargo_instance_exec(
     ("id" = String, Path, description = "Exec instance id to start"),
   ),
   responses(
-    (status = 200, description = "Stream of the output of the command", content_type = "nanocl/streaming-v1", body = String),
+    (status = 200, description = "Stream of the output of the command", content_type = "nanocl/streaming-v1", body = CargoInstanceOutput),
     (status = 400, description = "Generic database error", body = ApiError),
   ),
 ))]
@@ -73,16 +161,107 @@ async fn create_cargo_instance_exec(
 async fn start_cargo_instance_exec(
   id: web::types::Path<String>,
   docker_api: web::types::State<bollard::Docker>,
-  // Todo pipe this stream with stdio
-  #[allow(unused_variables)] stream: web::types::Payload,
 ) -> Result<web::HttpResponse, HttpResponseError> {
   utils::cargo_instance::exec_cargo_instance_exec(&id, &docker_api).await
 }
 
+/// Endpoint to resize the tty of a cargo instance command
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  path = "/cargoes/instances/exec/{id}/resize",
+  request_body = CargoInstanceExecResizeBody,
+  params(
+    ("id" = String, Path, description = "Exec instance id to resize"),
+  ),
+  responses(
+    (status = 200, description = "The tty is resized"),
+    (status = 404, description = "Exec instance id not valid", body = ApiError),
+  ),
+))]
+#[web::post("/cargoes/instances/exec/{id}/resize")]
+async fn resize_cargo_instance_exec(
+  id: web::types::Path<String>,
+  web::types::Json(body): web::types::Json<CargoInstanceExecResizeBody>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  utils::cargo_instance::resize_cargo_instance_exec(&id, &body, &docker_api)
+    .await?;
+  Ok(web::HttpResponse::Ok().into())
+}
+
+/// Endpoint to inspect a cargo instance command
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/instances/exec/{id}/inspect",
+  params(
+    ("id" = String, Path, description = "Exec instance id to inspect"),
+  ),
+  responses(
+    (status = 200, description = "State of the command", body = CargoInstanceExecInspect),
+    (status = 404, description = "Exec instance id not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/instances/exec/{id}/inspect")]
+async fn inspect_cargo_instance_exec(
+  id: web::types::Path<String>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let res =
+    utils::cargo_instance::inspect_cargo_instance_exec(&id, &docker_api)
+      .await?;
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+/// Endpoint to attach to a cargo instance command over a websocket
+/// Messages of the client are written to the stdin of the command
+/// and his stdout and stderr are sent back as json text messages
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/cargoes/instances/exec/{id}/ws",
+  params(
+    ("id" = String, Path, description = "Exec instance id to attach"),
+  ),
+  responses(
+    (status = 101, description = "Websocket of the command", body = CargoInstanceOutput),
+    (status = 404, description = "Exec instance id not valid", body = ApiError),
+  ),
+))]
+#[web::get("/cargoes/instances/exec/{id}/ws")]
+async fn attach_cargo_instance_exec(
+  req: web::HttpRequest,
+  id: web::types::Path<String>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, web::Error> {
+  let id = id.into_inner();
+  let docker_api = docker_api.get_ref().to_owned();
+  // Ensure the exec exists before upgrading the connection
+  docker_api
+    .inspect_exec(&id)
+    .await
+    .record_error()
+    .map_err(|err| web::error::Error::new(HttpResponseError::from(err)))?;
+  web::ws::start::<_, _, web::Error>(
+    req,
+    fn_factory_with_config(move |sink| {
+      utils::cargo_instance::exec_ws_service(
+        id.to_owned(),
+        sink,
+        docker_api.to_owned(),
+      )
+    }),
+  )
+  .await
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(list_cargo_instance);
+  config.service(logs_cargo_instance);
+  config.service(stats_cargo_instance);
   config.service(create_cargo_instance_exec);
   config.service(start_cargo_instance_exec);
+  config.service(attach_cargo_instance_exec);
+  config.service(resize_cargo_instance_exec);
+  config.service(inspect_cargo_instance_exec);
 }
 
 /// Cargo instances unit tests
@@ -94,7 +273,14 @@ mod tests {
   use bollard::service::ContainerSummary;
   use ntex::http::StatusCode;
 
+  use ntex::ws;
+  use ntex::util::ByteString;
+
   use crate::utils::tests::*;
+  use crate::models::{
+    CargoInstanceOutput, CargoInstanceOutputKind, CargoInstanceExecInspect,
+    CargoInstanceStats,
+  };
 
   /// Test utils to list cargo instances
   pub async fn list(srv: &TestServer, namespace: Option<String>) -> TestReqRet {
@@ -129,6 +315,218 @@ mod tests {
     Ok(())
   }
 
+  /// Test to get the last logs of the store
+  #[ntex::test]
+  async fn logs_store() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let resp = srv
+      .get("/cargoes/instances/store/logs")
+      .query(&[("tail", "10"), ("namespace", "system")])
+      .expect("Expect to bind cargo instance logs query")
+      .send()
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::OK,
+      "Expect logs of the store to return {}, got {}",
+      StatusCode::OK,
+      resp.status()
+    );
+    let content_type = resp
+      .header("content-type")
+      .expect("Expect response to have a content type header")
+      .to_str()
+      .unwrap();
+    assert_eq!(
+      content_type, "nanocl/streaming-v1",
+      "Expect content type header to be nanocl/streaming-v1 got {}",
+      content_type
+    );
+
+    let resp = srv
+      .get("/cargoes/instances/non-existing-container/logs")
+      .send()
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Expect logs of a non existing instance to return {}, got {}",
+      StatusCode::NOT_FOUND,
+      resp.status()
+    );
+
+    let resp = srv.get("/cargoes/instances/store/logs").send().await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Expect logs of the store outside of his namespace to return {}, got {}",
+      StatusCode::NOT_FOUND,
+      resp.status()
+    );
+    Ok(())
+  }
+
+  /// Test to get the stats of the store
+  /// Expect a container outside of the cargoes to not be found
+  #[ntex::test]
+  async fn stats_store() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let mut resp = srv
+      .get("/cargoes/instances/store/stats")
+      .query(&[("namespace", "system")])
+      .expect("Expect to bind cargo instance stats query")
+      .send()
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::OK,
+      "Expect stats of the store to return {}, got {}",
+      StatusCode::OK,
+      resp.status()
+    );
+    let stats: CargoInstanceStats = resp
+      .json()
+      .await
+      .expect("Expect stats to return a CargoInstanceStats");
+    assert_eq!(stats.name, "store");
+    assert!(stats.memory_usage > 0, "Expect the store to use memory");
+
+    let resp = srv
+      .get("/cargoes/instances/non-existing-container/stats")
+      .send()
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Expect stats of a non existing instance to return {}, got {}",
+      StatusCode::NOT_FOUND,
+      resp.status()
+    );
+    Ok(())
+  }
+
+  /// Test to run cat in the store attached to a websocket
+  /// Expect the input sent to be received back on stdout
+  #[ntex::test]
+  async fn exec_ws_cat_in_store() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let exec = CargoInstanceExecBody {
+      attach_stdin: Some(true),
+      attach_stdout: Some(true),
+      attach_stderr: Some(true),
+      detach_keys: None,
+      tty: Some(false),
+      env: None,
+      cmd: Some(vec![String::from("cat")]),
+      privileged: None,
+      user: None,
+      working_dir: None,
+    };
+    let mut resp = srv
+      .post("/cargoes/instances/store/exec")
+      .send_json(&exec)
+      .await?;
+    assert!(resp.status().is_success(), "Expect exec to be created");
+    let exec: CreateExecResults = resp.json().await?;
+
+    let (io, codec, _) = srv
+      .ws_at(&format!("/cargoes/instances/exec/{}/ws", &exec.id))
+      .await?
+      .into_inner();
+    io.send(ws::Message::Text(ByteString::from("hello\n")), &codec)
+      .await
+      .expect("Expect to send the input of the exec");
+    let frame = io
+      .recv(&codec)
+      .await
+      .expect("Expect to read the websocket")
+      .expect("Expect to receive the output of the exec");
+    let output: CargoInstanceOutput = match frame {
+      ws::Frame::Text(data) => serde_json::from_slice(&data)?,
+      _ => panic!("Expect output to be a text message, got {:?}", frame),
+    };
+    assert_eq!(
+      output.kind,
+      CargoInstanceOutputKind::StdOut,
+      "Expect output to be on stdout"
+    );
+    assert_eq!(output.data, "hello\n", "Expect input to be echoed");
+    io.send(ws::Message::Close(None), &codec)
+      .await
+      .expect("Expect to close the websocket");
+    Ok(())
+  }
+
+  /// Test to inspect a finished command in the store
+  /// Expect his exit code to be available
+  #[ntex::test]
+  async fn exec_inspect_in_store() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let exec = CargoInstanceExecBody {
+      attach_stdin: Some(false),
+      attach_stdout: Some(true),
+      attach_stderr: Some(true),
+      detach_keys: None,
+      tty: Some(false),
+      env: None,
+      cmd: Some(vec![String::from("ls")]),
+      privileged: None,
+      user: None,
+      working_dir: None,
+    };
+    let mut resp = srv
+      .post("/cargoes/instances/store/exec")
+      .send_json(&exec)
+      .await?;
+    assert!(resp.status().is_success(), "Expect exec to be created");
+    let exec: CreateExecResults = resp.json().await?;
+
+    let mut resp = srv
+      .post(format!("/cargoes/instances/exec/{}/start", &exec.id))
+      .send()
+      .await?;
+    assert!(resp.status().is_success(), "Expect exec to be started");
+    // Wait for the command to finish
+    let body = resp.body().await?;
+    let body = String::from_utf8_lossy(&body);
+    let output = body
+      .lines()
+      .nth(1)
+      .expect("Expect the output to be framed with his length");
+    let output: CargoInstanceOutput = serde_json::from_str(output)?;
+    assert_eq!(
+      output.kind,
+      CargoInstanceOutputKind::StdOut,
+      "Expect output of ls to be tagged as stdout"
+    );
+
+    let mut resp = srv
+      .get(format!("/cargoes/instances/exec/{}/inspect", &exec.id))
+      .send()
+      .await?;
+    assert!(resp.status().is_success(), "Expect exec to be inspected");
+    let inspect: CargoInstanceExecInspect = resp.json().await?;
+    assert!(!inspect.running, "Expect exec to be finished");
+    assert_eq!(inspect.exit_code, Some(0), "Expect exec to succeed");
+
+    let resize = CargoInstanceExecResizeBody {
+      width: 80,
+      height: 24,
+    };
+    let resp = srv
+      .post("/cargoes/instances/exec/non-existing-exec/resize")
+      .send_json(&resize)
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Expect resize of a non existing exec to return {}, got {}",
+      StatusCode::NOT_FOUND,
+      resp.status()
+    );
+    Ok(())
+  }
+
   #[ntex::test]
   async fn exec_ls_in_store(srv: &TestServer) -> TestRet {
     let instance_name = "store";
@@ -0,0 +1,220 @@
+//! File to handle certificate routes
+use ntex::web;
+
+use crate::{repositories, utils};
+use crate::models::{Pool, DaemonConfig, CertificatePartial};
+
+use crate::errors::HttpResponseError;
+
+/// List the certificates, the closest to their expiry first
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/certificates",
+  responses(
+    (status = 200, description = "Array of certificates", body = [CertificateItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+  ),
+))]
+#[web::get("/certificates")]
+async fn list_certificate(
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let items = repositories::certificate::list(&pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
+/// Upload a certificate and its private key into the ssl directory of the proxy
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  request_body = CertificatePartial,
+  path = "/certificates",
+  responses(
+    (status = 201, description = "The new certificate", body = CertificateItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 409, description = "A certificate with this name already exist", body = ApiError),
+    (status = 422, description = "The certificate or its key is not valid", body = ApiError),
+  ),
+))]
+#[web::post("/certificates")]
+async fn create_certificate(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  web::types::Json(payload): web::types::Json<CertificatePartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let item = utils::certificate::create(&payload, &config, &pool).await?;
+
+  Ok(web::HttpResponse::Created().json(&item))
+}
+
+/// Inspect a certificate by name
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/certificates/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the certificate"),
+  ),
+  responses(
+    (status = 200, description = "The certificate", body = CertificateItem),
+    (status = 404, description = "Certificate name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/certificates/{name}")]
+async fn inspect_certificate_by_name(
+  pool: web::types::State<Pool>,
+  name: web::types::Path<String>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let item =
+    repositories::certificate::find_by_name(name.into_inner(), &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Delete a certificate by name and remove its files
+/// The certificate is kept while the proxy configuration use it
+#[cfg_attr(feature = "dev", utoipa::path(
+  delete,
+  path = "/certificates/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the certificate"),
+  ),
+  responses(
+    (status = 200, description = "Generic delete", body = GenericDelete),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Certificate name not valid", body = ApiError),
+    (status = 409, description = "The certificate is still used by the proxy", body = ApiError),
+  ),
+))]
+#[web::delete("/certificates/{name}")]
+async fn delete_certificate_by_name(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  name: web::types::Path<String>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let res =
+    utils::certificate::delete(&name.into_inner(), &config, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(list_certificate);
+  config.service(create_certificate);
+  config.service(inspect_certificate_by_name);
+  config.service(delete_certificate_by_name);
+}
+
+/// Certificate unit tests
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  use ntex::http::StatusCode;
+
+  use crate::utils::tests::*;
+  use crate::utils::certificate::tests::{
+    CERTIFICATE, CERTIFICATE_KEY, OTHER_CERTIFICATE_KEY,
+  };
+  use crate::models::{
+    CertificateItem, GenericDelete, ProxyTemplateItem, ProxyTemplateModes,
+  };
+
+  /// Test utils to list certificates
+  pub async fn list(srv: &TestServer) -> TestReqRet {
+    srv.get("/certificates").send().await
+  }
+
+  /// Test utils to upload a certificate
+  pub async fn create(
+    srv: &TestServer,
+    payload: &CertificatePartial,
+  ) -> TestReqRet {
+    srv.post("/certificates").send_json(payload).await
+  }
+
+  /// Test utils to inspect a certificate by name
+  pub async fn inspect(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/certificates/{}", name)).send().await
+  }
+
+  /// Test utils to delete a certificate by name
+  pub async fn delete(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.delete(format!("/certificates/{}", name)).send().await
+  }
+
+  /// Perform CRUD test against a certificate
+  #[ntex::test]
+  async fn crud() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let mut payload = CertificatePartial {
+      name: String::from("utcert"),
+      certificate: String::from(CERTIFICATE),
+      certificate_key: String::from("not a key"),
+    };
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect certificate without private key to be refused"
+    );
+    payload.certificate_key = String::from(OTHER_CERTIFICATE_KEY);
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect certificate with another private key to be refused"
+    );
+    payload.certificate_key = String::from(CERTIFICATE_KEY);
+    let mut resp = create(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let item: CertificateItem = resp.json().await?;
+    assert_eq!(item.sans, ["api.nanocl.io", "www.nanocl.io", "127.0.0.1"]);
+    let file_path = "/var/lib/nanocl/nginx/ssl/certificates/utcert/privkey.pem";
+    let content = tokio::fs::read_to_string(file_path).await?;
+    assert_eq!(content, CERTIFICATE_KEY);
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let metadata = tokio::fs::metadata(file_path).await?;
+      assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let mut resp = list(&srv).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let items: Vec<CertificateItem> = resp.json().await?;
+    assert!(items.iter().any(|item| item.name == "utcert"));
+
+    let mut resp = inspect(&srv, "utcert").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let item: CertificateItem = resp.json().await?;
+    assert_eq!(item.not_after.to_rfc3339(), "2126-09-23T19:45:15+00:00");
+
+    // A proxy template using the certificate prevent its deletion
+    let pool = gen_postgre_pool().await;
+    let template = ProxyTemplateItem {
+      name: String::from("utcert"),
+      mode: ProxyTemplateModes::Http,
+      content: String::from(
+        "ssl_certificate {{certificates.utcert.ssl_certificate}};",
+      ),
+    };
+    repositories::proxy_template::create(template, &pool).await?;
+    let resp = delete(&srv, "utcert").await?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    repositories::proxy_template::delete_by_name(String::from("utcert"), &pool)
+      .await?;
+
+    let mut resp = delete(&srv, "utcert").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let res: GenericDelete = resp.json().await?;
+    assert_eq!(res.count, 1);
+    assert!(
+      tokio::fs::metadata(file_path).await.is_err(),
+      "Expect the certificate files to be removed"
+    );
+    Ok(())
+  }
+}
@@ -8,10 +8,12 @@ use futures::StreamExt;
 use ntex::http::StatusCode;
 use tokio::fs;
 use crate::models::DaemonConfig;
+use crate::utils::metrics::DockerErrorMetric;
 use crate::models::ClusterTemplatePartial;
 use crate::models::DeleteClusterTemplatePath;
+use crate::models::RenderClusterTemplatePath;
 use crate::models::ProxyTemplateModes;
-use crate::{utils, repositories};
+use crate::{utils, controllers, repositories};
 use crate::utils::cluster::JoinCargoOptions;
 use crate::models::{
   Pool, GenericNspQuery, ClusterJoinBody, ClusterPartial,
@@ -104,8 +106,16 @@ async fn delete_cluster_by_name(
     cargo: None,
   };
 
+  let dns_entries =
+    repositories::dns_entry::delete_by_cluster_key(key.to_owned(), &pool)
+      .await?;
   repositories::cargo_instance::delete_by_cluster_key(key.to_owned(), &pool)
     .await?;
+  if dns_entries.count > 0 {
+    controllers::dns::sync_dns_entries(&config.state_dir, &pool).await?;
+    // Ignore error if we can't restart the dns server
+    let _ = controllers::dns::restart(&docker_api).await;
+  }
   let containers = utils::cargo::list_instances(qs, &docker_api).await?;
   let mut stream = stream::iter(containers);
   while let Some(container) = stream.next().await {
@@ -115,8 +125,11 @@ async fn delete_cluster_by_name(
     };
     docker_api
       .remove_container(&container.id.unwrap(), Some(options))
-      .await?;
+      .await
+      .record_error()?;
   }
+  controllers::dns::sync_service_records(&config.state_dir, &docker_api)
+    .await?;
 
   utils::cluster_network::delete_networks(item.to_owned(), &docker_api, &pool)
     .await?;
@@ -202,6 +215,33 @@ async fn inspect_cluster_by_name(
   Ok(web::HttpResponse::Ok().json(&res))
 }
 
+/// Export a cluster as a state file
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/clusters/{name}/export",
+  params(
+    ("name" = String, Path, description = "Name of the cluster"),
+    ("namespace" = Option<String>, Query, description = "Namespace where the cluster is if empty we use 'global' as value"),
+  ),
+  responses(
+    (status = 200, description = "State file of the cluster", body = StateFile),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster name or namespace name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/clusters/{name}/export")]
+async fn export_cluster_by_name(
+  name: web::types::Path<String>,
+  pool: web::types::State<Pool>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  let state =
+    utils::state_file::export_cluster(&nsp, &name.into_inner(), &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&state))
+}
+
 /// Start all cargo inside cluster
 #[cfg_attr(feature = "dev", utoipa::path(
   post,
@@ -249,6 +289,7 @@ async fn start_cluster_by_name(
 #[web::post("/clusters/{name}/join")]
 async fn join_cargo_to_cluster(
   pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
   docker_api: web::types::State<bollard::Docker>,
   name: web::types::Path<String>,
   web::types::Query(qs): web::types::Query<GenericNspQuery>,
@@ -291,8 +332,10 @@ async fn join_cargo_to_cluster(
     cargo,
     network,
     is_creating_relation: true,
+    indexes: None,
   };
-  utils::cluster::join_cargo(&join_cargo_opts, &docker_api, &pool).await?;
+  utils::cluster::join_cargo(&join_cargo_opts, &config, &docker_api, &pool)
+    .await?;
   log::debug!("join success.");
   Ok(web::HttpResponse::Ok().into())
 }
@@ -372,15 +415,58 @@ async fn delete_cluster_template(
   Ok(web::HttpResponse::Ok().into())
 }
 
+/// Render a proxy template of a cluster without writing it
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  path = "/clusters/{cl_name}/proxy/templates/{nt_name}/render",
+  params(
+    ("cl_name" = String, Path, description = "Name of the cluster"),
+    ("nt_name" = String, Path, description = "Name of the proxy template"),
+    ("namespace" = Option<String>, Query, description = "Namespace where the cluster is stored if empty we use 'global' as value"),
+  ),
+  responses(
+    (status = 200, description = "The rendered proxy config", body = String),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster or proxy template name invalid", body = ApiError),
+  ),
+))]
+#[web::post("/clusters/{cl_name}/proxy/templates/{nt_name}/render")]
+async fn render_cluster_template(
+  req_path: web::types::Path<RenderClusterTemplatePath>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &req_path.cl_name);
+  let cluster = repositories::cluster::find_by_key(key, &pool).await?;
+  let config_file = utils::cluster::render_proxy_template(
+    &cluster,
+    &req_path.nt_name,
+    &config,
+    &docker_api,
+    &pool,
+  )
+  .await?;
+
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type("text/plain")
+      .body(config_file),
+  )
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(list_cluster);
   config.service(count_cluster);
   config.service(create_cluster);
   config.service(inspect_cluster_by_name);
+  config.service(export_cluster_by_name);
   config.service(start_cluster_by_name);
   config.service(join_cargo_to_cluster);
   config.service(add_cluster_template);
   config.service(delete_cluster_template);
+  config.service(render_cluster_template);
   config.service(delete_cluster_by_name);
 }
 
@@ -440,6 +526,11 @@ pub mod tests {
     srv.get(format!("/clusters/{}/inspect", name)).send().await
   }
 
+  /// Test utils to export a cluster
+  pub async fn export(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/clusters/{}/export", name)).send().await
+  }
+
   /// Test utils to start a cluster
   pub async fn start(srv: &TestServer, name: &str) -> TestReqRet {
     srv.post(format!("/clusters/{}/start", name)).send().await
@@ -487,6 +578,21 @@ pub mod tests {
       .await
   }
 
+  /// Test utils to render a nginx template of a cluster
+  pub async fn render_template(
+    srv: &TestServer,
+    name: &str,
+    template_name: &str,
+  ) -> TestReqRet {
+    srv
+      .post(format!(
+        "/clusters/{}/proxy/templates/{}/render",
+        name, template_name
+      ))
+      .send()
+      .await
+  }
+
   /// Basic test to list clusters
   #[ntex::test]
   async fn basic_list() -> TestRet {
@@ -627,7 +733,7 @@ pub mod tests {
             proxy_http_version 1.1;\n\
             proxy_set_header x-forwarded-for $proxy_add_x_forwarded_for;\n\
             proxy_set_header host $host;\n\
-            proxy_pass http://{{cargoes.unit-test-nginx-cluster-advenced.target_ip}}:9000;\n\
+            proxy_pass http://{{cargoes.utcj.target_ip}}:9000;\n\
         }\n\
       }\n"
         .to_owned(),
@@ -684,6 +790,39 @@ pub mod tests {
       status
     );
 
+    // Render proxy template
+    let mut res =
+      render_template(&srv, cluster_name, &proxy_template.name).await?;
+    let status = res.status();
+    assert_eq!(
+      status,
+      StatusCode::OK,
+      "Expect render proxy template to return with status {}, got {}",
+      StatusCode::OK,
+      status
+    );
+    let body = res.body().await?;
+    let config_file = String::from_utf8_lossy(&body);
+    assert!(
+      config_file.contains("server_name test.get-started.internal;"),
+      "Expect rendered proxy template to contain the server name"
+    );
+    assert!(
+      !config_file.contains("{{"),
+      "Expect rendered proxy template to have no mustache tag left"
+    );
+
+    // Render a proxy template not used by the cluster
+    let res = render_template(&srv, cluster_name, "utcj-unused").await?;
+    let status = res.status();
+    assert_eq!(
+      status,
+      StatusCode::NOT_FOUND,
+      "Expect render unused proxy template to return with status {}, got {}",
+      StatusCode::NOT_FOUND,
+      status
+    );
+
     // Start
     let res = start(&srv, cluster_name).await?;
     let status = res.status();
@@ -695,6 +834,19 @@ pub mod tests {
       StatusCode::OK,
       status
     );
+    let dns_entry_path = "/var/lib/nanocl/dnsmasq/dnsmasq.d/dns_entry.conf";
+    let dns_entries = fs::read_to_string(dns_entry_path).await?;
+    assert!(
+      dns_entries.contains("address=/.test.get-started.internal/127.0.0.1\n"),
+      "Expect the dns entry of the cargo to be written"
+    );
+    let service_records_path =
+      "/var/lib/nanocl/dnsmasq/dnsmasq.d/service_records.conf";
+    let service_records = fs::read_to_string(service_records_path).await?;
+    assert!(
+      service_records.contains("host-record=utcj.utcj.global.nanocl.internal,"),
+      "Expect the service record of the cargo to be written"
+    );
 
     // Remove proxy template to cluster
     let res = remove_template(&srv, cluster_name, &proxy_template.name).await?;
@@ -718,6 +870,16 @@ pub mod tests {
       StatusCode::OK,
       status
     );
+    let dns_entries = fs::read_to_string(dns_entry_path).await?;
+    assert!(
+      !dns_entries.contains("test.get-started.internal"),
+      "Expect the dns entry of the cargo to be removed"
+    );
+    let service_records = fs::read_to_string(service_records_path).await?;
+    assert!(
+      !service_records.contains("utcj.utcj.global.nanocl.internal"),
+      "Expect the service record of the cargo to be removed"
+    );
 
     // Delete cargo
     let res = cargo::tests::delete(&cargo_srv, &cargo.name).await?;
@@ -0,0 +1,330 @@
+//! File to handle dns record routes
+use ntex::web;
+use ntex::http::StatusCode;
+
+use crate::{controllers, repositories, utils};
+use crate::models::{Pool, DaemonConfig, GenericNspQuery, DnsRecordPartial};
+
+use crate::errors::HttpResponseError;
+
+/// Write the dns records and restart the dns controller to serve them
+async fn sync_dns_records(
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  controllers::dns::sync_dns_records(&config.state_dir, pool).await?;
+  // Ignore error if we can't restart the dns server
+  let _ = controllers::dns::restart(docker_api).await;
+  Ok(())
+}
+
+/// List the dns records of a namespace
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/dns/records",
+  params(
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the records are stored"),
+  ),
+  responses(
+    (status = 200, description = "Array of dns records", body = [DnsRecordItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+  ),
+))]
+#[web::get("/dns/records")]
+async fn list_dns_record(
+  pool: web::types::State<Pool>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  let items = repositories::dns_record::find_by_namespace(nsp, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
+/// Create a dns record and serve it with the dns controller
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  request_body = DnsRecordPartial,
+  path = "/dns/records",
+  params(
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the record will be stored"),
+  ),
+  responses(
+    (status = 201, description = "The new dns record created", body = DnsRecordItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 409, description = "A record with this name or his domain in another namespace already exist", body = ApiError),
+    (status = 422, description = "The record is not valid", body = ApiError),
+  ),
+))]
+#[web::post("/dns/records")]
+async fn create_dns_record(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  web::types::Json(payload): web::types::Json<DnsRecordPartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  utils::dns_record::validate(&payload)?;
+  let key = utils::key::gen_key(&nsp, &payload.name);
+  if repositories::dns_record::find_by_key(key.to_owned(), &pool)
+    .await
+    .is_ok()
+  {
+    return Err(HttpResponseError {
+      msg: format!("dns record {} already exist", &key),
+      status: StatusCode::CONFLICT,
+    });
+  }
+  let item = utils::dns_record::gen_item(&nsp, payload);
+  utils::dns_record::validate_conflicts(&item, &pool).await?;
+  let item = repositories::dns_record::create(item, &pool).await?;
+  sync_dns_records(&config, &docker_api, &pool).await?;
+
+  Ok(web::HttpResponse::Created().json(&item))
+}
+
+/// Inspect a dns record by name
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/dns/records/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the record"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the record is stored"),
+  ),
+  responses(
+    (status = 200, description = "The dns record", body = DnsRecordItem),
+    (status = 404, description = "Record name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/dns/records/{name}")]
+async fn inspect_dns_record_by_name(
+  pool: web::types::State<Pool>,
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let item = repositories::dns_record::find_by_key(key, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Update a dns record by name
+/// The name of the payload is ignored, a record can't be renamed
+#[cfg_attr(feature = "dev", utoipa::path(
+  put,
+  request_body = DnsRecordPartial,
+  path = "/dns/records/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the record"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the record is stored"),
+  ),
+  responses(
+    (status = 200, description = "The updated dns record", body = DnsRecordItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Record name not valid", body = ApiError),
+    (status = 409, description = "His domain is used in another namespace", body = ApiError),
+    (status = 422, description = "The record is not valid", body = ApiError),
+  ),
+))]
+#[web::put("/dns/records/{name}")]
+async fn update_dns_record_by_name(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  web::types::Json(payload): web::types::Json<DnsRecordPartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  let payload = DnsRecordPartial {
+    name: name.into_inner(),
+    ..payload
+  };
+  utils::dns_record::validate(&payload)?;
+  let key = utils::key::gen_key(&nsp, &payload.name);
+  repositories::dns_record::find_by_key(key.to_owned(), &pool).await?;
+  let item = utils::dns_record::gen_item(&nsp, payload);
+  utils::dns_record::validate_conflicts(&item, &pool).await?;
+  let item = repositories::dns_record::update_by_key(key, item, &pool).await?;
+  sync_dns_records(&config, &docker_api, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Delete a dns record by name
+#[cfg_attr(feature = "dev", utoipa::path(
+  delete,
+  path = "/dns/records/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the record"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the record is stored"),
+  ),
+  responses(
+    (status = 200, description = "Generic delete", body = GenericDelete),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Record name not valid", body = ApiError),
+  ),
+))]
+#[web::delete("/dns/records/{name}")]
+async fn delete_dns_record_by_name(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  repositories::dns_record::find_by_key(key.to_owned(), &pool).await?;
+  let res = repositories::dns_record::delete_by_key(key, &pool).await?;
+  sync_dns_records(&config, &docker_api, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(list_dns_record);
+  config.service(create_dns_record);
+  config.service(inspect_dns_record_by_name);
+  config.service(update_dns_record_by_name);
+  config.service(delete_dns_record_by_name);
+}
+
+/// Dns record unit tests
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  use crate::utils::tests::*;
+  use crate::models::{DnsRecordItem, DnsRecordKinds, GenericDelete};
+
+  /// Test utils to list dns records
+  pub async fn list(srv: &TestServer) -> TestReqRet {
+    srv.get("/dns/records").send().await
+  }
+
+  /// Test utils to create a dns record
+  pub async fn create(
+    srv: &TestServer,
+    payload: &DnsRecordPartial,
+  ) -> TestReqRet {
+    srv.post("/dns/records").send_json(payload).await
+  }
+
+  /// Test utils to inspect a dns record by name
+  pub async fn inspect(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/dns/records/{}", name)).send().await
+  }
+
+  /// Test utils to update a dns record by name
+  pub async fn update(
+    srv: &TestServer,
+    name: &str,
+    payload: &DnsRecordPartial,
+  ) -> TestReqRet {
+    srv
+      .put(format!("/dns/records/{}", name))
+      .send_json(payload)
+      .await
+  }
+
+  /// Test utils to delete a dns record by name
+  pub async fn delete(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.delete(format!("/dns/records/{}", name)).send().await
+  }
+
+  /// Perform CRUD test against a dns record
+  #[ntex::test]
+  async fn crud() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+
+    let mut payload = DnsRecordPartial {
+      name: String::from("utdr"),
+      kind: DnsRecordKinds::A,
+      domain: String::from("utdr.nanocl.internal"),
+      value: String::from("::1"),
+      port: None,
+      priority: None,
+      weight: None,
+    };
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect A record with an ipv6 address to be refused"
+    );
+    payload.value = String::from("10.0.0.1");
+    let mut resp = create(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let item: DnsRecordItem = resp.json().await?;
+    assert_eq!(item.key, "global-utdr");
+    let file_path = "/var/lib/nanocl/dnsmasq/dnsmasq.d/dns_records.conf";
+    let content = tokio::fs::read_to_string(file_path).await?;
+    assert!(content.contains("host-record=utdr.nanocl.internal,10.0.0.1\n"));
+
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let other = DnsRecordPartial {
+      name: String::from("utdr-other"),
+      ..payload.to_owned()
+    };
+    let resp = srv
+      .post("/dns/records?namespace=system")
+      .send_json(&other)
+      .await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::CONFLICT,
+      "Expect a domain used in another namespace to be refused"
+    );
+    let cname = DnsRecordPartial {
+      name: String::from("utdr-cname"),
+      kind: DnsRecordKinds::Cname,
+      domain: String::from("www.utdr.nanocl.internal"),
+      value: String::from("unknown.utdr.nanocl.internal"),
+      ..payload.to_owned()
+    };
+    let resp = create(&srv, &cname).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect a CNAME record to an unknown domain to be refused"
+    );
+
+    let mut resp = list(&srv).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let items: Vec<DnsRecordItem> = resp.json().await?;
+    assert!(items.iter().any(|item| item.key == "global-utdr"));
+
+    payload.kind = DnsRecordKinds::Srv;
+    payload.domain = String::from("_http._tcp.utdr.nanocl.internal");
+    payload.value = String::from("utdr.nanocl.internal");
+    payload.port = Some(9000);
+    let mut resp = update(&srv, "utdr", &payload).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let item: DnsRecordItem = resp.json().await?;
+    assert_eq!(item.kind, DnsRecordKinds::Srv);
+
+    let mut resp = inspect(&srv, "utdr").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let item: DnsRecordItem = resp.json().await?;
+    assert_eq!(item.port, Some(9000));
+    let content = tokio::fs::read_to_string(file_path).await?;
+    assert!(content.contains(
+      "srv-host=_http._tcp.utdr.nanocl.internal,utdr.nanocl.internal,9000,0,0\n"
+    ));
+
+    let mut resp = delete(&srv, "utdr").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let res: GenericDelete = resp.json().await?;
+    assert_eq!(res.count, 1);
+    let content = tokio::fs::read_to_string(file_path).await?;
+    assert!(
+      !content.contains("utdr.nanocl.internal"),
+      "Expect the dns record to be removed"
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,123 @@
+//! File to handle dns settings routes
+use ntex::web;
+
+use crate::controllers;
+use crate::models::{DaemonConfig, DnsSettingsPartial};
+
+use crate::errors::HttpResponseError;
+
+/// Get the settings of the dns controller
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/dns/settings",
+  responses(
+    (status = 200, description = "The dns settings", body = DnsSettings),
+  ),
+))]
+#[web::get("/dns/settings")]
+async fn get_dns_settings(
+  config: web::types::State<DaemonConfig>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let settings = controllers::dns::get_settings(&config).await?;
+
+  Ok(web::HttpResponse::Ok().json(&settings))
+}
+
+/// Update the settings of the dns controller and restart it
+/// Only the given settings are changed,
+/// the search domains are given to the containers created after
+#[cfg_attr(feature = "dev", utoipa::path(
+  patch,
+  request_body = DnsSettingsPartial,
+  path = "/dns/settings",
+  responses(
+    (status = 200, description = "The updated dns settings", body = DnsSettings),
+    (status = 422, description = "The settings are not valid", body = ApiError),
+  ),
+))]
+#[web::patch("/dns/settings")]
+async fn patch_dns_settings(
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  web::types::Json(payload): web::types::Json<DnsSettingsPartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let settings = controllers::dns::update_settings(&payload, &config).await?;
+  // Ignore error if we can't restart the dns server
+  let _ = controllers::dns::restart(&docker_api).await;
+
+  Ok(web::HttpResponse::Ok().json(&settings))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(get_dns_settings);
+  config.service(patch_dns_settings);
+}
+
+/// Dns settings unit tests
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  use ntex::http::StatusCode;
+
+  use crate::utils::tests::*;
+  use crate::models::DnsSettings;
+
+  /// Test utils to get the dns settings
+  pub async fn get(srv: &TestServer) -> TestReqRet {
+    srv.get("/dns/settings").send().await
+  }
+
+  /// Test utils to patch the dns settings
+  pub async fn patch(
+    srv: &TestServer,
+    payload: &DnsSettingsPartial,
+  ) -> TestReqRet {
+    srv.patch("/dns/settings").send_json(payload).await
+  }
+
+  /// Test to update the cache size and restore it
+  #[ntex::test]
+  async fn patch_settings() -> TestRet {
+    // The dnsmasq config of the daemon must not be changed
+    let state_dir = std::env::temp_dir().join("nanocl-dns-settings-test");
+    let daemon_config = DaemonConfig {
+      state_dir: state_dir.display().to_string(),
+      ..Default::default()
+    };
+    let srv = generate_server_with_config(ntex_config, daemon_config).await;
+
+    let mut resp = get(&srv).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let prev: DnsSettings = resp.json().await?;
+
+    let payload = DnsSettingsPartial {
+      cache_size: Some(20000),
+      ..Default::default()
+    };
+    let resp = patch(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let payload = DnsSettingsPartial {
+      cache_size: Some(prev.cache_size + 1),
+      ..Default::default()
+    };
+    let mut resp = patch(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let settings: DnsSettings = resp.json().await?;
+    assert_eq!(settings.cache_size, prev.cache_size + 1);
+    assert_eq!(settings.servers, prev.servers);
+    let content =
+      tokio::fs::read_to_string(state_dir.join("dnsmasq/dnsmasq.conf")).await?;
+    assert!(content.contains(&format!("cache-size={}\n", prev.cache_size + 1)));
+
+    let payload = DnsSettingsPartial {
+      cache_size: Some(prev.cache_size),
+      ..Default::default()
+    };
+    let resp = patch(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    tokio::fs::remove_dir_all(&state_dir).await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,66 @@
+use ntex::web;
+
+use crate::utils;
+use crate::models::Pool;
+use crate::errors::HttpResponseError;
+
+/// Endpoint to get the metrics of the daemon and his workloads
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/metrics",
+  responses(
+    (status = 200, description = "Metrics in the prometheus text format", content_type = "text/plain", body = String),
+    (status = 400, description = "Generic database error", body = ApiError),
+  ),
+))]
+#[web::get("/metrics")]
+async fn get_metrics(
+  pool: web::types::State<Pool>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let metrics = utils::metrics::gather(&docker_api, &pool).await?;
+  Ok(
+    web::HttpResponse::Ok()
+      .content_type("text/plain; version=0.0.4")
+      .body(metrics),
+  )
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(get_metrics);
+}
+
+#[cfg(test)]
+mod tests {
+  use ntex::http::StatusCode;
+
+  use super::*;
+  use crate::utils::tests::*;
+
+  #[ntex::test]
+  async fn get_metrics() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let mut resp = srv.get("/metrics").send().await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::OK,
+      "Expect metrics to return {}, got {}",
+      StatusCode::OK,
+      resp.status()
+    );
+    let body = resp.body().await?;
+    let metrics = String::from_utf8_lossy(&body);
+    for name in [
+      "nanocl_http_requests_total",
+      "nanocl_docker_errors_total",
+      "nanocl_store_connections",
+      "nanocl_namespaces",
+      "nanocl_clusters{namespace=\"system\"}",
+      "nanocl_cargo_replicas_desired",
+      "nanocl_cargo_replicas_running",
+    ] {
+      assert!(metrics.contains(name), "Expect metrics to contain {}", name);
+    }
+    Ok(())
+  }
+}
@@ -5,10 +5,20 @@ pub mod namespace;
 pub mod cluster;
 // Manage cargo
 pub mod cargo;
+/// Manage cargo autoscaler
+pub mod cargo_autoscaler;
 /// Manage cluster network
 pub mod cluster_network;
 /// Manage nginx template
 pub mod proxy_template;
+/// Manage proxy route
+pub mod proxy_route;
+/// Manage tls certificate
+pub mod certificate;
+/// Manage dns record
+pub mod dns_record;
+/// Manage dns settings
+pub mod dns_setting;
 /// Manage cluster variable
 pub mod cluster_variable;
 /// Manage container_image
@@ -17,3 +27,9 @@ pub mod cargo_image;
 pub mod system;
 /// Mange cargo instance
 pub mod cargo_instance;
+/// Manage state file
+pub mod state_file;
+/// Expose metrics
+pub mod metrics;
+/// Query proxy access logs
+pub mod nginx_log;
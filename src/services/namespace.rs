@@ -1,6 +1,7 @@
 /// Manage nanocl namespace
 use ntex::web;
 
+use crate::utils;
 use crate::repositories::{namespace, self};
 use crate::models::{Pool, NamespacePartial};
 
@@ -95,6 +96,29 @@ async fn inspect_namespace_by_name(
   Ok(web::HttpResponse::Ok().json(&item))
 }
 
+/// Export namespace as a state file
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/namespaces/{name}/export",
+  responses(
+      (status = 200, description = "State file of the namespace", body = StateFile),
+      (status = 404, description = "Namespace not found", body = ApiError),
+  ),
+  params(
+    ("name" = String, Path, description = "name of the namespace"),
+  )
+))]
+#[web::get("/namespaces/{name}/export")]
+async fn export_namespace_by_name(
+  name: web::types::Path<String>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let state =
+    utils::state_file::export_namespace(&name.into_inner(), &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&state))
+}
+
 /// # ntex config
 /// Bind namespace routes to ntex http server
 ///
@@ -112,6 +136,7 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(list_namespace);
   config.service(create_namespace);
   config.service(inspect_namespace_by_name);
+  config.service(export_namespace_by_name);
   config.service(delete_namespace_by_name);
 }
 
@@ -119,7 +144,7 @@ pub fn ntex_config(config: &mut web::ServiceConfig) {
 mod test_namespace {
   use serde_json::json;
 
-  use crate::models::{NamespacePartial, GenericDelete};
+  use crate::models::{NamespacePartial, GenericDelete, StateFile};
   use crate::utils::tests::*;
 
   use super::ntex_config;
@@ -171,6 +196,21 @@ mod test_namespace {
     Ok(())
   }
 
+  async fn test_export(srv: &TestServer) -> TestRet {
+    let mut resp = srv
+      .get(format!(
+        "/namespaces/{name}/export",
+        name = "controller-default"
+      ))
+      .send()
+      .await?;
+
+    assert!(resp.status().is_success());
+    let body = resp.json::<StateFile>().await?;
+    assert_eq!(body.namespace, Some(String::from("controller-default")));
+    Ok(())
+  }
+
   async fn test_delete(srv: &TestServer) -> TestRet {
     let mut resp = srv
       .delete(format!("/namespaces/{name}", name = "controller-default"))
@@ -191,6 +231,7 @@ mod test_namespace {
     test_create(&srv).await?;
     test_inspect_by_id(&srv).await?;
     test_list(&srv).await?;
+    test_export(&srv).await?;
     test_delete(&srv).await?;
     Ok(())
   }
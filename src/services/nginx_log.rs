@@ -0,0 +1,294 @@
+use ntex::web;
+
+use crate::{utils, repositories};
+use crate::models::{Pool, NginxLogQuery, NginxLogGroupBy};
+
+use crate::errors::HttpResponseError;
+
+/// Endpoint to count the proxied http requests by host or uri over time
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/logs/requests",
+  params(
+    ("namespace" = Option<String>, Query, description = "Namespace of the cluster or the cargo"),
+    ("cluster" = Option<String>, Query, description = "Only the requests to the cargoes of this cluster"),
+    ("cargo" = Option<String>, Query, description = "Only the requests to the dns entry of this cargo"),
+    ("host" = Option<String>, Query, description = "Only the requests to this host"),
+    ("since" = Option<i64>, Query, description = "Only the requests since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only the requests until this unix timestamp"),
+    ("interval" = Option<i64>, Query, description = "Size of the time buckets in seconds"),
+    ("group_by" = Option<NginxLogGroupBy>, Query, description = "Count the requests by host or by host and uri"),
+  ),
+  responses(
+    (status = 200, description = "Number of requests by time bucket", body = [NginxLogRequestCount]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster or cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/logs/requests")]
+async fn count_nginx_log_requests(
+  web::types::Query(qs): web::types::Query<NginxLogQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = utils::nginx_log::gen_filter(&qs, &pool).await?;
+  let requests = repositories::nginx_log::list_requests(filter, &pool).await?;
+  let counts = utils::nginx_log::count_requests(
+    requests,
+    utils::nginx_log::get_interval(&qs),
+    qs.group_by.unwrap_or(NginxLogGroupBy::Host),
+  );
+  Ok(web::HttpResponse::Ok().json(&counts))
+}
+
+/// Endpoint to count the proxied http requests by status code
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/logs/status",
+  params(
+    ("namespace" = Option<String>, Query, description = "Namespace of the cluster or the cargo"),
+    ("cluster" = Option<String>, Query, description = "Only the requests to the cargoes of this cluster"),
+    ("cargo" = Option<String>, Query, description = "Only the requests to the dns entry of this cargo"),
+    ("host" = Option<String>, Query, description = "Only the requests to this host"),
+    ("since" = Option<i64>, Query, description = "Only the requests since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only the requests until this unix timestamp"),
+  ),
+  responses(
+    (status = 200, description = "Number of requests by status code", body = [NginxLogStatusCount]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster or cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/logs/status")]
+async fn count_nginx_log_status(
+  web::types::Query(qs): web::types::Query<NginxLogQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = utils::nginx_log::gen_filter(&qs, &pool).await?;
+  let counts = repositories::nginx_log::count_by_status(filter, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&counts))
+}
+
+/// Endpoint to get the percentiles of the request time of the proxied http requests
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/logs/latency",
+  params(
+    ("namespace" = Option<String>, Query, description = "Namespace of the cluster or the cargo"),
+    ("cluster" = Option<String>, Query, description = "Only the requests to the cargoes of this cluster"),
+    ("cargo" = Option<String>, Query, description = "Only the requests to the dns entry of this cargo"),
+    ("host" = Option<String>, Query, description = "Only the requests to this host"),
+    ("since" = Option<i64>, Query, description = "Only the requests since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only the requests until this unix timestamp"),
+  ),
+  responses(
+    (status = 200, description = "Percentiles of the request time", body = NginxLogLatency),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster or cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/logs/latency")]
+async fn get_nginx_log_latency(
+  web::types::Query(qs): web::types::Query<NginxLogQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = utils::nginx_log::gen_filter(&qs, &pool).await?;
+  let times =
+    repositories::nginx_log::list_request_times(filter, &pool).await?;
+  let latency = utils::nginx_log::compute_latency(&times);
+  Ok(web::HttpResponse::Ok().json(&latency))
+}
+
+/// Endpoint to list the user agents sending the most proxied http requests
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/logs/user_agents",
+  params(
+    ("namespace" = Option<String>, Query, description = "Namespace of the cluster or the cargo"),
+    ("cluster" = Option<String>, Query, description = "Only the requests to the cargoes of this cluster"),
+    ("cargo" = Option<String>, Query, description = "Only the requests to the dns entry of this cargo"),
+    ("host" = Option<String>, Query, description = "Only the requests to this host"),
+    ("since" = Option<i64>, Query, description = "Only the requests since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only the requests until this unix timestamp"),
+    ("limit" = Option<i64>, Query, description = "Maximum number of user agents"),
+  ),
+  responses(
+    (status = 200, description = "Number of requests by user agent", body = [NginxLogTopItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster or cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/logs/user_agents")]
+async fn list_nginx_log_user_agents(
+  web::types::Query(qs): web::types::Query<NginxLogQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = utils::nginx_log::gen_filter(&qs, &pool).await?;
+  let limit = utils::nginx_log::get_limit(&qs);
+  let items =
+    repositories::nginx_log::top_user_agents(filter, limit, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
+/// Endpoint to list the remote addresses sending the most proxied http requests
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/logs/remote_addrs",
+  params(
+    ("namespace" = Option<String>, Query, description = "Namespace of the cluster or the cargo"),
+    ("cluster" = Option<String>, Query, description = "Only the requests to the cargoes of this cluster"),
+    ("cargo" = Option<String>, Query, description = "Only the requests to the dns entry of this cargo"),
+    ("host" = Option<String>, Query, description = "Only the requests to this host"),
+    ("since" = Option<i64>, Query, description = "Only the requests since this unix timestamp"),
+    ("until" = Option<i64>, Query, description = "Only the requests until this unix timestamp"),
+    ("limit" = Option<i64>, Query, description = "Maximum number of remote addresses"),
+  ),
+  responses(
+    (status = 200, description = "Number of requests by remote address", body = [NginxLogTopItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cluster or cargo name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/logs/remote_addrs")]
+async fn list_nginx_log_remote_addrs(
+  web::types::Query(qs): web::types::Query<NginxLogQuery>,
+  pool: web::types::State<Pool>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let filter = utils::nginx_log::gen_filter(&qs, &pool).await?;
+  let limit = utils::nginx_log::get_limit(&qs);
+  let items =
+    repositories::nginx_log::top_remote_addrs(filter, limit, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(count_nginx_log_requests);
+  config.service(count_nginx_log_status);
+  config.service(get_nginx_log_latency);
+  config.service(list_nginx_log_user_agents);
+  config.service(list_nginx_log_remote_addrs);
+}
+
+#[cfg(test)]
+mod tests {
+  use ntex::http::StatusCode;
+
+  use super::*;
+  use crate::utils::tests::*;
+  use crate::models::{
+    NginxLogRequestCount, NginxLogStatusCount, NginxLogLatency, NginxLogTopItem,
+  };
+
+  /// Host of the requests stored by the tests
+  const HOST: &str = "analytics.test.internal";
+
+  /// Store requests in the year 2000 so they don't mix with the proxy ones
+  async fn store_requests(pool: &Pool) {
+    let mut items = Vec::new();
+    for (index, status) in [200, 200, 200, 404].iter().enumerate() {
+      let line = serde_json::json!({
+        "date_gmt": format!("2000-01-01T00:0{}:00+00:00", index),
+        "uri": "/",
+        "host": HOST,
+        "remote_addr": format!("10.0.0.{}", index % 2),
+        "realip_remote_addr": "10.0.0.1",
+        "server_protocol": "HTTP/1.1",
+        "request_method": "GET",
+        "status": status.to_string(),
+        "request_time": format!("0.{}", index + 1),
+        "body_bytes_sent": "0",
+        "http_user_agent": "curl/7.81.0",
+      });
+      let item = utils::nginx_log::parse_line(&line.to_string())
+        .expect("Expect a valid nginx log");
+      items.push(item);
+    }
+    repositories::nginx_log::create_many(items, pool)
+      .await
+      .expect("Expect nginx logs to be stored");
+  }
+
+  #[ntex::test]
+  async fn analytics() -> TestRet {
+    let pool = gen_postgre_pool().await;
+    store_requests(&pool).await;
+    let srv = generate_server(ntex_config).await;
+    let query = [
+      ("host", HOST),
+      ("since", "946684800"),
+      ("until", "946688400"),
+    ];
+
+    let mut resp = srv
+      .get("/proxy/logs/requests")
+      .query(&query)
+      .expect("Expect to bind nginx logs query")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let counts: Vec<NginxLogRequestCount> = resp.json().await?;
+    assert_eq!(counts.len(), 1, "Expect requests in a single bucket");
+    assert_eq!(counts[0].count, 4);
+
+    let mut resp = srv
+      .get("/proxy/logs/status")
+      .query(&query)
+      .expect("Expect to bind nginx logs query")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let counts: Vec<NginxLogStatusCount> = resp.json().await?;
+    assert_eq!(counts.len(), 2, "Expect requests with 2 status codes");
+    assert_eq!((counts[0].status, counts[0].count), (200, 3));
+    assert_eq!((counts[1].status, counts[1].count), (404, 1));
+
+    let mut resp = srv
+      .get("/proxy/logs/latency")
+      .query(&query)
+      .expect("Expect to bind nginx logs query")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let latency: NginxLogLatency = resp.json().await?;
+    assert_eq!(latency.count, 4);
+    assert_eq!(latency.p50, 0.2);
+    assert_eq!(latency.p99, 0.4);
+
+    let mut resp = srv
+      .get("/proxy/logs/user_agents")
+      .query(&query)
+      .expect("Expect to bind nginx logs query")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let items: Vec<NginxLogTopItem> = resp.json().await?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].count, 4);
+
+    let mut resp = srv
+      .get("/proxy/logs/remote_addrs")
+      .query(&query)
+      .expect("Expect to bind nginx logs query")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let items: Vec<NginxLogTopItem> = resp.json().await?;
+    assert_eq!(items.len(), 2);
+
+    let resp = srv
+      .get("/proxy/logs/status")
+      .query(&[("cargo", "non-existing-cargo")])
+      .expect("Expect to bind nginx logs query")
+      .send()
+      .await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let date = chrono::DateTime::parse_from_rfc3339("2000-01-02T00:00:00Z")?;
+    repositories::nginx_log::delete_older_than(
+      date.with_timezone(&chrono::Utc),
+      &pool,
+    )
+    .await
+    .expect("Expect nginx logs to be deleted");
+    Ok(())
+  }
+}
@@ -0,0 +1,358 @@
+//! File to handle proxy route routes
+use ntex::web;
+use ntex::http::StatusCode;
+
+use crate::{repositories, utils};
+use crate::models::{Pool, DaemonConfig, GenericNspQuery, ProxyRoutePartial};
+
+use crate::errors::HttpResponseError;
+
+/// List the proxy routes of a namespace
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/routes",
+  params(
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the routes are stored"),
+  ),
+  responses(
+    (status = 200, description = "Array of proxy routes", body = [ProxyRouteItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/routes")]
+async fn list_proxy_route(
+  pool: web::types::State<Pool>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  let items = repositories::proxy_route::find_by_namespace(nsp, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
+/// Create a proxy route and write its proxy configuration
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  request_body = ProxyRoutePartial,
+  path = "/proxy/routes",
+  params(
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the route will be stored"),
+  ),
+  responses(
+    (status = 201, description = "The new proxy route created", body = ProxyRouteItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Cargo name not valid", body = ApiError),
+    (status = 409, description = "The route conflict with an existing route", body = ApiError),
+    (status = 422, description = "The route is not valid", body = ApiError),
+  ),
+))]
+#[web::post("/proxy/routes")]
+async fn create_proxy_route(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  web::types::Json(payload): web::types::Json<ProxyRoutePartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  utils::proxy_route::validate(&payload)?;
+  let key = utils::key::gen_key(&nsp, &payload.name);
+  if repositories::proxy_route::find_by_key(key.to_owned(), &pool)
+    .await
+    .is_ok()
+  {
+    return Err(HttpResponseError {
+      msg: format!("route {} already exist", &key),
+      status: StatusCode::CONFLICT,
+    });
+  }
+  let cargo_key = utils::key::gen_key(&nsp, &payload.cargo);
+  repositories::cargo::find_by_key(cargo_key.to_owned(), &pool).await?;
+  let item = utils::proxy_route::gen_item(&nsp, &cargo_key, payload);
+  utils::proxy_route::ensure_host_ssl(&item, &pool).await?;
+  let item = repositories::proxy_route::create(item, &pool).await?;
+  if let Err(err) = utils::proxy_route::sync_routes(
+    &[item.to_owned()],
+    &config,
+    &docker_api,
+    &pool,
+  )
+  .await
+  {
+    // The proxy refused the config so the route is not kept
+    repositories::proxy_route::delete_by_key(item.key, &pool).await?;
+    return Err(err);
+  }
+
+  Ok(web::HttpResponse::Created().json(&item))
+}
+
+/// Inspect a proxy route by name
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/routes/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the route"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the route is stored"),
+  ),
+  responses(
+    (status = 200, description = "The proxy route", body = ProxyRouteItem),
+    (status = 404, description = "Route name not valid", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/routes/{name}")]
+async fn inspect_proxy_route_by_name(
+  pool: web::types::State<Pool>,
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let item = repositories::proxy_route::find_by_key(key, &pool).await?;
+
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Update a proxy route by name and rewrite its proxy configuration
+/// The name of the payload is ignored, a route can't be renamed
+#[cfg_attr(feature = "dev", utoipa::path(
+  put,
+  request_body = ProxyRoutePartial,
+  path = "/proxy/routes/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the route"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the route is stored"),
+  ),
+  responses(
+    (status = 200, description = "The updated proxy route", body = ProxyRouteItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Route or cargo name not valid", body = ApiError),
+    (status = 409, description = "The route conflict with an existing route", body = ApiError),
+    (status = 422, description = "The route is not valid", body = ApiError),
+  ),
+))]
+#[web::put("/proxy/routes/{name}")]
+async fn update_proxy_route_by_name(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+  web::types::Json(payload): web::types::Json<ProxyRoutePartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&qs.namespace);
+  let payload = ProxyRoutePartial {
+    name: name.into_inner(),
+    ..payload
+  };
+  utils::proxy_route::validate(&payload)?;
+  let key = utils::key::gen_key(&nsp, &payload.name);
+  let prev =
+    repositories::proxy_route::find_by_key(key.to_owned(), &pool).await?;
+  let cargo_key = utils::key::gen_key(&nsp, &payload.cargo);
+  repositories::cargo::find_by_key(cargo_key.to_owned(), &pool).await?;
+  let item = utils::proxy_route::gen_item(&nsp, &cargo_key, payload);
+  utils::proxy_route::ensure_host_ssl(&item, &pool).await?;
+  let item = repositories::proxy_route::update_by_key(key, item, &pool).await?;
+  // The previous host or stream port must be cleaned up too
+  if prev.host != item.host || prev.mode != item.mode {
+    utils::proxy_route::delete_route_config(&prev, &config, &docker_api, &pool)
+      .await?;
+  }
+  if let Err(err) = utils::proxy_route::sync_routes(
+    &[item.to_owned()],
+    &config,
+    &docker_api,
+    &pool,
+  )
+  .await
+  {
+    // The proxy refused the config so the previous route is restored
+    repositories::proxy_route::update_by_key(
+      prev.key.to_owned(),
+      prev.to_owned(),
+      &pool,
+    )
+    .await?;
+    utils::proxy_route::sync_routes(&[prev], &config, &docker_api, &pool)
+      .await?;
+    return Err(err);
+  }
+
+  Ok(web::HttpResponse::Ok().json(&item))
+}
+
+/// Delete a proxy route by name and remove it from the proxy configuration
+#[cfg_attr(feature = "dev", utoipa::path(
+  delete,
+  path = "/proxy/routes/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the route"),
+    ("namespace" = Option<String>, Query, description = "Name of the namespace where the route is stored"),
+  ),
+  responses(
+    (status = 200, description = "Generic delete", body = GenericDelete),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Route name not valid", body = ApiError),
+  ),
+))]
+#[web::delete("/proxy/routes/{name}")]
+async fn delete_proxy_route_by_name(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  name: web::types::Path<String>,
+  web::types::Query(qs): web::types::Query<GenericNspQuery>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let key = utils::key::gen_key_from_nsp(&qs.namespace, &name.into_inner());
+  let item = repositories::proxy_route::find_by_key(key, &pool).await?;
+  let res =
+    utils::proxy_route::delete_route(&item, &config, &docker_api, &pool)
+      .await?;
+
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(list_proxy_route);
+  config.service(create_proxy_route);
+  config.service(inspect_proxy_route_by_name);
+  config.service(update_proxy_route_by_name);
+  config.service(delete_proxy_route_by_name);
+}
+
+/// Proxy route unit tests
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  use crate::utils::tests::*;
+  use crate::services::cargo;
+  use crate::models::{
+    CargoPartial, ProxyRouteItem, ProxyTemplateModes, GenericDelete,
+  };
+
+  /// Test utils to list proxy routes
+  pub async fn list(srv: &TestServer) -> TestReqRet {
+    srv.get("/proxy/routes").send().await
+  }
+
+  /// Test utils to create a proxy route
+  pub async fn create(
+    srv: &TestServer,
+    payload: &ProxyRoutePartial,
+  ) -> TestReqRet {
+    srv.post("/proxy/routes").send_json(payload).await
+  }
+
+  /// Test utils to inspect a proxy route by name
+  pub async fn inspect(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.get(format!("/proxy/routes/{}", name)).send().await
+  }
+
+  /// Test utils to update a proxy route by name
+  pub async fn update(
+    srv: &TestServer,
+    name: &str,
+    payload: &ProxyRoutePartial,
+  ) -> TestReqRet {
+    srv
+      .put(format!("/proxy/routes/{}", name))
+      .send_json(payload)
+      .await
+  }
+
+  /// Test utils to delete a proxy route by name
+  pub async fn delete(srv: &TestServer, name: &str) -> TestReqRet {
+    srv.delete(format!("/proxy/routes/{}", name)).send().await
+  }
+
+  /// Perform CRUD test against a route of a cargo
+  #[ntex::test]
+  async fn crud() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let cargo_srv = generate_server(cargo::ntex_config).await;
+    let config = bollard::container::Config {
+      image: Some(String::from("nexthat/nanocl-get-started")),
+      ..Default::default()
+    };
+    let cargo = CargoPartial {
+      name: String::from("utpr"),
+      config: serde_json::to_value(config).unwrap(),
+      ..Default::default()
+    };
+    let resp = cargo::tests::create(&cargo_srv, &cargo).await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while creating cargo"
+    );
+
+    let mut payload = ProxyRoutePartial {
+      name: String::from("utpr"),
+      mode: ProxyTemplateModes::Http,
+      host: None,
+      path: Some(String::from("/api")),
+      cargo: String::from("utpr"),
+      target_port: 9000,
+      stream_port: None,
+      ssl_certificate: None,
+      ssl_certificate_key: None,
+      ssl_redirect: None,
+    };
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(
+      resp.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect http route without host to be refused"
+    );
+    payload.host = Some(String::from("utpr.nanocl.internal"));
+    let mut resp = create(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let item: ProxyRouteItem = resp.json().await?;
+    assert_eq!(item.key, "global-utpr");
+    assert_eq!(item.cargo_key, "global-utpr");
+    let file_path =
+      "/var/lib/nanocl/nginx/sites-enabled/route.utpr.nanocl.internal.conf";
+    let content = tokio::fs::read_to_string(file_path).await?;
+    assert!(content.contains("server_name utpr.nanocl.internal;"));
+    assert!(content.contains("location /api {"));
+    assert!(
+      content.contains("return 503;"),
+      "Expect a cargo without instance to answer 503"
+    );
+
+    let resp = create(&srv, &payload).await?;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let mut resp = list(&srv).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let items: Vec<ProxyRouteItem> = resp.json().await?;
+    assert!(items.iter().any(|item| item.key == "global-utpr"));
+
+    payload.path = Some(String::from("/v1"));
+    let mut resp = update(&srv, "utpr", &payload).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let item: ProxyRouteItem = resp.json().await?;
+    assert_eq!(item.path, "/v1");
+
+    let mut resp = inspect(&srv, "utpr").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let item: ProxyRouteItem = resp.json().await?;
+    assert_eq!(item.path, "/v1");
+
+    let mut resp = delete(&srv, "utpr").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let res: GenericDelete = resp.json().await?;
+    assert_eq!(res.count, 1);
+    assert!(
+      tokio::fs::metadata(file_path).await.is_err(),
+      "Expect the route config to be removed"
+    );
+
+    let resp = cargo::tests::delete(&cargo_srv, "utpr").await?;
+    assert!(
+      resp.status().is_success(),
+      "Expect success while deleting cargo"
+    );
+    Ok(())
+  }
+}
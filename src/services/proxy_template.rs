@@ -1,7 +1,9 @@
 use ntex::web;
 
-use crate::repositories;
-use crate::models::{Pool, ProxyTemplateItem};
+use crate::{utils, repositories};
+use crate::models::{
+  Pool, DaemonConfig, ProxyTemplateItem, ProxyTemplatePatchPartial,
+};
 
 use crate::errors::HttpResponseError;
 
@@ -36,6 +38,7 @@ async fn create_proxy_template(
   web::types::Json(payload): web::types::Json<ProxyTemplateItem>,
 ) -> Result<web::HttpResponse, HttpResponseError> {
   let res = repositories::proxy_template::create(payload, &pool).await?;
+  repositories::proxy_template_revision::create(res.to_owned(), &pool).await?;
 
   Ok(web::HttpResponse::Created().json(&res))
 }
@@ -46,9 +49,13 @@ async fn delete_proxy_template_by_name(
   pool: web::types::State<Pool>,
   name: web::types::Path<String>,
 ) -> Result<web::HttpResponse, HttpResponseError> {
-  let res =
-    repositories::proxy_template::delete_by_name(name.into_inner(), &pool)
-      .await?;
+  let name = name.into_inner();
+  repositories::proxy_template_revision::delete_by_template_name(
+    name.to_owned(),
+    &pool,
+  )
+  .await?;
+  let res = repositories::proxy_template::delete_by_name(name, &pool).await?;
 
   Ok(web::HttpResponse::Ok().json(&res))
 }
@@ -65,11 +72,75 @@ async fn inspect_proxy_template_by_name(
   Ok(web::HttpResponse::Ok().json(&res))
 }
 
+/// Update proxy template by name
+/// Every clusters using the template render it again
+#[cfg_attr(feature = "dev", utoipa::path(
+  patch,
+  request_body = ProxyTemplatePatchPartial,
+  path = "/proxy/templates/{name}",
+  params(
+    ("name" = String, Path, description = "Name of the proxy template"),
+  ),
+  responses(
+    (status = 200, description = "The updated proxy template", body = ProxyTemplateItem),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 404, description = "Proxy template name not valid", body = ApiError),
+    (status = 422, description = "The proxy refused the rendered config", body = ApiError),
+  ),
+))]
+#[web::patch("/proxy/templates/{name}")]
+async fn patch_proxy_template_by_name(
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+  name: web::types::Path<String>,
+  web::types::Json(payload): web::types::Json<ProxyTemplatePatchPartial>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let res = utils::proxy_template::update(
+    &name.into_inner(),
+    payload,
+    &config,
+    &docker_api,
+    &pool,
+  )
+  .await?;
+
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+/// List the revisions of a proxy template
+#[cfg_attr(feature = "dev", utoipa::path(
+  get,
+  path = "/proxy/templates/{name}/revisions",
+  params(
+    ("name" = String, Path, description = "Name of the proxy template"),
+  ),
+  responses(
+    (status = 200, description = "Revisions of the proxy template, the latest first", body = [ProxyTemplateRevisionItem]),
+    (status = 400, description = "Generic database error", body = ApiError),
+  ),
+))]
+#[web::get("/proxy/templates/{name}/revisions")]
+async fn list_proxy_template_revisions(
+  pool: web::types::State<Pool>,
+  name: web::types::Path<String>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let items = repositories::proxy_template_revision::list_by_template_name(
+    name.into_inner(),
+    &pool,
+  )
+  .await?;
+
+  Ok(web::HttpResponse::Ok().json(&items))
+}
+
 pub fn ntex_config(config: &mut web::ServiceConfig) {
   config.service(list_proxy_template);
   config.service(create_proxy_template);
   config.service(delete_proxy_template_by_name);
   config.service(inspect_proxy_template_by_name);
+  config.service(patch_proxy_template_by_name);
+  config.service(list_proxy_template_revisions);
 }
 
 /// Proxy template unit tests
@@ -80,7 +151,9 @@ pub mod tests {
 
   use ntex::http::StatusCode;
 
-  use crate::models::{ProxyTemplateModes, GenericDelete};
+  use crate::models::{
+    ProxyTemplateModes, ProxyTemplateRevisionItem, GenericDelete,
+  };
   use crate::utils::tests::*;
 
   /// Test utils to list proxy template
@@ -101,6 +174,26 @@ pub mod tests {
     srv.get(format!("/proxy/templates/{}", name)).send().await
   }
 
+  /// Test utils to patch proxy template by name
+  pub async fn patch(
+    srv: &TestServer,
+    name: &str,
+    payload: &ProxyTemplatePatchPartial,
+  ) -> TestReqRet {
+    srv
+      .patch(format!("/proxy/templates/{}", name))
+      .send_json(payload)
+      .await
+  }
+
+  /// Test utils to list the revisions of a proxy template
+  pub async fn list_revisions(srv: &TestServer, name: &str) -> TestReqRet {
+    srv
+      .get(format!("/proxy/templates/{}/revisions", name))
+      .send()
+      .await
+  }
+
   /// Test utils to delete proxy template by name
   pub async fn delete(srv: &TestServer, name: &str) -> TestReqRet {
     srv
@@ -171,6 +264,37 @@ pub mod tests {
       payload.name, body.name
     );
 
+    // Patch
+    let patch_payload = ProxyTemplatePatchPartial {
+      mode: None,
+      content: Some(String::from("patched")),
+    };
+    let mut res = patch(&srv, &payload.name, &patch_payload).await?;
+    let status = res.status();
+    assert_eq!(
+      status,
+      StatusCode::OK,
+      "Expected patching a proxy template with status {} got {}",
+      StatusCode::OK,
+      status
+    );
+    let body: ProxyTemplateItem = res
+      .json()
+      .await
+      .expect("Expect to parse a proxy template item");
+    assert_eq!(body.content, "patched");
+    assert_eq!(body.mode, ProxyTemplateModes::Http);
+
+    // List revisions
+    let mut res = list_revisions(&srv, &payload.name).await?;
+    let revisions: Vec<ProxyTemplateRevisionItem> = res
+      .json()
+      .await
+      .expect("Expect to parse proxy template revisions");
+    assert_eq!(revisions.len(), 2, "Expected a revision per change");
+    assert_eq!(revisions[0].revision, 2);
+    assert_eq!(revisions[0].content, "patched");
+
     // Delete
     let mut res = delete(&srv, &payload.name).await?;
     let status = res.status();
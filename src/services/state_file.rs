@@ -0,0 +1,311 @@
+//! File to handle state file routes
+use ntex::web;
+use ntex::util::Bytes;
+
+use crate::utils;
+use crate::models::{Pool, DaemonConfig};
+
+use crate::errors::{HttpResponseError, StateApplyError};
+
+/// Apply a state file describing the desired state of a namespace
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  path = "/apply",
+  request_body = StateFile,
+  responses(
+    (status = 200, description = "Action taken for each resource", body = [StateResourceResult]),
+    (status = 400, description = "Generic database error with the resources applied before it", body = ApiStateApplyError),
+    (status = 404, description = "A referenced resource does not exist", body = ApiStateApplyError),
+    (status = 422, description = "The state file is not valid", body = ApiStateApplyError),
+  ),
+))]
+#[web::post("/apply")]
+async fn apply_state_file(
+  body: Bytes,
+  pool: web::types::State<Pool>,
+  config: web::types::State<DaemonConfig>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, StateApplyError> {
+  let state = utils::state_file::parse(&body)?;
+  let res =
+    utils::state_file::apply(state, &config, &docker_api, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+/// Plan a state file and return what would change without applying it
+#[cfg_attr(feature = "dev", utoipa::path(
+  post,
+  path = "/plan",
+  request_body = StateFile,
+  responses(
+    (status = 200, description = "Action that would be taken for each resource", body = [StateResourceResult]),
+    (status = 400, description = "Generic database error", body = ApiError),
+    (status = 422, description = "The state file is not valid", body = ApiError),
+  ),
+))]
+#[web::post("/plan")]
+async fn plan_state_file(
+  body: Bytes,
+  pool: web::types::State<Pool>,
+  docker_api: web::types::State<bollard::Docker>,
+) -> Result<web::HttpResponse, HttpResponseError> {
+  let state = utils::state_file::parse(&body)?;
+  let res = utils::state_file::plan(&state, &docker_api, &pool).await?;
+  Ok(web::HttpResponse::Ok().json(&res))
+}
+
+pub fn ntex_config(config: &mut web::ServiceConfig) {
+  config.service(apply_state_file);
+  config.service(plan_state_file);
+}
+
+/// State file unit tests
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  use ntex::http::StatusCode;
+
+  use crate::utils::tests::*;
+  use crate::services::{cargo, cargo_image, cluster};
+  use crate::models::{
+    StateFile, StateResourceResult, StateResourceKind, StateAction,
+  };
+
+  /// Test utils to apply a state file
+  pub async fn apply(srv: &TestServer, state: &str) -> TestReqRet {
+    srv
+      .post("/apply")
+      .content_type("application/x-yaml")
+      .send_body(state.to_owned())
+      .await
+  }
+
+  /// Test utils to plan a state file
+  pub async fn plan(srv: &TestServer, state: &str) -> TestReqRet {
+    srv
+      .post("/plan")
+      .content_type("application/x-yaml")
+      .send_body(state.to_owned())
+      .await
+  }
+
+  /// Test to apply an invalid state file
+  #[ntex::test]
+  async fn apply_invalid() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let res = apply(&srv, "clusters: not_a_list").await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::UNPROCESSABLE_ENTITY,
+      "Expect apply of an invalid state file to return {}, got {}",
+      StatusCode::UNPROCESSABLE_ENTITY,
+      res.status()
+    );
+    Ok(())
+  }
+
+  /// Test to plan a state file
+  /// Planning must not create anything
+  #[ntex::test]
+  async fn plan_without_changes() -> TestRet {
+    let srv = generate_server(ntex_config).await;
+    let cluster_srv = generate_server(cluster::ntex_config).await;
+    let state = "clusters:\n\
+  - name: utsfplan\n\
+    variables:\n\
+      - name: pre_domain\n\
+        value: utsfplan.\n";
+
+    let mut res = plan(&srv, state).await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::OK,
+      "Expect plan to return {}, got {}",
+      StatusCode::OK,
+      res.status()
+    );
+    let body: Vec<StateResourceResult> = res.json().await?;
+    assert!(
+      body
+        .iter()
+        .any(|item| item.kind == StateResourceKind::Cluster
+          && item.action == StateAction::Create
+          && item.reason.is_some()),
+      "Expect plan to create cluster with a reason, got {:#?}",
+      body
+    );
+
+    let res = cluster::tests::inspect(&cluster_srv, "utsfplan").await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::NOT_FOUND,
+      "Expect cluster to not exist after plan, got {}",
+      res.status()
+    );
+    Ok(())
+  }
+
+  /// Test to apply the same state file twice
+  /// The second time every resource should be left as is
+  #[ntex::test]
+  async fn apply_twice() -> TestRet {
+    cargo_image::tests::ensure_test_image().await?;
+    let srv = generate_server(ntex_config).await;
+    let cargo_srv = generate_server(cargo::ntex_config).await;
+    let cluster_srv = generate_server(cluster::ntex_config).await;
+    let state = "namespace: global\n\
+clusters:\n\
+  - name: utsf\n\
+    variables:\n\
+      - name: pre_domain\n\
+        value: utsf.\n\
+    networks:\n\
+      - name: utsf\n\
+    joins:\n\
+      - cargo: utsf\n\
+        network: utsf\n\
+cargoes:\n\
+  - name: utsf\n\
+    config:\n\
+      Image: nexthat/nanocl-get-started:latest\n\
+    environnements:\n\
+      - TEST=1\n";
+
+    let mut res = apply(&srv, state).await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::OK,
+      "Expect first apply to return {}, got {}",
+      StatusCode::OK,
+      res.status()
+    );
+    let body: Vec<StateResourceResult> = res.json().await?;
+    assert!(
+      body.iter().any(|item| item.action == StateAction::Create),
+      "Expect first apply to create resources"
+    );
+
+    let mut res = apply(&srv, state).await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::OK,
+      "Expect second apply to return {}, got {}",
+      StatusCode::OK,
+      res.status()
+    );
+    let body: Vec<StateResourceResult> = res.json().await?;
+    assert!(
+      body.iter().all(|item| item.action == StateAction::Leave),
+      "Expect second apply to leave every resource, got {:#?}",
+      body
+    );
+
+    let mut res = cluster::tests::export(&cluster_srv, "utsf").await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::OK,
+      "Expect export of cluster to return {}, got {}",
+      StatusCode::OK,
+      res.status()
+    );
+    let export: StateFile = res.json().await?;
+    let cargo = export
+      .cargoes
+      .iter()
+      .flatten()
+      .find(|cargo| cargo.name == "utsf")
+      .expect("Expect exported state file to contain cargo utsf");
+    assert_eq!(
+      cargo.environnements,
+      Some(vec![String::from("TEST=1")]),
+      "Expect exported cargo to contain his environnements"
+    );
+
+    // Exported state file should be re-importable without changes
+    let export = serde_json::to_string(&export)?;
+    let mut res = plan(&srv, &export).await?;
+    let body: Vec<StateResourceResult> = res.json().await?;
+    assert!(
+      body.iter().all(|item| item.action == StateAction::Leave),
+      "Expect plan of exported state file to leave every resource, got {:#?}",
+      body
+    );
+
+    let res = cluster::tests::delete(&cluster_srv, "utsf").await?;
+    assert!(res.status().is_success(), "Expect cluster to be deleted");
+    let res = cargo::tests::delete(&cargo_srv, "utsf").await?;
+    assert!(res.status().is_success(), "Expect cargo to be deleted");
+    Ok(())
+  }
+
+  /// Test to export a cargo with an environnement value containing `=`
+  /// and apply the exported state file again
+  #[ntex::test]
+  async fn export_apply_round_trip() -> TestRet {
+    cargo_image::tests::ensure_test_image().await?;
+    let srv = generate_server(ntex_config).await;
+    let cargo_srv = generate_server(cargo::ntex_config).await;
+    let cluster_srv = generate_server(cluster::ntex_config).await;
+    let state = "namespace: global\n\
+clusters:\n\
+  - name: utsfenv\n\
+    networks:\n\
+      - name: utsfenv\n\
+    joins:\n\
+      - cargo: utsfenv\n\
+        network: utsfenv\n\
+cargoes:\n\
+  - name: utsfenv\n\
+    config:\n\
+      Image: nexthat/nanocl-get-started:latest\n\
+    environnements:\n\
+      - QUERY=a=1&b=2\n";
+
+    let res = apply(&srv, state).await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::OK,
+      "Expect apply to return {}, got {}",
+      StatusCode::OK,
+      res.status()
+    );
+
+    let mut res = cluster::tests::export(&cluster_srv, "utsfenv").await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    let export: StateFile = res.json().await?;
+    let cargo = export
+      .cargoes
+      .iter()
+      .flatten()
+      .find(|cargo| cargo.name == "utsfenv")
+      .expect("Expect exported state file to contain cargo utsfenv");
+    assert_eq!(
+      cargo.environnements,
+      Some(vec![String::from("QUERY=a=1&b=2")]),
+      "Expect exported environnement to keep the `=` of his value"
+    );
+
+    let export = serde_json::to_string(&export)?;
+    let mut res = apply(&srv, &export).await?;
+    assert_eq!(
+      res.status(),
+      StatusCode::OK,
+      "Expect apply of exported state file to return {}, got {}",
+      StatusCode::OK,
+      res.status()
+    );
+    let body: Vec<StateResourceResult> = res.json().await?;
+    assert!(
+      body.iter().all(|item| item.action == StateAction::Leave),
+      "Expect apply of exported state file to leave every resource, got {:#?}",
+      body
+    );
+
+    let res = cluster::tests::delete(&cluster_srv, "utsfenv").await?;
+    assert!(res.status().is_success(), "Expect cluster to be deleted");
+    let res = cargo::tests::delete(&cargo_srv, "utsfenv").await?;
+    assert!(res.status().is_success(), "Expect cargo to be deleted");
+    Ok(())
+  }
+}
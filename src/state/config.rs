@@ -27,10 +27,35 @@ fn merge_config(args: &Cli, config: &DaemonConfigFile) -> DaemonConfig {
     String::from("/run/docker.sock")
   };
 
+  let nginx_log_retention = args
+    .nginx_log_retention
+    .or(config.nginx_log_retention)
+    .unwrap_or(7);
+
+  let acme_email = args.acme_email.to_owned().or(config.acme_email.to_owned());
+
+  let acme_directory = args
+    .acme_directory
+    .to_owned()
+    .or(config.acme_directory.to_owned())
+    .unwrap_or_else(|| {
+      String::from("https://acme-v02.api.letsencrypt.org/directory")
+    });
+
+  let acme_insecure =
+    args.acme_insecure || config.acme_insecure.unwrap_or(false);
+
+  let dns = config.dns.to_owned().unwrap_or_default();
+
   DaemonConfig {
     hosts,
     state_dir,
     docker_host,
+    nginx_log_retention,
+    acme_email,
+    acme_directory,
+    acme_insecure,
+    dns,
   }
 }
 
@@ -72,6 +97,8 @@ mod tests {
 
   use super::*;
 
+  use crate::models::DnsSettingsPartial;
+
   /// Test merge config
   #[test]
   fn test_merge_config() {
@@ -81,12 +108,24 @@ mod tests {
       docker_host: Some(String::from("/run/docker.sock")),
       config_dir: String::from("/etc/nanocl"),
       init: false,
+      nginx_log_retention: None,
+      acme_email: None,
+      acme_directory: None,
+      acme_insecure: false,
     };
 
     let config = DaemonConfigFile {
       hosts: Some(vec![String::from("unix:///run/nanocl/nanocl.sock")]),
       state_dir: Some(String::from("/var/lib/nanocl")),
       docker_host: Some(String::from("/run/docker.sock")),
+      nginx_log_retention: Some(30),
+      acme_email: Some(String::from("admin@nanocl.internal")),
+      acme_directory: None,
+      acme_insecure: None,
+      dns: Some(DnsSettingsPartial {
+        servers: Some(vec![String::from("10.0.0.53")]),
+        ..Default::default()
+      }),
     };
 
     let merged = merge_config(&args, &config);
@@ -94,6 +133,17 @@ mod tests {
     assert_eq!(merged.hosts, args.hosts.unwrap());
     assert_eq!(merged.state_dir, args.state_dir.unwrap());
     assert_eq!(merged.docker_host, args.docker_host.unwrap());
+    assert_eq!(merged.nginx_log_retention, 30);
+    assert_eq!(
+      merged.acme_email,
+      Some(String::from("admin@nanocl.internal"))
+    );
+    assert_eq!(
+      merged.acme_directory,
+      "https://acme-v02.api.letsencrypt.org/directory"
+    );
+    assert!(!merged.acme_insecure);
+    assert_eq!(merged.dns.servers, Some(vec![String::from("10.0.0.53")]));
   }
 
   /// Test read config file
@@ -161,6 +211,10 @@ mod tests {
       docker_host: Some(String::from("/run/docker.sock")),
       config_dir: String::from("/etc/nanocl"),
       init: false,
+      nginx_log_retention: None,
+      acme_email: None,
+      acme_directory: None,
+      acme_insecure: false,
     };
 
     let config = init(&args).unwrap();
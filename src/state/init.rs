@@ -11,6 +11,7 @@ use bollard::network::{CreateNetworkOptions, InspectNetworkOptions};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
 use crate::cli::Cli;
+use crate::utils::metrics::DockerErrorMetric;
 use crate::{utils, controllers, repositories};
 use crate::models::{
   Pool, NamespacePartial, ClusterPartial, CargoPartial, ClusterNetworkPartial,
@@ -46,7 +47,7 @@ async fn ensure_system_network(docker_api: &Docker) -> Result<(), DaemonError> {
     options,
     ..Default::default()
   };
-  docker_api.create_network(config).await?;
+  docker_api.create_network(config).await.record_error()?;
   Ok(())
 }
 
@@ -198,6 +199,7 @@ async fn register_daemon(arg: &ArgState) -> Result<(), DaemonError> {
     environnements: None,
     replicas: Some(1),
     dns_entry: None,
+    healthcheck: None,
     config: serde_json::to_value(config).map_err(|err| HttpResponseError {
       msg: format!("Unable to serialize config: {}", err),
       status: StatusCode::INTERNAL_SERVER_ERROR,
@@ -258,6 +260,26 @@ pub async fn init(args: &Cli) -> Result<DaemonState, DaemonError> {
     sys_namespace: String::from("system"),
   };
   register_dependencies(&arg_state).await?;
+  // Converge containers to the store in background
+  utils::reconciler::spawn(
+    config.to_owned(),
+    docker_api.to_owned(),
+    pool.to_owned(),
+  );
+  // Scale cargoes with an autoscaler in background
+  utils::autoscaler::spawn(
+    config.to_owned(),
+    docker_api.to_owned(),
+    pool.to_owned(),
+  );
+  // Store the access logs of the proxy in background
+  utils::nginx_log::spawn(
+    config.to_owned(),
+    docker_api.to_owned(),
+    pool.to_owned(),
+  );
+  // Issue and renew the certificates of the domains in background
+  utils::acme::spawn(config.to_owned(), docker_api.to_owned(), pool.to_owned());
   Ok(DaemonState {
     pool,
     config,
@@ -282,6 +304,10 @@ mod tests {
       docker_host: None,
       state_dir: None,
       config_dir: String::from("/etc/nanocl"),
+      nginx_log_retention: None,
+      acme_email: None,
+      acme_directory: None,
+      acme_insecure: false,
     };
 
     // test function init
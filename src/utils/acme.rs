@@ -0,0 +1,287 @@
+//! Issuance and renewal of the certificates of the domains with ACME
+//! Challenges are solved with HTTP-01 by the certbot nginx plugin of the proxy
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+use ntex::rt;
+use ntex::time::{sleep, Seconds};
+use ntex::http::StatusCode;
+
+use crate::{utils, controllers, repositories};
+use crate::errors::HttpResponseError;
+use crate::models::{Pool, DaemonConfig};
+
+/// Interval between two renewals, certbot only renew the certificates close to their expiry
+const RENEW_INTERVAL: Seconds = Seconds(60 * 60 * 12);
+/// Directory of the certificates inside the state directory
+const ACME_DIR: &str = "nginx/ssl/acme";
+/// Directory of the certificates inside the proxy container
+const PROXY_ACME_DIR: &str = "/etc/nginx/ssl/acme";
+
+/// Ensure a domain can be validated with HTTP-01
+/// Wildcards and domains still holding a template can't
+///
+/// ## Arguments
+/// - [domain](str) The domain
+///
+/// ## Return
+/// - [bool](bool) The domain can be validated
+pub fn is_valid_domain(domain: &str) -> bool {
+  domain.contains('.')
+    && !domain.starts_with('.')
+    && !domain.ends_with('.')
+    && domain
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Get the paths of the certificate of a domain inside the proxy container
+/// when the certificate has been issued
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [domain](str) The domain
+///
+/// ## Return
+/// - [Option]((String, String)) The paths of the certificate and its key
+pub fn get_certificate(
+  config: &DaemonConfig,
+  domain: &str,
+) -> Option<(String, String)> {
+  if !is_valid_domain(domain) {
+    return None;
+  }
+  let dir = gen_certificate_dir(config, domain);
+  if !dir.join("fullchain.pem").exists() || !dir.join("privkey.pem").exists() {
+    return None;
+  }
+  Some((
+    format!("{}/{}/fullchain.pem", PROXY_ACME_DIR, domain),
+    format!("{}/{}/privkey.pem", PROXY_ACME_DIR, domain),
+  ))
+}
+
+fn gen_certificate_dir(config: &DaemonConfig, domain: &str) -> PathBuf {
+  Path::new(&config.state_dir).join(ACME_DIR).join(domain)
+}
+
+/// Generate the certbot command issuing or renewing the certificate of a domain
+/// The certificate is kept as is while it's not close to its expiry
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [email](str) Email of the ACME account
+/// - [domain](str) The domain
+///
+/// ## Return
+/// - [Vec](Vec<String>) The command and its arguments
+pub fn gen_certbot_cmd(
+  config: &DaemonConfig,
+  email: &str,
+  domain: &str,
+) -> Vec<String> {
+  let mut cmd = vec![
+    "certbot",
+    "certonly",
+    "--nginx",
+    "--non-interactive",
+    "--agree-tos",
+    "--keep-until-expiring",
+    "--email",
+    email,
+    "--server",
+    config.acme_directory.as_str(),
+    "--cert-name",
+    domain,
+    "-d",
+    domain,
+  ];
+  if config.acme_insecure {
+    cmd.push("--no-verify-ssl");
+  }
+  cmd.into_iter().map(String::from).collect()
+}
+
+/// Copy the certificate of a domain issued by certbot into the ssl directory of the proxy
+/// Returns true when the certificate changed
+async fn copy_certificate(
+  config: &DaemonConfig,
+  domain: &str,
+) -> Result<bool, HttpResponseError> {
+  let live_dir = Path::new(&config.state_dir)
+    .join("nginx/letsencrypt/live")
+    .join(domain);
+  let dir = gen_certificate_dir(config, domain);
+  let gen_error = |err: std::io::Error| HttpResponseError {
+    msg: format!("Unable to copy certificate of {} {}", domain, err),
+    status: StatusCode::INTERNAL_SERVER_ERROR,
+  };
+  tokio::fs::create_dir_all(&dir).await.map_err(gen_error)?;
+  let mut is_changed = false;
+  // The private key is only readable by the owner from its creation
+  for (file_name, mode) in [("fullchain.pem", 0o644), ("privkey.pem", 0o600)] {
+    let content = tokio::fs::read(live_dir.join(file_name))
+      .await
+      .map_err(gen_error)?;
+    let file_path = dir.join(file_name);
+    if tokio::fs::read(&file_path).await.ok().as_ref() == Some(&content) {
+      continue;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .mode(mode)
+      .open(&file_path)
+      .await
+      .map_err(gen_error)?;
+    file.write_all(&content).await.map_err(gen_error)?;
+    file.flush().await.map_err(gen_error)?;
+    is_changed = true;
+  }
+  Ok(is_changed)
+}
+
+/// Issue or renew the certificate of a domain
+///
+/// ## Arguments
+/// - [domain](str) The domain
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+///
+/// ## Return
+/// - [Result](bool) True when the certificate changed
+/// - [Result](HttpResponseError) The certbot error if the certificate can't be issued
+pub async fn issue(
+  domain: &str,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+) -> Result<bool, HttpResponseError> {
+  let email = config.acme_email.as_ref().ok_or(HttpResponseError {
+    msg: String::from("ACME is disabled, no account email is configured"),
+    status: StatusCode::BAD_REQUEST,
+  })?;
+  if !is_valid_domain(domain) {
+    return Err(HttpResponseError {
+      msg: format!("Domain {} can't be validated with HTTP-01", domain),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
+  let cmd = gen_certbot_cmd(config, email, domain);
+  let (exit_code, logs) =
+    controllers::proxy::exec_command(cmd, docker_api).await?;
+  if exit_code != Some(0) {
+    return Err(HttpResponseError {
+      msg: format!(
+        "Unable to issue certificate of {}: {}",
+        domain,
+        logs.trim()
+      ),
+      status: StatusCode::BAD_GATEWAY,
+    });
+  }
+  copy_certificate(config, domain).await
+}
+
+/// List the domains of the cargo dns entries and of the http routes
+async fn list_domains(pool: &Pool) -> Result<Vec<String>, HttpResponseError> {
+  let mut domains = repositories::cargo::list_dns_entries(pool)
+    .await?
+    .into_iter()
+    .filter_map(|dns_entry| {
+      dns_entry
+        .split_once(':')
+        .map(|(_, domain)| domain.to_owned())
+    })
+    .collect::<Vec<String>>();
+  domains.extend(repositories::proxy_route::list_hosts(pool).await?);
+  domains.retain(|domain| is_valid_domain(domain));
+  domains.sort();
+  domains.dedup();
+  Ok(domains)
+}
+
+/// Issue the missing certificates and renew the ones close to their expiry
+/// The routes of a domain with a new certificate are written again,
+/// templates pick it up the next time their cluster is started.
+async fn renew_all(
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let mut is_changed = false;
+  for domain in list_domains(pool).await? {
+    let had_certificate = get_certificate(config, &domain).is_some();
+    match issue(&domain, config, docker_api).await {
+      Err(err) => log::warn!("{}", err.msg),
+      Ok(false) => {}
+      Ok(true) => {
+        log::info!("certificate of {} updated", &domain);
+        is_changed = true;
+        if !had_certificate {
+          let routes =
+            repositories::proxy_route::find_by_host(domain, pool).await?;
+          utils::proxy_route::sync_routes(&routes, config, docker_api, pool)
+            .await?;
+        }
+      }
+    }
+  }
+  if is_changed {
+    // Ignore error if we can't reload the proxy config
+    let _ = controllers::proxy::reload_config(docker_api).await;
+  }
+  Ok(())
+}
+
+/// Spawn the renewal loop of the certificates
+/// Nothing is done when no ACME account email is configured
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub fn spawn(config: DaemonConfig, docker_api: bollard::Docker, pool: Pool) {
+  if config.acme_email.is_none() {
+    log::debug!("ACME disabled, no account email configured");
+    return;
+  }
+  rt::spawn(async move {
+    loop {
+      if let Err(err) = renew_all(&config, &docker_api, &pool).await {
+        log::warn!("unable to renew certificates: {}", err.msg);
+      }
+      sleep(RENEW_INTERVAL).await;
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn is_valid_domain_test() {
+    assert!(is_valid_domain("api.nanocl.io"));
+    assert!(!is_valid_domain("*.nanocl.io"));
+    assert!(!is_valid_domain("{{vars.pre_domain}}nanocl.io"));
+    assert!(!is_valid_domain("localhost"));
+  }
+
+  #[test]
+  fn gen_certbot_cmd_test() {
+    let config = DaemonConfig {
+      acme_directory: String::from("https://localhost:14000/dir"),
+      acme_insecure: true,
+      ..Default::default()
+    };
+    let cmd = gen_certbot_cmd(&config, "admin@nanocl.io", "api.nanocl.io");
+    assert_eq!(cmd[0..3], ["certbot", "certonly", "--nginx"]);
+    assert!(cmd.contains(&String::from("--keep-until-expiring")));
+    assert!(cmd
+      .windows(2)
+      .any(|args| args == ["--server", "https://localhost:14000/dir"]));
+    assert!(cmd.windows(2).any(|args| args == ["-d", "api.nanocl.io"]));
+    assert_eq!(cmd.last().map(String::as_str), Some("--no-verify-ssl"));
+  }
+}
@@ -0,0 +1,434 @@
+//! Background autoscaling of cargoes based on the stats of their containers
+use std::sync::Mutex;
+use std::collections::HashMap;
+use ntex::rt;
+use ntex::http::StatusCode;
+use ntex::time::{sleep, Seconds};
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use bollard::container::StatsOptions;
+
+use crate::{utils, repositories};
+use crate::utils::metrics::DockerErrorMetric;
+use crate::errors::HttpResponseError;
+use crate::models::{
+  Pool, DaemonConfig, CargoAutoscalerPartial, CargoAutoscalerItem,
+  CargoScaleEventPartial, CargoScaleEventItem, CargoInstanceFilterQuery,
+};
+
+/// Interval between two samples of the containers stats
+const AUTOSCALE_INTERVAL: Seconds = Seconds(30);
+
+/// Relative gap between the utilisation and the target ignored
+/// to avoid scaling back and forth around the target
+const TOLERANCE: f64 = 0.1;
+
+/// Number of recommendations kept to stabilise the scale down
+/// a cargo is scaled down to the highest of them
+const STABILIZATION_SAMPLES: usize = 10;
+
+/// Minimum seconds between two scale events of a cargo
+const SCALE_COOLDOWN: i64 = 120;
+
+/// Last recommendations of each cargo, the latest last
+static RECOMMENDATIONS: Mutex<Option<HashMap<String, Vec<i64>>>> =
+  Mutex::new(None);
+
+/// Ensure an autoscaler is valid
+///
+/// ## Arguments
+/// - [autoscaler](CargoAutoscalerPartial) The autoscaler to validate
+///
+/// ## Return
+/// - [Result](()) The autoscaler is valid
+/// - [Result](HttpResponseError) An http response error if the autoscaler is not valid
+pub fn validate(
+  autoscaler: &CargoAutoscalerPartial,
+) -> Result<(), HttpResponseError> {
+  let gen_error = |msg: &str| HttpResponseError {
+    msg: msg.to_owned(),
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  };
+  // A cargo without replicas has no container to sample to scale it up
+  if autoscaler.min_replicas < 1 {
+    return Err(gen_error("min_replicas must be at least 1"));
+  }
+  if autoscaler.max_replicas < autoscaler.min_replicas {
+    return Err(gen_error("max_replicas must be greater than min_replicas"));
+  }
+  if autoscaler.target_cpu.is_none() && autoscaler.target_memory.is_none() {
+    return Err(gen_error("target_cpu or target_memory is required"));
+  }
+  for target in [autoscaler.target_cpu, autoscaler.target_memory]
+    .into_iter()
+    .flatten()
+  {
+    if !(1..=100).contains(&target) {
+      return Err(gen_error("targets must be a percentage between 1 and 100"));
+    }
+  }
+  Ok(())
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+  if values.is_empty() {
+    return None;
+  }
+  Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Sample the average cpu and memory utilisation of the running containers of a cargo
+async fn sample(
+  namespace: &str,
+  cargo_name: &str,
+  docker_api: &bollard::Docker,
+) -> Result<(Option<f64>, Option<f64>), HttpResponseError> {
+  let qs = CargoInstanceFilterQuery {
+    namespace: Some(namespace.to_owned()),
+    cargo: Some(cargo_name.to_owned()),
+    cluster: None,
+  };
+  let containers = utils::cargo::list_instances(qs, docker_api).await?;
+  let samples = containers
+    .into_iter()
+    .filter(|container| container.state.as_deref() == Some("running"))
+    .map(|container| async move {
+      let name = utils::cargo_instance::get_container_name(&container);
+      let options = Some(StatsOptions {
+        stream: false,
+        one_shot: true,
+      });
+      match docker_api.stats(&name, options).next().await {
+        None => Ok(None),
+        Some(stats) => Ok::<_, HttpResponseError>(Some(stats.record_error()?)),
+      }
+    })
+    .collect::<FuturesUnordered<_>>()
+    .collect::<Vec<_>>()
+    .await;
+  let mut cpus = Vec::new();
+  let mut memories = Vec::new();
+  for stats in samples {
+    if let Some(stats) = stats? {
+      cpus.extend(utils::cargo_instance::cpu_percent(&stats));
+      memories.extend(utils::cargo_instance::memory_percent(&stats));
+    }
+  }
+  Ok((average(&cpus), average(&memories)))
+}
+
+/// Compute the replicas of a cargo from the utilisation of his containers
+/// The metric asking for the most replicas wins
+/// and the result is bounded by the min and max replicas of the autoscaler.
+///
+/// ## Arguments
+/// - [autoscaler](CargoAutoscalerItem) The autoscaler of the cargo
+/// - [replicas](i64) The current replicas of the cargo
+/// - [cpu](Option<f64>) The average cpu utilisation
+/// - [memory](Option<f64>) The average memory utilisation
+///
+/// ## Return
+/// - [Option]((i64, String)) The recommended replicas with the reason
+///   or none when there is no sample for the targets of the autoscaler
+pub fn compute_replicas(
+  autoscaler: &CargoAutoscalerItem,
+  replicas: i64,
+  cpu: Option<f64>,
+  memory: Option<f64>,
+) -> Option<(i64, String)> {
+  let metrics = [
+    ("cpu", cpu, autoscaler.target_cpu),
+    ("memory", memory, autoscaler.target_memory),
+  ];
+  let mut desired: Option<(i64, String)> = None;
+  for (metric, usage, target) in metrics {
+    let (usage, target) = match (usage, target) {
+      (Some(usage), Some(target)) => (usage, target as f64),
+      _ => continue,
+    };
+    let ratio = usage / target;
+    let wanted = if (ratio - 1.0).abs() <= TOLERANCE {
+      replicas
+    } else {
+      (replicas as f64 * ratio).ceil() as i64
+    };
+    if desired.as_ref().map_or(true, |(value, _)| wanted > *value) {
+      let reason =
+        format!("{} at {:.1}% for a target of {}%", metric, usage, target);
+      desired = Some((wanted, reason));
+    }
+  }
+  let (wanted, reason) = desired?;
+  let bounded = wanted.clamp(autoscaler.min_replicas, autoscaler.max_replicas);
+  if bounded != wanted {
+    return Some((bounded, format!("{} bounded to {}", reason, bounded)));
+  }
+  Some((wanted, reason))
+}
+
+/// Bring the replicas of a cargo back between the bounds of his autoscaler
+/// A cargo outside of them may have no container to sample,
+/// so it's scaled without waiting for samples.
+///
+/// ## Arguments
+/// - [autoscaler](CargoAutoscalerItem) The autoscaler of the cargo
+/// - [replicas](i64) The current replicas of the cargo
+///
+/// ## Return
+/// - [Option]((i64, String)) The bounded replicas with the reason
+///   or none when the replicas are already between the bounds
+pub fn bound_replicas(
+  autoscaler: &CargoAutoscalerItem,
+  replicas: i64,
+) -> Option<(i64, String)> {
+  let bounded =
+    replicas.clamp(autoscaler.min_replicas, autoscaler.max_replicas);
+  if bounded == replicas {
+    return None;
+  }
+  let reason = format!(
+    "{} replicas out of the bounds {} to {}",
+    replicas, autoscaler.min_replicas, autoscaler.max_replicas
+  );
+  Some((bounded, reason))
+}
+
+/// Add a recommendation to the window of a cargo and stabilise it
+/// A scale up is applied as is,
+/// a scale down is limited to the highest recommendation of the window.
+///
+/// ## Arguments
+/// - [window](Vec<i64>) The last recommendations of the cargo
+/// - [recommendation](i64) The new recommendation
+/// - [replicas](i64) The current replicas of the cargo
+///
+/// ## Return
+/// - [i64](i64) The stabilised replicas
+pub fn stabilize(
+  window: &mut Vec<i64>,
+  recommendation: i64,
+  replicas: i64,
+) -> i64 {
+  window.push(recommendation);
+  if window.len() > STABILIZATION_SAMPLES {
+    window.remove(0);
+  }
+  if recommendation >= replicas {
+    return recommendation;
+  }
+  let highest = window.iter().copied().max().unwrap_or(recommendation);
+  std::cmp::min(highest, replicas)
+}
+
+/// Stabilise a recommendation with the window of his cargo
+fn stabilize_cargo(cargo_key: &str, recommendation: i64, replicas: i64) -> i64 {
+  let mut recommendations = match RECOMMENDATIONS.lock() {
+    Ok(recommendations) => recommendations,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  let window = recommendations
+    .get_or_insert_with(HashMap::new)
+    .entry(cargo_key.to_owned())
+    .or_default();
+  stabilize(window, recommendation, replicas)
+}
+
+/// Check if the last scale event of a cargo is older than the cooldown
+async fn is_cooled_down(
+  cargo_key: &str,
+  pool: &Pool,
+) -> Result<bool, HttpResponseError> {
+  let events = repositories::cargo_scale_event::list_by_cargo_key(
+    cargo_key.to_owned(),
+    pool,
+  )
+  .await?;
+  let is_cooled_down = events.first().map_or(true, |event| {
+    let elapsed = chrono::Utc::now() - event.created_at;
+    elapsed.num_seconds() >= SCALE_COOLDOWN
+  });
+  Ok(is_cooled_down)
+}
+
+/// Sample the containers of a cargo and scale it when needed
+/// The scaling decision is recorded as a scale event
+async fn autoscale_cargo(
+  autoscaler: &CargoAutoscalerItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Option<CargoScaleEventItem>, HttpResponseError> {
+  // Containers of a cargo being updated are managed by the update
+  if utils::cargo::is_updating(&autoscaler.cargo_key) {
+    return Ok(None);
+  }
+  let cargo =
+    repositories::cargo::find_by_key(autoscaler.cargo_key.to_owned(), pool)
+      .await?;
+  let (replicas, reason, cpu, memory) =
+    match bound_replicas(autoscaler, cargo.replicas) {
+      Some((replicas, reason)) => (replicas, reason, None, None),
+      None => {
+        let (cpu, memory) =
+          sample(&cargo.namespace_name, &cargo.name, docker_api).await?;
+        // Without samples there is nothing to base a decision on
+        let (recommendation, reason) =
+          match compute_replicas(autoscaler, cargo.replicas, cpu, memory) {
+            None => return Ok(None),
+            Some(res) => res,
+          };
+        let replicas =
+          stabilize_cargo(&cargo.key, recommendation, cargo.replicas);
+        if replicas == cargo.replicas
+          || !is_cooled_down(&cargo.key, pool).await?
+        {
+          return Ok(None);
+        }
+        let reason = if replicas != recommendation {
+          format!("{} stabilised to {}", reason, replicas)
+        } else {
+          reason
+        };
+        (replicas, reason, cpu, memory)
+      }
+    };
+  utils::cargo::scale_instances(&cargo.key, replicas, config, docker_api, pool)
+    .await?;
+  utils::cargo::create_revision(&cargo.key, pool).await?;
+  let event = CargoScaleEventPartial {
+    cargo_key: cargo.key,
+    from_replicas: cargo.replicas,
+    to_replicas: replicas,
+    cpu,
+    memory,
+    reason,
+  };
+  let event = repositories::cargo_scale_event::create(event, pool).await?;
+  Ok(Some(event))
+}
+
+/// Spawn the autoscaling loop
+/// Every scaling decisions are reported in the logs
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub fn spawn(config: DaemonConfig, docker_api: bollard::Docker, pool: Pool) {
+  rt::spawn(async move {
+    loop {
+      sleep(AUTOSCALE_INTERVAL).await;
+      let autoscalers = match repositories::cargo_autoscaler::list(&pool).await
+      {
+        Err(err) => {
+          log::warn!("unable to list autoscalers: {}", err);
+          continue;
+        }
+        Ok(autoscalers) => autoscalers,
+      };
+      for autoscaler in autoscalers {
+        match autoscale_cargo(&autoscaler, &config, &docker_api, &pool).await {
+          Err(err) => {
+            log::warn!(
+              "unable to autoscale cargo {}: {}",
+              &autoscaler.cargo_key,
+              err
+            )
+          }
+          Ok(None) => {}
+          Ok(Some(event)) => log::info!(
+            "autoscaler scaled cargo {} from {} to {}: {}",
+            event.cargo_key,
+            event.from_replicas,
+            event.to_replicas,
+            event.reason,
+          ),
+        }
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Test to compute the replicas of a cargo from the utilisation
+  /// Expect the replicas to follow the utilisation within the bounds
+  #[test]
+  fn compute_replicas_test() {
+    let autoscaler = CargoAutoscalerItem {
+      cargo_key: String::from("global-utas"),
+      min_replicas: 1,
+      max_replicas: 5,
+      target_cpu: Some(50),
+      target_memory: Some(80),
+    };
+    let res = compute_replicas(&autoscaler, 2, Some(100.0), Some(40.0));
+    assert_eq!(
+      res.map(|(replicas, _)| replicas),
+      Some(4),
+      "Expect replicas to double when cpu is twice the target"
+    );
+    let res = compute_replicas(&autoscaler, 2, Some(52.0), Some(80.0));
+    assert_eq!(
+      res.map(|(replicas, _)| replicas),
+      Some(2),
+      "Expect no scaling within the tolerance"
+    );
+    let res = compute_replicas(&autoscaler, 4, Some(10.0), Some(10.0));
+    assert_eq!(
+      res.map(|(replicas, _)| replicas),
+      Some(1),
+      "Expect replicas to scale down to the min replicas"
+    );
+    let res = compute_replicas(&autoscaler, 4, Some(500.0), None);
+    assert_eq!(
+      res.map(|(replicas, _)| replicas),
+      Some(5),
+      "Expect replicas to scale up to the max replicas"
+    );
+    let res = compute_replicas(&autoscaler, 0, None, None);
+    assert!(res.is_none(), "Expect no recommendation without samples");
+  }
+
+  /// Test to bring the replicas of a cargo between the bounds
+  /// Expect a cargo scaled to 0 to be scaled up without samples
+  #[test]
+  fn bound_replicas_test() {
+    let autoscaler = CargoAutoscalerItem {
+      cargo_key: String::from("global-utas"),
+      min_replicas: 1,
+      max_replicas: 5,
+      target_cpu: Some(50),
+      target_memory: None,
+    };
+    let res = bound_replicas(&autoscaler, 0);
+    assert_eq!(res.map(|(replicas, _)| replicas), Some(1));
+    let res = bound_replicas(&autoscaler, 8);
+    assert_eq!(res.map(|(replicas, _)| replicas), Some(5));
+    assert!(bound_replicas(&autoscaler, 3).is_none());
+  }
+
+  /// Test to stabilise the recommendations of a cargo
+  /// Expect a scale down to wait for the window to only recommend it
+  #[test]
+  fn stabilize_test() {
+    let mut window = Vec::new();
+    assert_eq!(stabilize(&mut window, 4, 2), 4, "Expect scale up as is");
+    assert_eq!(
+      stabilize(&mut window, 1, 4),
+      4,
+      "Expect scale down to keep the highest recommendation"
+    );
+    for _ in 0..STABILIZATION_SAMPLES - 1 {
+      stabilize(&mut window, 2, 4);
+    }
+    assert_eq!(
+      stabilize(&mut window, 1, 4),
+      2,
+      "Expect scale down once the highest recommendation left the window"
+    );
+    assert_eq!(window.len(), STABILIZATION_SAMPLES);
+  }
+}
@@ -1,13 +1,22 @@
+use std::sync::Mutex;
 use ntex::http::StatusCode;
+use ntex::time::{sleep, Seconds};
 use std::collections::HashMap;
-use futures::{StreamExt, stream};
+use futures::StreamExt;
 use futures::stream::FuturesUnordered;
+use bollard::container::StopContainerOptions;
+use bollard::models::{HealthConfig, HealthStatusEnum};
 use bollard::service::{RestartPolicy, RestartPolicyNameEnum};
 
 use crate::{repositories, utils};
+use crate::utils::metrics::DockerErrorMetric;
 use crate::models::{DaemonConfig, CargoInstanceFilterQuery};
 
-use crate::models::{CreateCargoInstanceOpts, Pool};
+use crate::models::{
+  CreateCargoInstanceOpts, Pool, CargoItem, CargoInstanceItem,
+  CargoUpdateStrategy, CargoEnvPartial, CargoRevisionPartial,
+  CargoRevisionItem, CargoHealthCheck, CargoPatchPartial,
+};
 
 use crate::errors::HttpResponseError;
 
@@ -61,11 +70,347 @@ pub async fn list_instances(
     filters,
     ..Default::default()
   });
-  let containers = docker_api.list_containers(options).await?;
+  let containers = docker_api.list_containers(options).await.record_error()?;
 
   Ok(containers)
 }
 
+/// Parse a cargo config
+/// Ensure the config is a valid json object that can be parsed as bollard::container::Config
+/// and that an image is provided
+///
+/// # Arguments
+/// - [config](serde_json::Value) - The cargo config to parse
+///
+/// # Return
+/// - [Result](bollard::container::Config) - The parsed container config
+/// - [Result](HttpResponseError) - An http response error if the config is not valid
+pub fn parse_config(
+  config: &serde_json::Value,
+) -> Result<bollard::container::Config<String>, HttpResponseError> {
+  let config = serde_json::from_value::<bollard::container::Config<String>>(
+    config.to_owned(),
+  )
+  .map_err(|e| HttpResponseError {
+    msg: format!("config is not a valid json: {}", e),
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  })?;
+
+  if config.image.is_none() {
+    return Err(HttpResponseError {
+      msg: "config.image is required".to_owned(),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
+
+  Ok(config)
+}
+
+/// Set the docker HEALTHCHECK of a cargo config from a cargo health check
+///
+/// # Arguments
+/// - [config](serde_json::Value) - The cargo config
+/// - [healthcheck](CargoHealthCheck) - The health check of the cargo
+///
+/// # Return
+/// - [Result](serde_json::Value) - The config with the health check
+/// - [Result](HttpResponseError) - An http response error if the health check is not valid
+pub fn merge_healthcheck(
+  config: &serde_json::Value,
+  healthcheck: &CargoHealthCheck,
+) -> Result<serde_json::Value, HttpResponseError> {
+  if healthcheck.cmd.is_empty() {
+    return Err(HttpResponseError {
+      msg: String::from("healthcheck.cmd is required"),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
+  let to_nanos =
+    |secs: Option<u64>| secs.map(|secs| secs as i64 * 1_000_000_000);
+  let mut test = vec![String::from("CMD")];
+  test.extend(healthcheck.cmd.to_owned());
+  let health_config = HealthConfig {
+    test: Some(test),
+    interval: to_nanos(healthcheck.interval),
+    timeout: to_nanos(healthcheck.timeout),
+    retries: healthcheck.retries,
+    start_period: to_nanos(healthcheck.start_period),
+  };
+  let mut config = config.to_owned();
+  let object = config.as_object_mut().ok_or(HttpResponseError {
+    msg: String::from("config is not a valid json object"),
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  })?;
+  let health_config =
+    serde_json::to_value(health_config).map_err(|err| HttpResponseError {
+      msg: format!("unable to serialize healthcheck: {}", err),
+      status: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+  object.insert(String::from("Healthcheck"), health_config);
+  Ok(config)
+}
+
+/// Parse environnements variables formated as `NAME=VALUE`
+///
+/// # Arguments
+/// - [environnements](Vec<String>) - The environnements variables to parse
+///
+/// # Return
+/// - [Result](Vec<(String, String)>) - List of name and value
+/// - [Result](HttpResponseError) - An http response error if an item is not valid
+pub fn parse_environnements(
+  environnements: &[String],
+) -> Result<Vec<(String, String)>, HttpResponseError> {
+  let mut envs: Vec<(String, String)> = Vec::new();
+  for env in environnements {
+    // Only the first `=` separate the name from the value
+    let (name, value) = env.split_once('=').ok_or(HttpResponseError {
+      msg: format!("env item {} is not a valid format", env),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    })?;
+    if name.is_empty() {
+      return Err(HttpResponseError {
+        msg: format!("env item {} is not a valid format", env),
+        status: StatusCode::UNPROCESSABLE_ENTITY,
+      });
+    }
+    envs.push((name.to_owned(), value.to_owned()));
+  }
+  Ok(envs)
+}
+
+/// Replace the environnements variables of a cargo
+/// Variables are created, updated or deleted to match the given ones
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+/// - [envs](Vec<(String, String)>) - List of name and value
+/// - [pool](Pool) - Database pool
+///
+/// # Return
+/// - [Result](()) - The environnements are replaced
+/// - [Result](HttpResponseError) - An http response error if something went wrong
+pub async fn sync_environnements(
+  cargo_key: &str,
+  envs: Vec<(String, String)>,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let current_envs =
+    repositories::cargo_env::list_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?
+      .into_iter()
+      .map(|env| (env.name, env.value))
+      .collect::<HashMap<String, String>>();
+  let envs = envs.into_iter().collect::<HashMap<String, String>>();
+  for (name, value) in &envs {
+    match current_envs.get(name) {
+      None => {
+        let env = CargoEnvPartial {
+          cargo_key: cargo_key.to_owned(),
+          name: name.to_owned(),
+          value: value.to_owned(),
+        };
+        repositories::cargo_env::create(env, pool).await?;
+      }
+      Some(current_value) if current_value != value => {
+        repositories::cargo_env::patch_for_cargo(
+          name.to_owned(),
+          cargo_key.to_owned(),
+          value.to_owned(),
+          pool,
+        )
+        .await?;
+      }
+      Some(_) => {}
+    }
+  }
+  for name in current_envs.keys() {
+    if !envs.contains_key(name) {
+      let env_key = utils::key::gen_key(cargo_key, name);
+      repositories::cargo_env::delete_by_key(env_key, pool).await?;
+    }
+  }
+  Ok(())
+}
+
+/// Take a snapshot of a cargo with his current config, replicas,
+/// dns entry and environnements
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+/// - [pool](Pool) - Database pool
+///
+/// # Return
+/// - [Result](CargoRevisionPartial) - The snapshot of the cargo
+/// - [Result](HttpResponseError) - An http response error if something went wrong
+pub async fn gen_snapshot(
+  cargo_key: &str,
+  pool: &Pool,
+) -> Result<CargoRevisionPartial, HttpResponseError> {
+  let cargo =
+    repositories::cargo::find_by_key(cargo_key.to_owned(), pool).await?;
+  let mut environnements =
+    repositories::cargo_env::list_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?
+      .into_iter()
+      .map(|env| format!("{}={}", env.name, env.value))
+      .collect::<Vec<String>>();
+  environnements.sort();
+  Ok(CargoRevisionPartial {
+    cargo_key: cargo.key,
+    config: cargo.config,
+    replicas: cargo.replicas,
+    dns_entry: cargo.dns_entry,
+    environnements,
+  })
+}
+
+/// Store a revision of a cargo with his current config, replicas,
+/// dns entry and environnements
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+/// - [pool](Pool) - Database pool
+///
+/// # Return
+/// - [Result](CargoRevisionItem) - The stored revision
+/// - [Result](HttpResponseError) - An http response error if something went wrong
+pub async fn create_revision(
+  cargo_key: &str,
+  pool: &Pool,
+) -> Result<CargoRevisionItem, HttpResponseError> {
+  let item = gen_snapshot(cargo_key, pool).await?;
+  repositories::cargo_revision::create(item, pool).await
+}
+
+/// Restore a cargo and his environnements from a snapshot
+/// Only the store is changed, his containers are left untouched
+///
+/// # Arguments
+/// - [snapshot](CargoRevisionPartial) - The snapshot to restore
+/// - [pool](Pool) - Database pool
+///
+/// # Return
+/// - [Result](CargoItem) - The restored cargo
+/// - [Result](HttpResponseError) - An http response error if something went wrong
+pub async fn restore_snapshot(
+  snapshot: CargoRevisionPartial,
+  pool: &Pool,
+) -> Result<CargoItem, HttpResponseError> {
+  let envs = parse_environnements(&snapshot.environnements)?;
+  sync_environnements(&snapshot.cargo_key, envs, pool).await?;
+  repositories::cargo::restore_revision(snapshot, pool).await
+}
+
+/// Validate the strategy of a rolling update
+///
+/// # Arguments
+/// - [strategy](CargoUpdateStrategy) - The strategy to validate
+///
+/// # Return
+/// - [Result](()) - The strategy is valid
+/// - [Result](HttpResponseError) - An http response error with the invalid value
+pub fn validate_update_strategy(
+  strategy: &CargoUpdateStrategy,
+) -> Result<(), HttpResponseError> {
+  let values = [
+    ("surge", strategy.surge),
+    ("max_unavailable", strategy.max_unavailable),
+    ("ready_timeout", strategy.ready_timeout),
+  ];
+  for (name, value) in values {
+    if value < 0 {
+      return Err(HttpResponseError {
+        msg: format!(
+          "update strategy {} must be positive, got {}",
+          name, value
+        ),
+        status: StatusCode::UNPROCESSABLE_ENTITY,
+      });
+    }
+  }
+  if strategy.surge + strategy.max_unavailable == 0 {
+    return Err(HttpResponseError {
+      msg: String::from(
+        "update strategy surge or max_unavailable must be greater than 0",
+      ),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
+  Ok(())
+}
+
+/// Generate the container name of a cargo replica inside a cluster
+/// The first replica has no index suffix
+///
+/// # Arguments
+/// - [cargo](CargoItem) - The cargo of the replica
+/// - [cluster_name](str) - The name of the cluster
+/// - [index](i64) - The index of the replica
+///
+/// # Return
+/// - [String](String) - The container name
+pub fn gen_instance_name(
+  cargo: &CargoItem,
+  cluster_name: &str,
+  index: i64,
+) -> String {
+  let name =
+    format!("{}-{}-{}", &cargo.namespace_name, cluster_name, &cargo.name);
+  if index == 0 {
+    return name;
+  }
+  format!("{}-{}", name, index)
+}
+
+/// Difference between the expected replicas of a cargo and his containers
+#[derive(Debug, Default)]
+pub struct ReplicaDiff {
+  /// Indexes of the replicas without container
+  pub(crate) missing: Vec<i64>,
+  /// Names of the replicas with a container that is not running
+  pub(crate) stopped: Vec<String>,
+  /// Names of the containers that are not an expected replica
+  pub(crate) unexpected: Vec<String>,
+}
+
+/// Compare the containers of a cargo inside a cluster with his expected replicas
+///
+/// # Arguments
+/// - [cargo](CargoItem) - The cargo
+/// - [cluster_name](str) - The name of the cluster
+/// - [containers](Vec<bollard::models::ContainerSummary>) - The containers of the cargo inside the cluster
+///
+/// # Return
+/// - [ReplicaDiff](ReplicaDiff) - The missing, stopped and unexpected replicas
+pub fn diff_replicas(
+  cargo: &CargoItem,
+  cluster_name: &str,
+  containers: &[bollard::models::ContainerSummary],
+) -> ReplicaDiff {
+  let mut diff = ReplicaDiff::default();
+  let mut names: Vec<String> = Vec::new();
+  for container in containers {
+    let name = utils::cargo_instance::get_container_name(container);
+    let is_expected = (0..cargo.replicas)
+      .any(|index| gen_instance_name(cargo, cluster_name, index) == name);
+    if !is_expected {
+      diff.unexpected.push(name);
+      continue;
+    }
+    if container.state.as_deref() != Some("running") {
+      diff.stopped.push(name.to_owned());
+    }
+    names.push(name);
+  }
+  diff.missing = (0..cargo.replicas)
+    .filter(|index| {
+      !names.contains(&gen_instance_name(cargo, cluster_name, *index))
+    })
+    .collect();
+  diff
+}
+
 pub async fn create_instances<'a>(
   opts: CreateCargoInstanceOpts<'a>,
   docker_api: &bollard::Docker,
@@ -98,7 +443,6 @@ pub async fn create_instances<'a>(
       status: StatusCode::BAD_REQUEST,
     });
   }
-  let mut count = 0;
   let mut container_ids: Vec<String> = Vec::new();
   let mut labels: HashMap<String, String> = match opts.labels {
     None => HashMap::new(),
@@ -109,14 +453,8 @@ pub async fn create_instances<'a>(
     opts.cargo.namespace_name.to_owned(),
   );
   labels.insert(String::from("cargo"), opts.cargo.key.to_owned());
-  while count < opts.cargo.replicas {
-    let mut name = format!(
-      "{}-{}-{}",
-      &opts.cargo.namespace_name, &opts.cluster_name, &opts.cargo.name,
-    );
-    if count != 0 {
-      name += &("-".to_owned() + &count.to_string());
-    }
+  for index in &opts.indexes {
+    let name = gen_instance_name(opts.cargo, opts.cluster_name, *index);
 
     log::debug!("passing env {:#?}", &opts.environnements);
 
@@ -143,13 +481,18 @@ pub async fn create_instances<'a>(
           maximum_retry_count: None,
         }),
         network_mode: net_mode,
+        dns_search: host_config.dns_search.to_owned().or_else(|| {
+          (!opts.dns_search.is_empty()).then(|| opts.dns_search.to_owned())
+        }),
         ..host_config.to_owned()
       }),
       ..cargo_config.to_owned()
     };
-    let res = docker_api.create_container(Some(options), config).await?;
+    let res = docker_api
+      .create_container(Some(options), config)
+      .await
+      .record_error()?;
     container_ids.push(res.id);
-    count += 1;
   }
   Ok(container_ids)
 }
@@ -176,7 +519,10 @@ pub async fn delete_instances(
         force: true,
         ..Default::default()
       });
-      docker_api.remove_container(&id, options).await?;
+      docker_api
+        .remove_container(&id, options)
+        .await
+        .record_error()?;
       Ok::<_, HttpResponseError>(())
     })
     .collect::<FuturesUnordered<_>>()
@@ -188,77 +534,536 @@ pub async fn delete_instances(
   Ok(())
 }
 
-/// Regenerate containers for a given cargo
-pub async fn update_instances(
+/// Keys of the cargoes being updated
+/// Their containers are left untouched by the reconciler during the update
+static UPDATING_CARGOES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Mark a cargo as being updated until it's dropped
+pub struct UpdatingGuard {
   cargo_key: String,
+}
+
+impl Drop for UpdatingGuard {
+  fn drop(&mut self) {
+    let mut cargoes = match UPDATING_CARGOES.lock() {
+      Ok(cargoes) => cargoes,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+    cargoes.retain(|key| key != &self.cargo_key);
+  }
+}
+
+/// Mark a cargo as being updated
+/// The cargo is released when the returned guard is dropped
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+///
+/// # Return
+/// - [Result](UpdatingGuard) - The guard of the update
+/// - [Result](HttpResponseError) - Conflict if the cargo is already being updated
+pub fn lock_updating(
+  cargo_key: &str,
+) -> Result<UpdatingGuard, HttpResponseError> {
+  let mut cargoes = match UPDATING_CARGOES.lock() {
+    Ok(cargoes) => cargoes,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  if cargoes.iter().any(|key| key == cargo_key) {
+    return Err(HttpResponseError {
+      msg: format!("cargo {} is being updated", cargo_key),
+      status: StatusCode::CONFLICT,
+    });
+  }
+  cargoes.push(cargo_key.to_owned());
+  Ok(UpdatingGuard {
+    cargo_key: cargo_key.to_owned(),
+  })
+}
+
+/// Check if the containers of a cargo are being updated
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+///
+/// # Return
+/// - [bool](bool) - True if an update of the cargo is in progress
+pub fn is_updating(cargo_key: &str) -> bool {
+  match UPDATING_CARGOES.lock() {
+    Ok(cargoes) => cargoes.iter().any(|key| key == cargo_key),
+    Err(poisoned) => poisoned.into_inner().iter().any(|key| key == cargo_key),
+  }
+}
+
+/// Wait for a container to be running
+/// or to be healthy if his image or config define a healthcheck
+async fn wait_instance_ready(
+  name: &str,
+  timeout: u64,
+  docker_api: &bollard::Docker,
+) -> Result<(), HttpResponseError> {
+  let mut elapsed = 0;
+  loop {
+    sleep(Seconds(1)).await;
+    elapsed += 1;
+    let container = docker_api
+      .inspect_container(name, None)
+      .await
+      .record_error()?;
+    let state = container.state.unwrap_or_default();
+    match state.health.and_then(|health| health.status) {
+      Some(HealthStatusEnum::HEALTHY) => return Ok(()),
+      Some(HealthStatusEnum::UNHEALTHY) => {
+        return Err(HttpResponseError {
+          msg: format!("container {} is unhealthy", name),
+          status: StatusCode::INTERNAL_SERVER_ERROR,
+        });
+      }
+      Some(HealthStatusEnum::STARTING) => {}
+      _ => {
+        if state.restarting == Some(true) || state.running != Some(true) {
+          return Err(HttpResponseError {
+            msg: format!(
+              "container {} is not running exit code {}",
+              name,
+              state.exit_code.unwrap_or_default(),
+            ),
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+          });
+        }
+        return Ok(());
+      }
+    }
+    if elapsed >= timeout {
+      return Err(HttpResponseError {
+        msg: format!("container {} is not ready after {}s", name, timeout),
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+      });
+    }
+  }
+}
+
+async fn remove_instance(
+  name: &str,
+  docker_api: &bollard::Docker,
+) -> Result<(), HttpResponseError> {
+  let options = Some(bollard::container::RemoveContainerOptions {
+    force: true,
+    ..Default::default()
+  });
+  docker_api
+    .remove_container(name, options)
+    .await
+    .record_error()?;
+  Ok(())
+}
+
+/// Create, start and wait the new replicas of a batch
+async fn create_batch(
+  opts: &JoinCargoOptions,
+  names: &[String],
+  strategy: &CargoUpdateStrategy,
   daemon_config: &DaemonConfig,
   docker_api: &bollard::Docker,
   pool: &Pool,
 ) -> Result<(), HttpResponseError> {
-  let cluster_cargoes =
-    repositories::cargo_instance::find_by_cargo_key(cargo_key, pool).await?;
-  let mut cluster_cargoes_stream = stream::iter(cluster_cargoes);
-  while let Some(cluster_cargo) = cluster_cargoes_stream.next().await {
-    let network = repositories::cluster_network::find_by_key(
-      cluster_cargo.network_key,
-      pool,
-    )
-    .await?;
-
-    let cluster = repositories::cluster::find_by_key(
-      cluster_cargo.cluster_key.to_owned(),
-      pool,
-    )
-    .await?;
-    let cargo = repositories::cargo::find_by_key(
-      cluster_cargo.cargo_key.to_owned(),
-      pool,
-    )
-    .await?;
+  utils::cluster::join_cargo(opts, daemon_config, docker_api, pool).await?;
+  for name in names {
+    utils::cargo_instance::start_cargo_instance(name, docker_api).await?;
+    wait_instance_ready(name, strategy.ready_timeout as u64, docker_api)
+      .await?;
+  }
+  Ok(())
+}
 
-    // Containers to remove after update
-    let qs = CargoInstanceFilterQuery {
-      cargo: Some(cargo.name.to_owned()),
-      cluster: Some(cluster.name.to_owned()),
-      namespace: Some(cargo.namespace_name.to_owned()),
+/// Remove the new replicas of a batch and give back their name to the old ones
+/// The old replicas stopped for the batch are started again
+async fn rollback_batch(
+  names: &[String],
+  replaced: &[(String, String)],
+  docker_api: &bollard::Docker,
+) {
+  for name in names {
+    // The replica may not have been created
+    let _ = remove_instance(name, docker_api).await;
+  }
+  for (name, tmp_name) in replaced {
+    let options = bollard::container::RenameContainerOptions {
+      name: name.to_owned(),
     };
-    let instances = list_instances(qs, docker_api).await?;
+    if let Err(err) = docker_api.rename_container(tmp_name, options).await {
+      log::error!("unable to rename back container {}: {}", tmp_name, err);
+      continue;
+    }
+    if let Err(err) =
+      utils::cargo_instance::start_cargo_instance(name, docker_api).await
+    {
+      log::error!("unable to restart container {}: {}", name, err);
+    }
+  }
+}
 
-    let mut instance_stream = stream::iter(instances.to_owned());
+/// Replace the containers of a cargo inside a cluster batch by batch
+/// A batch contains `surge + max_unavailable` replicas,
+/// the `max_unavailable` first old replicas of a batch are stopped
+/// before their replacement is ready, every old replicas of the batch
+/// are removed once the new ones are ready.
+/// If a new replica fails the update is aborted
+/// and the old replicas of the batch are restored.
+async fn rolling_update_instance(
+  instance: CargoInstanceItem,
+  strategy: &CargoUpdateStrategy,
+  daemon_config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let network =
+    repositories::cluster_network::find_by_key(instance.network_key, pool)
+      .await?;
+  let cluster =
+    repositories::cluster::find_by_key(instance.cluster_key, pool).await?;
+  let cargo =
+    repositories::cargo::find_by_key(instance.cargo_key, pool).await?;
+  let old_names =
+    utils::cluster::list_containers(&cluster.key, &cargo.key, docker_api)
+      .await?
+      .iter()
+      .map(utils::cargo_instance::get_container_name)
+      .collect::<Vec<String>>();
+
+  let batch_size = strategy.surge + strategy.max_unavailable;
+  let indexes = (0..cargo.replicas).collect::<Vec<i64>>();
+  for batch in indexes.chunks(batch_size as usize) {
+    let names = batch
+      .iter()
+      .map(|index| gen_instance_name(&cargo, &cluster.name, *index))
+      .collect::<Vec<String>>();
 
-    let mut count = 0;
-    while let Some(instance) = instance_stream.next().await {
+    // Old replicas are renamed so the new ones can take their name
+    let mut replaced: Vec<(String, String)> = Vec::new();
+    for name in &names {
+      if !old_names.contains(name) {
+        continue;
+      }
+      let tmp_name = format!("{}-tmp", name);
       let options = bollard::container::RenameContainerOptions {
-        name: format!("{}-tmp-{}", &cargo.name, &count),
+        name: tmp_name.to_owned(),
       };
       docker_api
-        .rename_container(&instance.id.unwrap_or_default(), options)
-        .await?;
-      count += 1;
+        .rename_container(name, options)
+        .await
+        .record_error()?;
+      replaced.push((name.to_owned(), tmp_name));
+    }
+    let unavailable = strategy.max_unavailable as usize;
+    for (_, tmp_name) in replaced.iter().take(unavailable) {
+      if let Err(err) = docker_api
+        .stop_container(tmp_name, None::<StopContainerOptions>)
+        .await
+      {
+        rollback_batch(&[], &replaced, docker_api).await;
+        return Err(err.into());
+      }
     }
 
     let opts = JoinCargoOptions {
       cluster: cluster.to_owned(),
-      cargo,
-      network,
+      cargo: cargo.to_owned(),
+      network: network.to_owned(),
       is_creating_relation: false,
+      indexes: Some(batch.to_vec()),
     };
+    if let Err(err) =
+      create_batch(&opts, &names, strategy, daemon_config, docker_api, pool)
+        .await
+    {
+      rollback_batch(&names, &replaced, docker_api).await;
+      return Err(HttpResponseError {
+        msg: format!(
+          "rolling update of cargo {} aborted: {}",
+          &cargo.key, err.msg
+        ),
+        status: err.status,
+      });
+    }
 
-    utils::cluster::join_cargo(&opts, docker_api, pool).await?;
-
+    // Stopped replicas are removed first so the cluster doesn't start them
+    for (_, tmp_name) in replaced.iter().take(unavailable) {
+      remove_instance(tmp_name, docker_api).await?;
+    }
+    // The proxy target the old and the new replicas until the old are removed
     utils::cluster::start(&cluster, daemon_config, pool, docker_api).await?;
+    for (_, tmp_name) in replaced.iter().skip(unavailable) {
+      remove_instance(tmp_name, docker_api).await?;
+    }
+  }
 
-    let mut scntr = stream::iter(instances);
+  // Old replicas above the new number of replicas
+  let names = indexes
+    .iter()
+    .map(|index| gen_instance_name(&cargo, &cluster.name, *index))
+    .collect::<Vec<String>>();
+  for name in old_names.iter().filter(|name| !names.contains(name)) {
+    remove_instance(name, docker_api).await?;
+  }
+  utils::cluster::start(&cluster, daemon_config, pool, docker_api).await?;
+  Ok(())
+}
 
-    while let Some(container) = scntr.next().await {
-      let options = Some(bollard::container::RemoveContainerOptions {
-        force: true,
-        ..Default::default()
+/// Regenerate containers for a given cargo
+/// The containers of each cluster are replaced with a rolling update.
+/// The caller must hold the guard given by [lock_updating](lock_updating)
+///
+/// # Arguments
+/// - [cargo_key](String) - The key of the cargo
+/// - [strategy](CargoUpdateStrategy) - The strategy of the rolling update
+/// - [daemon_config](DaemonConfig) - The daemon config
+/// - [docker_api](bollard::Docker) - The docker api
+/// - [pool](Pool) - The database pool
+///
+/// # Return
+/// - [Result](()) - The containers have been replaced
+/// - [Result](HttpResponseError) - The update has been aborted
+pub async fn update_instances(
+  cargo_key: String,
+  strategy: &CargoUpdateStrategy,
+  daemon_config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let cluster_cargoes =
+    repositories::cargo_instance::find_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?;
+  for cluster_cargo in cluster_cargoes {
+    rolling_update_instance(
+      cluster_cargo,
+      strategy,
+      daemon_config,
+      docker_api,
+      pool,
+    )
+    .await?;
+  }
+  Ok(())
+}
+
+/// Roll the containers of a cargo to the config stored for it
+/// A revision is recorded once every containers are replaced.
+/// When the update fail the cargo is restored from the `previous` snapshot
+/// and his containers are rolled back to it, including the ones
+/// already replaced by the previous batches.
+/// The caller must hold the guard given by [lock_updating](lock_updating)
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+/// - [previous](CargoRevisionPartial) - The snapshot of the cargo before his change
+/// - [strategy](CargoUpdateStrategy) - The strategy of the rolling update
+/// - [daemon_config](DaemonConfig) - The daemon config
+/// - [docker_api](bollard::Docker) - The docker api
+/// - [pool](Pool) - The database pool
+///
+/// # Return
+/// - [Result](CargoRevisionItem) - The revision of the updated cargo
+/// - [Result](HttpResponseError) - The update has been aborted
+pub async fn apply_update(
+  cargo_key: &str,
+  previous: CargoRevisionPartial,
+  strategy: &CargoUpdateStrategy,
+  daemon_config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<CargoRevisionItem, HttpResponseError> {
+  let err = match update_instances(
+    cargo_key.to_owned(),
+    strategy,
+    daemon_config,
+    docker_api,
+    pool,
+  )
+  .await
+  {
+    Ok(_) => return create_revision(cargo_key, pool).await,
+    Err(err) => err,
+  };
+  if let Err(err) = restore_snapshot(previous, pool).await {
+    log::error!("unable to restore cargo {}: {}", cargo_key, err);
+    return Err(err);
+  }
+  if let Err(err) = update_instances(
+    cargo_key.to_owned(),
+    strategy,
+    daemon_config,
+    docker_api,
+    pool,
+  )
+  .await
+  {
+    log::error!("unable to rollback containers of {}: {}", cargo_key, err);
+  }
+  Err(err)
+}
+
+/// Scale the containers of a cargo inside a cluster to his replicas
+/// Missing replicas are created and the highest indexed ones are removed
+/// once the proxy no longer target them,
+/// the other containers are left untouched.
+async fn scale_instance(
+  instance: CargoInstanceItem,
+  daemon_config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let network =
+    repositories::cluster_network::find_by_key(instance.network_key, pool)
+      .await?;
+  let cluster =
+    repositories::cluster::find_by_key(instance.cluster_key, pool).await?;
+  let cargo =
+    repositories::cargo::find_by_key(instance.cargo_key, pool).await?;
+  let containers =
+    utils::cluster::list_containers(&cluster.key, &cargo.key, docker_api)
+      .await?;
+  let diff = diff_replicas(&cargo, &cluster.name, &containers);
+  if !diff.missing.is_empty() {
+    let names = diff
+      .missing
+      .iter()
+      .map(|index| gen_instance_name(&cargo, &cluster.name, *index))
+      .collect::<Vec<String>>();
+    let opts = JoinCargoOptions {
+      cluster: cluster.to_owned(),
+      cargo: cargo.to_owned(),
+      network,
+      is_creating_relation: false,
+      indexes: Some(diff.missing),
+    };
+    let strategy = CargoUpdateStrategy::default();
+    if let Err(err) =
+      create_batch(&opts, &names, &strategy, daemon_config, docker_api, pool)
+        .await
+    {
+      for name in &names {
+        if let Err(err) = remove_instance(name, docker_api).await {
+          log::warn!("unable to remove container {}: {}", name, err);
+        }
+      }
+      return Err(HttpResponseError {
+        msg: format!("scaling of cargo {} aborted: {}", &cargo.key, err.msg),
+        status: err.status,
       });
-      docker_api
-        .remove_container(&container.id.clone().unwrap_or_default(), options)
-        .await?;
     }
   }
+  if diff.unexpected.is_empty() {
+    utils::cluster::start(&cluster, daemon_config, pool, docker_api).await?;
+    return Ok(());
+  }
+  // The proxy stop targeting the departing replicas before they are removed
+  let _draining = utils::cluster::drain_containers(&diff.unexpected);
+  utils::cluster::start(&cluster, daemon_config, pool, docker_api).await?;
+  for name in &diff.unexpected {
+    docker_api
+      .stop_container(name, None::<StopContainerOptions>)
+      .await
+      .record_error()?;
+    remove_instance(name, docker_api).await?;
+  }
+  Ok(())
+}
+
+/// Scale a cargo to the given number of replicas without recreating his containers
+/// The proxy templates of his clusters are rendered again after
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+/// - [replicas](i64) - The number of replicas
+/// - [daemon_config](DaemonConfig) - The daemon config
+/// - [docker_api](bollard::Docker) - The docker api
+/// - [pool](Pool) - The database pool
+///
+/// # Return
+/// - [Result](CargoItem) - The scaled cargo
+/// - [Result](HttpResponseError) - An http response error if the cargo can't be scaled
+pub async fn scale_instances(
+  cargo_key: &str,
+  replicas: i64,
+  daemon_config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<CargoItem, HttpResponseError> {
+  if replicas < 0 {
+    return Err(HttpResponseError {
+      msg: format!("replicas must be positive, got {}", replicas),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
+  let cargo =
+    repositories::cargo::find_by_key(cargo_key.to_owned(), pool).await?;
+  // The reconciler must not create the new replicas before us
+  let _guard = lock_updating(cargo_key)?;
+  let previous = CargoPatchPartial {
+    replicas: Some(cargo.replicas),
+    ..Default::default()
+  };
+  let patch = CargoPatchPartial {
+    replicas: Some(replicas),
+    ..Default::default()
+  };
+  let updated = repositories::cargo::update_by_key(
+    cargo.namespace_name.to_owned(),
+    cargo.name.to_owned(),
+    patch,
+    pool,
+  )
+  .await?;
+  let err =
+    match scale_cluster_instances(cargo_key, daemon_config, docker_api, pool)
+      .await
+    {
+      Ok(_) => return Ok(updated),
+      Err(err) => err,
+    };
+  // Scaling failed so the previous replicas are restored like a patch would
+  if let Err(err) = repositories::cargo::update_by_key(
+    cargo.namespace_name,
+    cargo.name,
+    previous,
+    pool,
+  )
+  .await
+  {
+    log::error!("unable to restore cargo {}: {}", cargo_key, err);
+    return Err(err);
+  }
+  if let Err(err) =
+    scale_cluster_instances(cargo_key, daemon_config, docker_api, pool).await
+  {
+    log::error!("unable to rollback containers of {}: {}", cargo_key, err);
+  }
+  Err(err)
+}
+
+/// Scale the containers of a cargo in every clusters he joined to his replicas
+///
+/// # Arguments
+/// - [cargo_key](str) - The key of the cargo
+/// - [daemon_config](DaemonConfig) - The daemon config
+/// - [docker_api](bollard::Docker) - The docker api
+/// - [pool](Pool) - The database pool
+///
+/// # Return
+/// - [Result](()) - The containers have been scaled
+/// - [Result](HttpResponseError) - An http response error if something went wrong
+async fn scale_cluster_instances(
+  cargo_key: &str,
+  daemon_config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let cluster_cargoes =
+    repositories::cargo_instance::find_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?;
+  for cluster_cargo in cluster_cargoes {
+    scale_instance(cluster_cargo, daemon_config, docker_api, pool).await?;
+  }
   Ok(())
 }
@@ -7,7 +7,9 @@ use futures::StreamExt;
 use bollard::Docker;
 use bollard::models::{ImageInspect, ImageSummary};
 
+use crate::utils;
 use crate::errors::HttpResponseError;
+use crate::utils::metrics::DockerErrorMetric;
 use crate::models::GenericDelete;
 
 /// List all cargo/container images
@@ -44,7 +46,7 @@ pub async fn inspect(
   image_name: &str,
   docker_api: &Docker,
 ) -> Result<ImageInspect, HttpResponseError> {
-  let image = docker_api.inspect_image(image_name).await?;
+  let image = docker_api.inspect_image(image_name).await.record_error()?;
 
   Ok(image)
 }
@@ -83,6 +85,7 @@ pub async fn download(
     while let Some(result) = stream.next().await {
       match result {
         Err(err) => {
+          utils::metrics::record_docker_error(&err);
           let err = ntex::web::Error::new(web::error::InternalError::default(
             format!("{:?}", err),
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -135,7 +138,10 @@ pub async fn delete(
   id_or_name: &str,
   docker_api: &Docker,
 ) -> Result<GenericDelete, HttpResponseError> {
-  docker_api.remove_image(id_or_name, None, None).await?;
+  docker_api
+    .remove_image(id_or_name, None, None)
+    .await
+    .record_error()?;
   let res = GenericDelete { count: 1 };
 
   Ok(res)
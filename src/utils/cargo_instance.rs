@@ -1,20 +1,35 @@
+use std::io;
+use std::pin::Pin;
+use std::future::ready;
 use bollard::container::StartContainerOptions;
 use ntex::rt;
+use ntex::ws;
 use ntex::web;
 use ntex::http::StatusCode;
 use ntex::util::Bytes;
 use ntex::channel::mpsc;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use ntex::service::{fn_service, Service};
 use bollard::Docker;
 use bollard::errors::Error as DockerError;
-use bollard::container::LogOutput;
+use bollard::container::{LogOutput, LogsOptions, Stats, StatsOptions};
+use bollard::models::ContainerSummary;
 use bollard::exec::{
-  CreateExecOptions, CreateExecResults, StartExecOptions, StartExecResults,
+  CreateExecOptions, CreateExecResults, ResizeExecOptions, StartExecOptions,
+  StartExecResults,
 };
 
+use crate::utils;
+use crate::utils::metrics::DockerErrorMetric;
 use crate::errors::HttpResponseError;
 use crate::models::CargoInstanceExecBody;
-use crate::models::CargoInstanceState;
+use crate::models::{
+  CargoInstanceState, CargoInstanceHealth, CargoInstanceLogQuery,
+  CargoInstanceOutput, CargoInstanceOutputKind, CargoInstanceExecResizeBody,
+  CargoInstanceExecInspect, CargoInstanceFilterQuery, CargoInstanceStats,
+  CargoStats,
+};
 
 /// Create cargo instance exec
 /// This function will create a exec instance for a container
@@ -44,13 +59,63 @@ pub async fn create_cargo_instance_exec(
     working_dir: params.working_dir.to_owned(),
     cmd: params.cmd.to_owned(),
   };
-  let exec_instance =
-    docker_api.create_exec(container_id_or_name, config).await?;
+  let exec_instance = docker_api
+    .create_exec(container_id_or_name, config)
+    .await
+    .record_error()?;
   Ok(exec_instance)
 }
 
+/// Resize the tty of a cargo instance exec
+///
+/// ## Arguments
+/// - [id](str) The id of the exec instance
+/// - [params](CargoInstanceExecResizeBody) The new size of the tty
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Result](()) The tty is resized
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn resize_cargo_instance_exec(
+  id: &str,
+  params: &CargoInstanceExecResizeBody,
+  docker_api: &Docker,
+) -> Result<(), HttpResponseError> {
+  let options = ResizeExecOptions {
+    width: params.width,
+    height: params.height,
+  };
+  docker_api.resize_exec(id, options).await.record_error()?;
+  Ok(())
+}
+
+/// Inspect a cargo instance exec
+///
+/// ## Arguments
+/// - [id](str) The id of the exec instance
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Result](CargoInstanceExecInspect) The state of the exec instance
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn inspect_cargo_instance_exec(
+  id: &str,
+  docker_api: &Docker,
+) -> Result<CargoInstanceExecInspect, HttpResponseError> {
+  let res = docker_api.inspect_exec(id).await.record_error()?;
+  Ok(CargoInstanceExecInspect {
+    id: res.id.unwrap_or_else(|| id.to_owned()),
+    container_id: res.container_id,
+    running: res.running.unwrap_or_default(),
+    exit_code: res.exit_code,
+    pid: res.pid,
+  })
+}
+
 /// Exec cargo instance
-/// This function will exec a command in a container
+/// This function will exec a command in a container,
+/// each output is streamed as a [CargoInstanceOutput](CargoInstanceOutput)
+/// so stdout and stderr can be told apart.
 ///
 /// ## Arguments
 /// - [id](str) The id of the exec instance
@@ -63,7 +128,11 @@ pub async fn exec_cargo_instance_exec(
   id: &str,
   docker_api: &Docker,
 ) -> Result<web::HttpResponse, HttpResponseError> {
-  let res = docker_api.start_exec(id, None::<StartExecOptions>).await?;
+  let res = docker_api
+    .start_exec(id, None::<StartExecOptions>)
+    .await
+    .record_error()?;
+  let id = id.to_owned();
 
   match res {
     StartExecResults::Attached {
@@ -84,39 +153,480 @@ pub async fn exec_cargo_instance_exec(
               tx.close();
               break;
             }
-            Ok(output) => match output {
-              LogOutput::Console { message } => {
-                if tx
-                  .send(Ok::<_, web::error::Error>(Bytes::from(
-                    message.to_vec(),
-                  )))
-                  .is_err()
-                {
+            Ok(output) => {
+              let output = gen_instance_output(&id, output);
+              let data = match serde_json::to_string(&output) {
+                Err(err) => {
+                  let err = web::error::Error::new(HttpResponseError {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    msg: format!("{:?}", err),
+                  });
+                  let _ = tx.send(Err::<_, web::error::Error>(err));
                   break;
                 }
+                Ok(data) => data,
+              };
+              // Same framing as the logs: the length then the data
+              let response = format!("{}\n{}\n", data.len(), data);
+              if tx
+                .send(Ok::<_, web::error::Error>(Bytes::from(response)))
+                .is_err()
+              {
+                break;
               }
-              LogOutput::StdOut { message } => {
-                if tx
-                  .send(Ok::<_, web::error::Error>(Bytes::from(
-                    message.to_vec(),
-                  )))
-                  .is_err()
-                {
-                  break;
-                }
-              }
-              _ => log::debug!("todo exec command outputs"),
-            },
+            }
           }
         }
         tx.close();
       });
-      Ok(web::HttpResponse::Ok().streaming(rx_body))
+      Ok(
+        web::HttpResponse::Ok()
+          .content_type("nanocl/streaming-v1")
+          .streaming(rx_body),
+      )
     }
     StartExecResults::Detached => Ok(web::HttpResponse::Ok().into()),
   }
 }
 
+/// Convert a docker output into a cargo instance output
+///
+/// ## Arguments
+/// - [name](str) The name of the cargo instance
+/// - [output](LogOutput) The docker output
+///
+/// ## Return
+/// - [CargoInstanceOutput](CargoInstanceOutput) The output of the cargo instance
+pub fn gen_instance_output(
+  name: &str,
+  output: LogOutput,
+) -> CargoInstanceOutput {
+  let (kind, message) = match output {
+    LogOutput::StdIn { message } => (CargoInstanceOutputKind::StdIn, message),
+    LogOutput::StdOut { message } => (CargoInstanceOutputKind::StdOut, message),
+    LogOutput::StdErr { message } => (CargoInstanceOutputKind::StdErr, message),
+    LogOutput::Console { message } => {
+      (CargoInstanceOutputKind::Console, message)
+    }
+  };
+  CargoInstanceOutput {
+    name: name.to_owned(),
+    kind,
+    data: String::from_utf8_lossy(&message).to_string(),
+  }
+}
+
+/// Stream the logs of cargo instances
+/// The logs of every instances are multiplexed in the same stream,
+/// each output is prefixed with the name of his instance.
+///
+/// ## Arguments
+/// - [names](Vec<String>) The names of the cargo instances
+/// - [qs](CargoInstanceLogQuery) The log options
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Receiver](Result<Bytes, web::error::Error>) The stream of outputs
+pub fn logs(
+  names: Vec<String>,
+  qs: &CargoInstanceLogQuery,
+  docker_api: &Docker,
+) -> mpsc::Receiver<Result<Bytes, web::error::Error>> {
+  let options = LogsOptions::<String> {
+    follow: qs.follow.unwrap_or(false),
+    stdout: true,
+    stderr: true,
+    since: qs.since.unwrap_or(0),
+    until: qs.until.unwrap_or(0),
+    timestamps: qs.timestamps.unwrap_or(false),
+    tail: qs.tail.to_owned().unwrap_or_else(|| String::from("all")),
+  };
+  let streams = names
+    .into_iter()
+    .map(|name| {
+      docker_api
+        .logs(&name, Some(options.to_owned()))
+        .map(move |output| (name.to_owned(), output))
+        .boxed()
+    })
+    .collect::<Vec<_>>();
+  let (tx, rx_body) = mpsc::channel();
+
+  rt::spawn(async move {
+    let mut stream = futures::stream::select_all(streams);
+    while let Some((name, output)) = stream.next().await {
+      let output = match output {
+        Err(err) => {
+          let err = web::error::Error::new(HttpResponseError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: format!("unable to get logs of {}: {}", name, err),
+          });
+          let _ = tx.send(Err::<_, web::error::Error>(err));
+          break;
+        }
+        Ok(output) => gen_instance_output(&name, output),
+      };
+      let data = match serde_json::to_string(&output) {
+        Err(err) => {
+          let err = web::error::Error::new(HttpResponseError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: format!("{:?}", err),
+          });
+          let _ = tx.send(Err::<_, web::error::Error>(err));
+          break;
+        }
+        Ok(data) => data,
+      };
+      // Same framing as the image download: the length then the data
+      let response = format!("{}\n{}\n", data.len(), data);
+      if tx
+        .send(Ok::<_, web::error::Error>(Bytes::from(response)))
+        .is_err()
+      {
+        break;
+      }
+    }
+    tx.close();
+  });
+  rx_body
+}
+
+/// Cpu utilisation of a container in percent of one cpu
+///
+/// ## Arguments
+/// - [stats](Stats) The stats of the container
+///
+/// ## Return
+/// - [Option](f64) The cpu utilisation or none if it can't be computed yet
+pub fn cpu_percent(stats: &Stats) -> Option<f64> {
+  let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as f64
+    - stats.precpu_stats.cpu_usage.total_usage as f64;
+  let system_delta = stats.cpu_stats.system_cpu_usage? as f64
+    - stats.precpu_stats.system_cpu_usage.unwrap_or_default() as f64;
+  if cpu_delta < 0.0 || system_delta <= 0.0 {
+    return None;
+  }
+  let online_cpus = stats.cpu_stats.online_cpus.unwrap_or(1) as f64;
+  Some(cpu_delta / system_delta * online_cpus * 100.0)
+}
+
+/// Memory utilisation of a container in percent of his limit
+///
+/// ## Arguments
+/// - [stats](Stats) The stats of the container
+///
+/// ## Return
+/// - [Option](f64) The memory utilisation or none if the container has no limit
+pub fn memory_percent(stats: &Stats) -> Option<f64> {
+  let usage = stats.memory_stats.usage? as f64;
+  let limit = stats.memory_stats.limit? as f64;
+  if limit <= 0.0 {
+    return None;
+  }
+  Some(usage / limit * 100.0)
+}
+
+/// Convert docker stats into the resource usage of a cargo instance
+///
+/// ## Arguments
+/// - [name](str) The name of the cargo instance
+/// - [stats](Stats) The docker stats
+///
+/// ## Return
+/// - [CargoInstanceStats](CargoInstanceStats) The resource usage of the cargo instance
+pub fn gen_instance_stats(name: &str, stats: &Stats) -> CargoInstanceStats {
+  let (network_rx, network_tx) = stats
+    .networks
+    .iter()
+    .flat_map(|networks| networks.values())
+    .fold((0, 0), |(rx, tx), network| {
+      (rx + network.rx_bytes, tx + network.tx_bytes)
+    });
+  let (block_read, block_write) = stats
+    .blkio_stats
+    .io_service_bytes_recursive
+    .iter()
+    .flatten()
+    .fold((0, 0), |(read, write), entry| {
+      match entry.op.to_lowercase().as_str() {
+        "read" => (read + entry.value, write),
+        "write" => (read, write + entry.value),
+        _ => (read, write),
+      }
+    });
+  CargoInstanceStats {
+    name: name.to_owned(),
+    cpu_percent: cpu_percent(stats).unwrap_or_default(),
+    memory_usage: stats.memory_stats.usage.unwrap_or_default(),
+    memory_limit: stats.memory_stats.limit.unwrap_or_default(),
+    network_rx,
+    network_tx,
+    block_read,
+    block_write,
+  }
+}
+
+/// Get a cargo instance by name using the labels of the cargoes
+/// so only containers managed by nanocl can be found
+///
+/// ## Arguments
+/// - [name](str) The name or id of the cargo instance
+/// - [qs](CargoInstanceFilterQuery) The filter to search the instance in
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Result](ContainerSummary) The container of the cargo instance
+/// - [Result](HttpResponseError) An http response error if the instance doesn't exist
+pub async fn find_instance(
+  name: &str,
+  qs: CargoInstanceFilterQuery,
+  docker_api: &Docker,
+) -> Result<ContainerSummary, HttpResponseError> {
+  utils::cargo::list_instances(qs, docker_api)
+    .await?
+    .into_iter()
+    .find(|container| {
+      // Only containers created for a cargo are cargo instances
+      get_container_label(container, "cargo").is_some()
+        && (get_container_name(container) == name
+          || container.id.as_deref() == Some(name))
+    })
+    .ok_or_else(|| HttpResponseError {
+      status: StatusCode::NOT_FOUND,
+      msg: format!("cargo instance {} not found", name),
+    })
+}
+
+/// Get the resource usage of a cargo instance at a given time
+///
+/// ## Arguments
+/// - [name](str) The name of the cargo instance
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Result](CargoInstanceStats) The resource usage of the cargo instance
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn get_stats(
+  name: &str,
+  docker_api: &Docker,
+) -> Result<CargoInstanceStats, HttpResponseError> {
+  let options = Some(StatsOptions {
+    stream: false,
+    one_shot: false,
+  });
+  match docker_api.stats(name, options).next().await {
+    None => Err(HttpResponseError {
+      status: StatusCode::INTERNAL_SERVER_ERROR,
+      msg: format!("unable to get stats of {}", name),
+    }),
+    Some(stats) => Ok(gen_instance_stats(name, &stats.record_error()?)),
+  }
+}
+
+/// Sum the resource usage of cargo instances
+///
+/// ## Arguments
+/// - [key](str) The key of the cargo
+/// - [instances](Vec<CargoInstanceStats>) The resource usage of the instances
+///
+/// ## Return
+/// - [CargoStats](CargoStats) The resource usage of the cargo
+pub fn sum_stats(key: &str, instances: Vec<CargoInstanceStats>) -> CargoStats {
+  let mut stats = CargoStats {
+    key: key.to_owned(),
+    ..Default::default()
+  };
+  for instance in &instances {
+    stats.cpu_percent += instance.cpu_percent;
+    stats.memory_usage += instance.memory_usage;
+    stats.memory_limit += instance.memory_limit;
+    stats.network_rx += instance.network_rx;
+    stats.network_tx += instance.network_tx;
+    stats.block_read += instance.block_read;
+    stats.block_write += instance.block_write;
+  }
+  stats.instances = instances;
+  stats
+}
+
+/// Stream the resource usage of a cargo instance
+///
+/// ## Arguments
+/// - [name](String) The name of the cargo instance
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Receiver](Result<Bytes, web::error::Error>) The stream of stats
+pub fn stream_stats(
+  name: String,
+  docker_api: &Docker,
+) -> mpsc::Receiver<Result<Bytes, web::error::Error>> {
+  let options = Some(StatsOptions {
+    stream: true,
+    one_shot: false,
+  });
+  let mut stream = docker_api.stats(&name, options);
+  let (tx, rx_body) = mpsc::channel();
+
+  rt::spawn(async move {
+    while let Some(stats) = stream.next().await {
+      let stats = match stats {
+        Err(err) => {
+          utils::metrics::record_docker_error(&err);
+          let err = web::error::Error::new(HttpResponseError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: format!("unable to get stats of {}: {}", name, err),
+          });
+          let _ = tx.send(Err::<_, web::error::Error>(err));
+          break;
+        }
+        Ok(stats) => gen_instance_stats(&name, &stats),
+      };
+      let data = match serde_json::to_string(&stats) {
+        Err(err) => {
+          let err = web::error::Error::new(HttpResponseError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: format!("{:?}", err),
+          });
+          let _ = tx.send(Err::<_, web::error::Error>(err));
+          break;
+        }
+        Ok(data) => data,
+      };
+      let response = format!("{}\n{}\n", data.len(), data);
+      if tx
+        .send(Ok::<_, web::error::Error>(Bytes::from(response)))
+        .is_err()
+      {
+        break;
+      }
+    }
+    tx.close();
+  });
+  rx_body
+}
+
+/// Forward the outputs of an exec instance to a websocket
+/// Each output is sent as a text message holding a json
+/// [CargoInstanceOutput](CargoInstanceOutput) so stdout and stderr can be told apart.
+async fn forward_exec_output(
+  id: String,
+  mut output: Pin<
+    Box<dyn Stream<Item = Result<LogOutput, DockerError>> + Send>,
+  >,
+  sink: web::ws::WsSink,
+) {
+  while let Some(output) = output.next().await {
+    let output = match output {
+      Err(err) => {
+        log::warn!("unable to read output of exec {}: {}", &id, err);
+        break;
+      }
+      Ok(output) => gen_instance_output(&id, output),
+    };
+    let data = match serde_json::to_string(&output) {
+      Err(err) => {
+        log::warn!("unable to serialize output of exec {}: {}", &id, err);
+        break;
+      }
+      Ok(data) => data,
+    };
+    if sink
+      .send(web::ws::Message::Text(data.into()))
+      .await
+      .is_err()
+    {
+      return;
+    }
+  }
+  let _ = sink.send(web::ws::Message::Close(None)).await;
+}
+
+/// Forward the input received from a websocket to an exec instance
+async fn forward_exec_input(
+  id: String,
+  mut input: Pin<Box<dyn AsyncWrite + Send>>,
+  mut rx: mpsc::Receiver<Bytes>,
+) {
+  while let Some(data) = rx.next().await {
+    if let Err(err) = input.write_all(&data).await {
+      log::warn!("unable to write input of exec {}: {}", &id, err);
+      break;
+    }
+  }
+}
+
+/// Start an exec instance attached to a websocket
+/// Text and binary messages of the client are written to the stdin of the exec,
+/// fragmented messages are written as their fragments arrive,
+/// his stdout and stderr are sent back as
+/// [CargoInstanceOutput](CargoInstanceOutput) json messages.
+///
+/// ## Arguments
+/// - [id](String) The id of the exec instance
+/// - [sink](web::ws::WsSink) The websocket sink
+/// - [docker_api](Docker) The docker api
+///
+/// ## Return
+/// - [Result](Service) The websocket service handling the client messages
+/// - [Result](web::Error) An error if the exec can't be started
+pub async fn exec_ws_service(
+  id: String,
+  sink: web::ws::WsSink,
+  docker_api: Docker,
+) -> Result<
+  impl Service<
+    web::ws::Frame,
+    Response = Option<web::ws::Message>,
+    Error = io::Error,
+  >,
+  web::Error,
+> {
+  let res = docker_api
+    .start_exec(&id, None::<StartExecOptions>)
+    .await
+    .record_error()
+    .map_err(|err| web::error::Error::new(HttpResponseError::from(err)))?;
+  let (output, input) = match res {
+    StartExecResults::Attached { output, input } => (output, input),
+    StartExecResults::Detached => {
+      return Err(web::error::Error::new(HttpResponseError {
+        msg: format!("exec {} is detached", &id),
+        status: StatusCode::BAD_REQUEST,
+      }))
+    }
+  };
+  let (tx, rx) = mpsc::channel::<Bytes>();
+  rt::spawn(forward_exec_output(id.to_owned(), output, sink));
+  rt::spawn(forward_exec_input(id, input, rx));
+
+  Ok(fn_service(move |frame| {
+    let item = match frame {
+      web::ws::Frame::Text(data) | web::ws::Frame::Binary(data) => {
+        let _ = tx.send(data);
+        None
+      }
+      web::ws::Frame::Continuation(item) => {
+        let data = match item {
+          ws::Item::FirstText(data)
+          | ws::Item::FirstBinary(data)
+          | ws::Item::Continue(data)
+          | ws::Item::Last(data) => data,
+        };
+        let _ = tx.send(data);
+        None
+      }
+      web::ws::Frame::Ping(msg) => Some(web::ws::Message::Pong(msg)),
+      web::ws::Frame::Close(reason) => {
+        tx.close();
+        Some(web::ws::Message::Close(reason))
+      }
+      _ => None,
+    };
+    ready(Ok(item))
+  }))
+}
+
 /// ## Start a service
 /// Start service by it's name
 ///
@@ -132,7 +642,8 @@ pub async fn start_cargo_instance(
 ) -> Result<(), DockerError> {
   docker_api
     .start_container(name, None::<StartContainerOptions<String>>)
-    .await?;
+    .await
+    .record_error()?;
   Ok(())
 }
 
@@ -167,6 +678,66 @@ pub async fn get_cargo_instance_state(
   CargoInstanceState::Stopped
 }
 
+/// Get the name of a container without the leading slash
+///
+/// ## Arguments
+/// - [container](bollard::models::ContainerSummary) The container
+///
+/// ## Return
+/// - [String](String) The name of the container or his id if he has no name
+pub fn get_container_name(
+  container: &bollard::models::ContainerSummary,
+) -> String {
+  container
+    .names
+    .to_owned()
+    .unwrap_or_default()
+    .first()
+    .map(|name| name.trim_start_matches('/').to_owned())
+    .unwrap_or_else(|| container.id.to_owned().unwrap_or_default())
+}
+
+/// Get the value of a label of a container
+///
+/// ## Arguments
+/// - [container](bollard::models::ContainerSummary) The container
+/// - [label](str) The name of the label
+///
+/// ## Return
+/// - [Option](String) The value of the label if it exists
+pub fn get_container_label(
+  container: &bollard::models::ContainerSummary,
+  label: &str,
+) -> Option<String> {
+  container
+    .labels
+    .as_ref()
+    .and_then(|labels| labels.get(label).cloned())
+}
+
+/// Get the health of a container
+/// The container summary only expose it in his status like `Up 2 minutes (healthy)`
+///
+/// ## Arguments
+/// - [container](bollard::models::ContainerSummary) The container
+///
+/// ## Return
+/// - [CargoInstanceHealth](CargoInstanceHealth) The health of the container
+pub fn get_container_health(
+  container: &bollard::models::ContainerSummary,
+) -> CargoInstanceHealth {
+  let status = container.status.to_owned().unwrap_or_default();
+  if status.ends_with("(health: starting)") {
+    CargoInstanceHealth::Starting
+  } else if status.ends_with("(unhealthy)") {
+    CargoInstanceHealth::Unhealthy
+  } else if status.ends_with("(healthy)") {
+    CargoInstanceHealth::Healthy
+  } else {
+    CargoInstanceHealth::None
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use bollard::container::StopContainerOptions;
@@ -217,4 +788,31 @@ mod tests {
       .await?;
     Ok(())
   }
+
+  /// Test to get the health of containers from their status
+  #[test]
+  fn get_container_health_test() {
+    let cases = [
+      ("Up 2 minutes (healthy)", CargoInstanceHealth::Healthy),
+      ("Up 1 minute (unhealthy)", CargoInstanceHealth::Unhealthy),
+      (
+        "Up 3 seconds (health: starting)",
+        CargoInstanceHealth::Starting,
+      ),
+      ("Up 2 minutes", CargoInstanceHealth::None),
+    ];
+    for (status, health) in cases {
+      let container = bollard::models::ContainerSummary {
+        status: Some(status.to_owned()),
+        ..Default::default()
+      };
+      assert_eq!(
+        get_container_health(&container),
+        health,
+        "Expect status {} to be {:?}",
+        status,
+        health
+      );
+    }
+  }
 }
@@ -1,24 +1,83 @@
-use std::path::Path;
+use std::sync::Mutex;
+use std::path::{Path, PathBuf};
 use ntex::http::StatusCode;
 use std::collections::HashMap;
 use futures::{StreamExt, stream};
 use serde::{Serialize, Deserialize};
 use futures::stream::FuturesUnordered;
+use bollard::models::HealthStatusEnum;
 
 use crate::models::DaemonConfig;
+use crate::utils::metrics::DockerErrorMetric;
 use crate::{utils, controllers, repositories};
 use crate::models::{
   Pool, ClusterItem, CargoItem, ClusterNetworkItem, CargoInstancePartial,
   CargoEnvItem, ProxyTemplateModes, CargoInstanceItem, CreateCargoInstanceOpts,
+  ProxyTemplateItem, DnsEntryItem,
 };
 
-use crate::errors::{HttpResponseError, IntoHttpResponseError};
+use crate::errors::HttpResponseError;
+
+/// Name of the containers about to be removed
+/// They are left out of the proxy targets and never started
+static DRAINING_CONTAINERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Keep containers out of the proxy targets until it's dropped
+pub struct DrainingGuard {
+  names: Vec<String>,
+}
+
+impl Drop for DrainingGuard {
+  fn drop(&mut self) {
+    let mut containers = match DRAINING_CONTAINERS.lock() {
+      Ok(containers) => containers,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+    containers.retain(|name| !self.names.contains(name));
+  }
+}
+
+/// Leave containers out of the proxy targets before they are removed
+/// They are given back when the returned guard is dropped
+///
+/// ## Arguments
+/// - [names](Vec<String>) The name of the containers
+///
+/// ## Return
+/// - [DrainingGuard](DrainingGuard) The guard of the containers
+pub fn drain_containers(names: &[String]) -> DrainingGuard {
+  let mut containers = match DRAINING_CONTAINERS.lock() {
+    Ok(containers) => containers,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  containers.extend(names.iter().cloned());
+  DrainingGuard {
+    names: names.to_vec(),
+  }
+}
+
+/// Check if a container is about to be removed
+///
+/// ## Arguments
+/// - [container](bollard::models::ContainerSummary) The container to check
+///
+/// ## Return
+/// - [bool](bool) True if the container must not be targeted
+pub fn is_draining(container: &bollard::models::ContainerSummary) -> bool {
+  let name = utils::cargo_instance::get_container_name(container);
+  match DRAINING_CONTAINERS.lock() {
+    Ok(containers) => containers.contains(&name),
+    Err(poisoned) => poisoned.into_inner().contains(&name),
+  }
+}
 
 pub struct JoinCargoOptions {
   pub(crate) cargo: CargoItem,
   pub(crate) cluster: ClusterItem,
   pub(crate) network: ClusterNetworkItem,
   pub(crate) is_creating_relation: bool,
+  /// Indexes of the replicas to create, every replicas are created if None
+  pub(crate) indexes: Option<Vec<i64>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -31,6 +90,8 @@ pub struct TemplateData {
   vars: Option<HashMap<String, String>>,
   cargoes: HashMap<String, CargoTemplateData>,
   networks: Option<HashMap<String, NetworkTemplateData>>,
+  /// Paths of the uploaded certificates inside the proxy by name
+  certificates: HashMap<String, utils::certificate::CertificateTemplateData>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +100,9 @@ pub struct CargoTemplateData {
   target_ip: String,
   dns_entry: Option<String>,
   target_ips: Vec<String>,
+  /// Paths of the ACME certificate of the dns entry inside the proxy
+  ssl_certificate: Option<String>,
+  ssl_certificate_key: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,29 +127,58 @@ pub async fn list_containers(
     filters,
     ..Default::default()
   });
-  let containers = docker_api.list_containers(options).await?;
+  let containers = docker_api.list_containers(options).await.record_error()?;
 
   Ok(containers)
 }
 
-async fn start_containers(
-  containers: Vec<bollard::models::ContainerSummary>,
+/// List every containers of a cluster whatever cargo they belong to
+///
+/// ## Arguments
+/// - [cluster_key](str) The cluster key
+/// - [docker_api](bollard::Docker) Docker api reference
+///
+/// ## Return
+/// - [Result](Vec<bollard::models::ContainerSummary>) The containers of the cluster
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn list_cluster_containers(
+  cluster_key: &str,
+  docker_api: &bollard::Docker,
+) -> Result<Vec<bollard::models::ContainerSummary>, HttpResponseError> {
+  let target_cluster = format!("cluster={}", &cluster_key);
+  let mut filters = HashMap::new();
+  filters.insert("label", vec![target_cluster.as_str()]);
+  let options = Some(bollard::container::ListContainersOptions {
+    all: true,
+    filters,
+    ..Default::default()
+  });
+  let containers = docker_api.list_containers(options).await.record_error()?;
+
+  Ok(containers)
+}
+
+/// Get the addresses of containers in a network
+/// Unhealthy containers are left out of the targets
+/// unless none is healthy to keep the proxy templates valid
+async fn get_target_ips(
+  container_ids: Vec<String>,
   network_key: &str,
   docker_api: &bollard::Docker,
 ) -> Result<Vec<String>, HttpResponseError> {
-  log::info!("Starting cargoes");
-  let target_ips = containers
+  let target_ips = container_ids
     .into_iter()
-    .map(|container| async move {
-      let container_id = container.id.unwrap_or_default();
-      log::info!("starting container {}", &container_id);
-      let state = container.state.unwrap_or_default();
-      if state != "running" {
-        utils::cargo_instance::start_cargo_instance(&container_id, docker_api)
-          .await?;
-      }
-      log::info!("successfully started container {}", &container_id);
-      let container = docker_api.inspect_container(&container_id, None).await?;
+    .map(|container_id| async move {
+      let container = docker_api
+        .inspect_container(&container_id, None)
+        .await
+        .record_error()?;
+      let is_unhealthy = container
+        .state
+        .as_ref()
+        .and_then(|state| state.health.as_ref())
+        .and_then(|health| health.status)
+        == Some(HealthStatusEnum::UNHEALTHY);
       let networks = container
         .network_settings
         .ok_or(HttpResponseError {
@@ -112,26 +205,78 @@ async fn start_containers(
             ),
             status: StatusCode::INTERNAL_SERVER_ERROR,
           })?;
-        Ok::<String, HttpResponseError>(ip_address.into())
+        Ok::<(String, bool), HttpResponseError>((
+          ip_address.into(),
+          is_unhealthy,
+        ))
       } else {
-        Ok::<String, HttpResponseError>(String::from("127.0.0.1"))
+        Ok::<(String, bool), HttpResponseError>((
+          String::from("127.0.0.1"),
+          is_unhealthy,
+        ))
       };
     })
     .collect::<FuturesUnordered<_>>()
     .collect::<Vec<_>>()
     .await
     .into_iter()
+    .collect::<Result<Vec<(String, bool)>, HttpResponseError>>()?;
+  let healthy_ips = target_ips
+    .iter()
+    .filter(|(_, is_unhealthy)| !is_unhealthy)
+    .map(|(ip_address, _)| ip_address.to_owned())
+    .collect::<Vec<String>>();
+  if healthy_ips.is_empty() && !target_ips.is_empty() {
+    log::warn!("every containers are unhealthy, keeping them as targets");
+    return Ok(
+      target_ips
+        .into_iter()
+        .map(|(ip_address, _)| ip_address)
+        .collect(),
+    );
+  }
+  Ok(healthy_ips)
+}
+
+async fn start_containers(
+  containers: Vec<bollard::models::ContainerSummary>,
+  network_key: &str,
+  docker_api: &bollard::Docker,
+) -> Result<Vec<String>, HttpResponseError> {
+  log::info!("Starting cargoes");
+  let container_ids = containers
+    .into_iter()
+    .map(|container| async move {
+      let container_id = container.id.unwrap_or_default();
+      log::info!("starting container {}", &container_id);
+      let state = container.state.unwrap_or_default();
+      if state != "running" {
+        utils::cargo_instance::start_cargo_instance(&container_id, docker_api)
+          .await?;
+      }
+      log::info!("successfully started container {}", &container_id);
+      Ok::<String, HttpResponseError>(container_id)
+    })
+    .collect::<FuturesUnordered<_>>()
+    .collect::<Vec<_>>()
+    .await
+    .into_iter()
     .collect::<Result<Vec<String>, HttpResponseError>>()?;
   log::info!("all cargo started");
-  Ok(target_ips)
+  get_target_ips(container_ids, network_key, docker_api).await
 }
 
-async fn start_cluster_cargoes(
+/// Generate the template data of the cargoes of a cluster
+/// Containers are started when `is_starting` is true,
+/// otherwise only the running containers are used as targets
+async fn gen_cluster_cargoes(
   cluster_cargoes: Vec<CargoInstanceItem>,
+  is_starting: bool,
+  config: &DaemonConfig,
   docker_api: &bollard::Docker,
   pool: &Pool,
-) -> Result<Vec<CargoTemplateData>, HttpResponseError> {
-  cluster_cargoes
+) -> Result<HashMap<String, CargoTemplateData>, HttpResponseError> {
+  let cargoes = cluster_cargoes
     .into_iter()
     .map(|cluster_cargo| async move {
       let cargo_key = &cluster_cargo.cargo_key;
@@ -141,23 +286,45 @@ async fn start_cluster_cargoes(
         &cluster_cargo.cargo_key,
         docker_api,
       )
-      .await?;
+      .await?
+      .into_iter()
+      .filter(|container| !is_draining(container))
+      .collect::<Vec<_>>();
 
       let cargo =
         repositories::cargo::find_by_key(cargo_key.to_owned(), pool).await?;
 
-      let mut target_ips =
-        start_containers(containers, network_key, docker_api).await?;
+      let mut target_ips = if is_starting {
+        start_containers(containers, network_key, docker_api).await?
+      } else {
+        let container_ids = containers
+          .into_iter()
+          .filter(|container| container.state.as_deref() == Some("running"))
+          .filter_map(|container| container.id)
+          .collect::<Vec<String>>();
+        get_target_ips(container_ids, network_key, docker_api).await?
+      };
       target_ips.reverse();
       let target_ip = match target_ips.get(0) {
         None => String::new(),
         Some(target_ip) => target_ip.to_owned(),
       };
+      let certificate = cargo
+        .dns_entry
+        .as_ref()
+        .and_then(|dns_entry| dns_entry.split_once(':'))
+        .and_then(|(_, domain)| utils::acme::get_certificate(config, domain));
+      let (ssl_certificate, ssl_certificate_key) = match certificate {
+        None => (None, None),
+        Some((certificate, key)) => (Some(certificate), Some(key)),
+      };
       let cargo_template_data = CargoTemplateData {
         name: cargo.name,
         dns_entry: cargo.dns_entry,
         target_ip,
         target_ips,
+        ssl_certificate,
+        ssl_certificate_key,
       };
       Ok::<CargoTemplateData, HttpResponseError>(cargo_template_data)
     })
@@ -165,7 +332,121 @@ async fn start_cluster_cargoes(
     .collect::<Vec<_>>()
     .await
     .into_iter()
-    .collect::<Result<Vec<CargoTemplateData>, HttpResponseError>>()
+    .collect::<Result<Vec<CargoTemplateData>, HttpResponseError>>()?
+    .into_iter()
+    .fold(HashMap::new(), |mut acc, item| {
+      acc.insert(item.name.to_owned(), item);
+      acc
+    });
+  Ok(cargoes)
+}
+
+/// Generate the data used to render the proxy templates of a cluster
+async fn gen_template_data(
+  cluster: &ClusterItem,
+  cargoes: HashMap<String, CargoTemplateData>,
+  pool: &Pool,
+) -> Result<TemplateData, HttpResponseError> {
+  let cluster_vars = repositories::cluster_variable::list_by_cluster(
+    cluster.key.to_owned(),
+    pool,
+  )
+  .await?;
+  let vars = utils::cluster_variable::cluster_vars_to_hashmap(cluster_vars);
+
+  let networks =
+    repositories::cluster_network::list_for_cluster(cluster.to_owned(), pool)
+      .await?
+      .into_iter()
+      .fold(HashMap::new(), |mut acc, network| {
+        acc.insert(
+          network.name.to_owned(),
+          NetworkTemplateData {
+            gateway: network.default_gateway,
+          },
+        );
+        acc
+      });
+
+  let certificates = utils::certificate::list_template_data(pool).await?;
+
+  Ok(TemplateData {
+    vars: Some(vars),
+    networks: Some(networks),
+    cargoes,
+    certificates,
+  })
+}
+
+/// Render a proxy template of a cluster without writing it
+/// The data are the same than when the cluster is started
+/// but stopped containers are not started so they are not in the targets
+///
+/// ## Arguments
+/// - [cluster](ClusterItem) The cluster
+/// - [template_name](str) The name of the proxy template
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api reference
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](String) The rendered proxy config
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn render_proxy_template(
+  cluster: &ClusterItem,
+  template_name: &str,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<String, HttpResponseError> {
+  if !cluster
+    .proxy_templates
+    .iter()
+    .any(|name| name == template_name)
+  {
+    return Err(HttpResponseError {
+      msg: format!(
+        "proxy template {} is not used by cluster {}",
+        template_name, &cluster.key
+      ),
+      status: StatusCode::NOT_FOUND,
+    });
+  }
+  let template =
+    repositories::proxy_template::get_by_name(template_name.to_owned(), pool)
+      .await?;
+  let cluster_cargoes = repositories::cargo_instance::get_by_cluster_key(
+    cluster.key.to_owned(),
+    pool,
+  )
+  .await?;
+  let cargoes =
+    gen_cluster_cargoes(cluster_cargoes, false, config, docker_api, pool)
+      .await?;
+  let template_data = gen_template_data(cluster, cargoes, pool).await?;
+  utils::render_template(template.content, &template_data)
+}
+
+/// Path of the config file of a proxy template rendered for a cluster
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [cluster_key](str) The cluster key
+/// - [template](ProxyTemplateItem) The proxy template
+///
+/// ## Return
+/// - [PathBuf](PathBuf) The path of the config file
+pub fn gen_template_file_path(
+  config: &DaemonConfig,
+  cluster_key: &str,
+  template: &ProxyTemplateItem,
+) -> PathBuf {
+  let file_path = Path::new(&config.state_dir);
+  let file_path = match template.mode {
+    ProxyTemplateModes::Http => file_path.join("nginx/sites-enabled"),
+    ProxyTemplateModes::Stream => file_path.join("nginx/streams-enabled"),
+  };
+  file_path.join(format!("{}.{}.conf", cluster_key, &template.name))
 }
 
 pub async fn start(
@@ -179,36 +460,22 @@ pub async fn start(
     pool,
   )
   .await?;
+  let cargo_keys = cluster_cargoes
+    .iter()
+    .map(|cluster_cargo| cluster_cargo.cargo_key.to_owned())
+    .collect::<Vec<String>>();
 
-  let cargoes = start_cluster_cargoes(cluster_cargoes, docker_api, pool)
-    .await?
-    .into_iter()
-    .fold(HashMap::new(), |mut acc, item| {
-      acc.insert(item.name.to_owned(), item);
-      acc
-    });
+  let cargoes =
+    gen_cluster_cargoes(cluster_cargoes, true, config, docker_api, pool)
+      .await?;
+  repositories::cargo_instance::set_started_by_cluster_key(
+    cluster.key.to_owned(),
+    pool,
+  )
+  .await?;
 
   if !cluster.proxy_templates.is_empty() {
-    let cluster_vars = repositories::cluster_variable::list_by_cluster(
-      cluster.key.to_owned(),
-      pool,
-    )
-    .await?;
-    let vars = utils::cluster_variable::cluster_vars_to_hashmap(cluster_vars);
-
-    let networks =
-      repositories::cluster_network::list_for_cluster(cluster.to_owned(), pool)
-        .await?
-        .into_iter()
-        .fold(HashMap::new(), |mut acc, network| {
-          acc.insert(
-            network.name.to_owned(),
-            NetworkTemplateData {
-              gateway: network.default_gateway,
-            },
-          );
-          acc
-        });
+    let template_data = gen_template_data(cluster, cargoes, pool).await?;
 
     let mut templates = stream::iter(&cluster.proxy_templates);
 
@@ -218,86 +485,81 @@ pub async fn start(
         pool,
       )
       .await?;
-      let file_path = Path::new(&config.state_dir);
-      let file_path = match template.mode {
-        ProxyTemplateModes::Http => file_path.join("nginx/sites-enabled"),
-        ProxyTemplateModes::Stream => file_path.join("nginx/streams-enabled"),
-      };
-      let file_name = format!("{}.{}", &cluster.key, &template.name);
-      let file_path = file_path.join(format!("{file_name}.conf"));
-      let template_data = TemplateData {
-        vars: Some(vars.to_owned()),
-        networks: Some(networks.to_owned()),
-        cargoes: cargoes.to_owned(),
-      };
-
+      let file_path = gen_template_file_path(config, &cluster.key, &template);
       let config_file =
         utils::render_template(template.content, &template_data)?;
-      tokio::fs::write(&file_path, config_file)
-        .await
-        .map_err(|err| HttpResponseError {
-          msg: format!(
-            "Unable to write config file {} {}",
-            &file_path.display(),
-            err
-          ),
-          status: StatusCode::INTERNAL_SERVER_ERROR,
-        })?;
+      controllers::proxy::apply_config(
+        &file_path,
+        Some(config_file),
+        docker_api,
+      )
+      .await?;
 
-      let mut cargoes = stream::iter(&cargoes);
+      // Ignore error if we can't reload the proxy config
+      let _ = controllers::proxy::reload_config(docker_api).await;
+    }
 
-      while let Some((_, item)) = cargoes.next().await {
-        if item.dns_entry.is_none() {
-          continue;
-        }
-        let item_string =
-          serde_json::to_string(&item).map_err(|err| HttpResponseError {
-            msg: format!("{}", err),
-            status: StatusCode::INTERNAL_SERVER_ERROR,
-          })?;
+    let mut cargoes = stream::iter(&template_data.cargoes);
 
-        let item: CargoTemplateData = serde_json::from_str(
-          &utils::render_template(item_string, &template_data)?,
-        )
-        .map_err(|err| HttpResponseError {
+    while let Some((name, item)) = cargoes.next().await {
+      if item.dns_entry.is_none() {
+        continue;
+      }
+      let item_string =
+        serde_json::to_string(&item).map_err(|err| HttpResponseError {
           msg: format!("{}", err),
           status: StatusCode::INTERNAL_SERVER_ERROR,
         })?;
 
-        let domain = item.dns_entry.ok_or(HttpResponseError {
-          msg: String::from("Unexpected error domain should not be null"),
-          status: StatusCode::INTERNAL_SERVER_ERROR,
-        })?;
-
-        let dns_settings = domain.split(':').collect::<Vec<_>>();
-
-        if dns_settings.len() != 2 {
-          return Err(HttpResponseError {
-            msg: String::from("Error dns settings have incorrect format"),
-            status: StatusCode::BAD_REQUEST,
-          });
-        }
-
-        controllers::dns::add_dns_entry(
-          dns_settings[1],
-          dns_settings[0],
-          &config.state_dir,
-        )
-        .await
-        .map_err(|err| err.to_http_error())?;
+      let item: CargoTemplateData = serde_json::from_str(
+        &utils::render_template(item_string, &template_data)?,
+      )
+      .map_err(|err| HttpResponseError {
+        msg: format!("{}", err),
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+      })?;
+
+      let domain = item.dns_entry.ok_or(HttpResponseError {
+        msg: String::from("Unexpected error domain should not be null"),
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+      })?;
+
+      let dns_settings = domain.split(':').collect::<Vec<_>>();
+
+      if dns_settings.len() != 2 {
+        return Err(HttpResponseError {
+          msg: String::from("Error dns settings have incorrect format"),
+          status: StatusCode::BAD_REQUEST,
+        });
       }
 
-      // Ignore error if we can't restart the dns server
-      let _ = controllers::dns::restart(docker_api).await;
-      // Ignore error if we can't reload the proxy config
-      let _ = controllers::proxy::reload_config(docker_api).await;
+      // Cargoes of a cluster are in the namespace of the cluster
+      let cargo_key = utils::key::gen_key(&cluster.namespace, name);
+      let dns_entry = DnsEntryItem {
+        cargo_instance_key: utils::key::gen_key(&cluster.key, &cargo_key),
+        domain: dns_settings[1].to_owned(),
+        ip_address: dns_settings[0].to_owned(),
+      };
+      repositories::dns_entry::upsert(dns_entry, pool).await?;
     }
+
+    controllers::dns::sync_dns_entries(&config.state_dir, pool).await?;
+    // Ignore error if we can't restart the dns server
+    let _ = controllers::dns::restart(docker_api).await;
   }
+
+  // Routes target the containers of their cargo in every clusters
+  for cargo_key in cargo_keys {
+    utils::proxy_route::sync_cargo_routes(&cargo_key, config, docker_api, pool)
+      .await?;
+  }
+  controllers::dns::sync_service_records(&config.state_dir, docker_api).await?;
   Ok(())
 }
 
 pub async fn join_cargo(
   opts: &JoinCargoOptions,
+  config: &DaemonConfig,
   docker_api: &bollard::Docker,
   pool: &Pool,
 ) -> Result<Vec<String>, HttpResponseError> {
@@ -353,12 +615,18 @@ pub async fn join_cargo(
       acc
     })
     .to_vec();
+  let indexes = match opts.indexes {
+    Some(ref indexes) => indexes.to_owned(),
+    None => (0..opts.cargo.replicas).collect::<Vec<i64>>(),
+  };
   let create_opts = CreateCargoInstanceOpts {
     cargo: &opts.cargo,
     network_key: &opts.network.key,
     cluster_name: &opts.cluster.name,
     labels: Some(&mut labels),
     environnements,
+    indexes,
+    dns_search: controllers::dns::get_settings(config).await?.search_domains,
   };
 
   let container_ids =
@@ -394,7 +662,8 @@ pub async fn join_cargo(
       };
       docker_api
         .connect_network(&opts.network.key, config)
-        .await?;
+        .await
+        .record_error()?;
       Ok::<(), HttpResponseError>(())
     })
     .collect::<FuturesUnordered<_>>()
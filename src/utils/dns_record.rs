@@ -0,0 +1,313 @@
+//! Validation of the dns records written in the dnsmasq configuration
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use regex::Regex;
+use ntex::http::StatusCode;
+
+use crate::{utils, repositories};
+use crate::errors::HttpResponseError;
+use crate::models::{
+  Pool, DnsEntryItem, DnsRecordPartial, DnsRecordItem, DnsRecordKinds,
+};
+
+/// Ensure a dns record is valid
+/// Values are written as is in the dnsmasq configuration
+/// so the characters that could change the meaning of an option are refused
+///
+/// ## Arguments
+/// - [record](DnsRecordPartial) The record to validate
+///
+/// ## Return
+/// - [Result](()) The record is valid
+/// - [Result](HttpResponseError) An http response error if the record is not valid
+pub fn validate(record: &DnsRecordPartial) -> Result<(), HttpResponseError> {
+  let gen_error = |msg: &str| HttpResponseError {
+    msg: msg.to_owned(),
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  };
+  let domain_reg = Regex::new(r"^[a-zA-Z0-9_-]+(\.[a-zA-Z0-9_-]+)*$").unwrap();
+  let name_reg = Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap();
+  if !name_reg.is_match(&record.name) {
+    return Err(gen_error(
+      "name must only contain alphanumeric characters, - and _",
+    ));
+  }
+  if !domain_reg.is_match(&record.domain) {
+    return Err(gen_error("domain is not a valid domain"));
+  }
+  match record.kind {
+    DnsRecordKinds::A => {
+      if record.value.parse::<Ipv4Addr>().is_err() {
+        return Err(gen_error("value of A records must be an ipv4 address"));
+      }
+    }
+    DnsRecordKinds::Aaaa => {
+      if record.value.parse::<Ipv6Addr>().is_err() {
+        return Err(gen_error("value of AAAA records must be an ipv6 address"));
+      }
+    }
+    DnsRecordKinds::Cname | DnsRecordKinds::Srv => {
+      if !domain_reg.is_match(&record.value) {
+        return Err(gen_error("value must be the domain of the target"));
+      }
+    }
+    DnsRecordKinds::Txt => {
+      if record.value.is_empty()
+        || record.value.len() > 255
+        || record.value.chars().any(|c| c == '"' || c.is_control())
+      {
+        return Err(gen_error(
+          "value of TXT records must be a text of 1 to 255 characters without quote",
+        ));
+      }
+    }
+  }
+  if record.kind != DnsRecordKinds::Srv {
+    if record.port.is_some()
+      || record.priority.is_some()
+      || record.weight.is_some()
+    {
+      return Err(gen_error(
+        "port, priority and weight are only valid for SRV records",
+      ));
+    }
+    return Ok(());
+  }
+  match record.port {
+    Some(port) if (1..=65535).contains(&port) => {}
+    Some(_) => return Err(gen_error("port must be between 1 and 65535")),
+    None => return Err(gen_error("port is required for SRV records")),
+  }
+  for value in [record.priority, record.weight].into_iter().flatten() {
+    if !(0..=65535).contains(&value) {
+      return Err(gen_error("priority and weight must be between 0 and 65535"));
+    }
+  }
+  Ok(())
+}
+
+/// Create a dns record item from a dns record partial
+///
+/// ## Arguments
+/// - [namespace](str) The namespace of the record
+/// - [record](DnsRecordPartial) The record partial
+///
+/// ## Return
+/// - [DnsRecordItem](DnsRecordItem) The record item
+pub fn gen_item(namespace: &str, record: DnsRecordPartial) -> DnsRecordItem {
+  let is_srv = record.kind == DnsRecordKinds::Srv;
+  DnsRecordItem {
+    key: utils::key::gen_key(namespace, &record.name),
+    name: record.name,
+    namespace_name: namespace.to_owned(),
+    kind: record.kind,
+    domain: record.domain,
+    value: record.value,
+    port: record.port,
+    priority: record.priority.or(if is_srv { Some(0) } else { None }),
+    weight: record.weight.or(if is_srv { Some(0) } else { None }),
+  }
+}
+
+/// Ensure a dns record doesn't conflict with the other records
+/// A domain belongs to the namespace of his first record,
+/// a CNAME record must be the only record of his domain
+/// and target a domain with a record or a dns entry
+fn check_conflicts(
+  item: &DnsRecordItem,
+  records: &[DnsRecordItem],
+  entries: &[DnsEntryItem],
+) -> Result<(), HttpResponseError> {
+  let others = records
+    .iter()
+    .filter(|record| record.key != item.key)
+    .collect::<Vec<_>>();
+  let same_domain = others
+    .iter()
+    .filter(|record| record.domain == item.domain)
+    .collect::<Vec<_>>();
+  if let Some(record) = same_domain
+    .iter()
+    .find(|record| record.namespace_name != item.namespace_name)
+  {
+    return Err(HttpResponseError {
+      msg: format!(
+        "domain {} is used by namespace {}",
+        &item.domain, &record.namespace_name
+      ),
+      status: StatusCode::CONFLICT,
+    });
+  }
+  let has_cname = item.kind == DnsRecordKinds::Cname
+    || same_domain
+      .iter()
+      .any(|record| record.kind == DnsRecordKinds::Cname);
+  if has_cname && !same_domain.is_empty() {
+    return Err(HttpResponseError {
+      msg: format!(
+        "domain {} can't have a CNAME record and other records",
+        &item.domain
+      ),
+      status: StatusCode::CONFLICT,
+    });
+  }
+  if item.kind != DnsRecordKinds::Cname {
+    return Ok(());
+  }
+  // A dns entry resolve every of his subdomains too
+  let is_known = others.iter().any(|record| record.domain == item.value)
+    || entries.iter().any(|entry| {
+      item.value == entry.domain
+        || item.value.ends_with(&format!(".{}", entry.domain))
+    });
+  if !is_known {
+    return Err(HttpResponseError {
+      msg: format!(
+        "value {} must be the domain of a dns record or a dns entry",
+        &item.value
+      ),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    });
+  }
+  Ok(())
+}
+
+/// Ensure a dns record doesn't conflict with the records and dns entries in store
+///
+/// ## Arguments
+/// - [item](DnsRecordItem) The record to create or update
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](()) The record can be stored
+/// - [Result](HttpResponseError) An http response error if the record conflict with another one
+pub async fn validate_conflicts(
+  item: &DnsRecordItem,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let records = repositories::dns_record::list(pool).await?;
+  let entries = repositories::dns_entry::list(pool).await?;
+  check_conflicts(item, &records, &entries)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn gen_record(
+    kind: DnsRecordKinds,
+    domain: &str,
+    value: &str,
+  ) -> DnsRecordPartial {
+    DnsRecordPartial {
+      name: String::from("test"),
+      kind,
+      domain: domain.to_owned(),
+      value: value.to_owned(),
+      port: None,
+      priority: None,
+      weight: None,
+    }
+  }
+
+  #[test]
+  fn validate_test() {
+    let record =
+      gen_record(DnsRecordKinds::A, "api.nanocl.internal", "10.0.0.1");
+    assert!(validate(&record).is_ok());
+    let record = gen_record(DnsRecordKinds::A, "api.nanocl.internal", "::1");
+    assert!(
+      validate(&record).is_err(),
+      "Expect ipv6 in A record to fail"
+    );
+    let record = gen_record(DnsRecordKinds::Aaaa, "api.nanocl.internal", "::1");
+    assert!(validate(&record).is_ok());
+    let record = gen_record(
+      DnsRecordKinds::Cname,
+      "www.nanocl.internal",
+      "api.nanocl.internal",
+    );
+    assert!(validate(&record).is_ok());
+    let record =
+      gen_record(DnsRecordKinds::A, "api.nanocl.internal/", "10.0.0.1");
+    assert!(validate(&record).is_err(), "Expect invalid domain to fail");
+    let record =
+      gen_record(DnsRecordKinds::Txt, "nanocl.internal", "v=spf1 -all");
+    assert!(validate(&record).is_ok());
+    let record =
+      gen_record(DnsRecordKinds::Txt, "nanocl.internal", "a\nserver=1.1.1.1");
+    assert!(
+      validate(&record).is_err(),
+      "Expect new line in TXT record to fail"
+    );
+    let mut record = gen_record(
+      DnsRecordKinds::Srv,
+      "_http._tcp.nanocl.internal",
+      "api.nanocl.internal",
+    );
+    assert!(
+      validate(&record).is_err(),
+      "Expect SRV record without port to fail"
+    );
+    record.port = Some(9000);
+    assert!(validate(&record).is_ok());
+    let item = gen_item("global", record);
+    assert_eq!(item.key, "global-test");
+    assert_eq!((item.priority, item.weight), (Some(0), Some(0)));
+    let mut record =
+      gen_record(DnsRecordKinds::A, "api.nanocl.internal", "10.0.0.1");
+    record.port = Some(9000);
+    assert!(
+      validate(&record).is_err(),
+      "Expect port in A record to fail"
+    );
+  }
+
+  #[test]
+  fn check_conflicts_test() {
+    let record = gen_item(
+      "global",
+      gen_record(DnsRecordKinds::A, "api.nanocl.internal", "10.0.0.1"),
+    );
+    let records = vec![record.to_owned()];
+    let entries = vec![DnsEntryItem {
+      cargo_instance_key: String::from("global-dev-global-api"),
+      domain: String::from("nanocl.io"),
+      ip_address: String::from("10.0.0.2"),
+    }];
+    assert!(check_conflicts(&record, &records, &entries).is_ok());
+    let mut item = gen_item(
+      "other",
+      gen_record(DnsRecordKinds::A, "api.nanocl.internal", "10.0.0.3"),
+    );
+    let err = check_conflicts(&item, &records, &entries)
+      .expect_err("Expect a domain of another namespace to conflict");
+    assert_eq!(err.status, StatusCode::CONFLICT);
+    item.namespace_name = String::from("global");
+    item.key = String::from("global-other");
+    assert!(check_conflicts(&item, &records, &entries).is_ok());
+    let mut item = gen_item(
+      "global",
+      gen_record(
+        DnsRecordKinds::Cname,
+        "api.nanocl.internal",
+        "www.nanocl.internal",
+      ),
+    );
+    item.key = String::from("global-cname");
+    let err = check_conflicts(&item, &records, &entries)
+      .expect_err("Expect a CNAME record to be alone on his domain");
+    assert_eq!(err.status, StatusCode::CONFLICT);
+    item.domain = String::from("www.nanocl.internal");
+    let err = check_conflicts(&item, &records, &entries)
+      .expect_err("Expect a CNAME record to an unknown domain to fail");
+    assert_eq!(err.status, StatusCode::UNPROCESSABLE_ENTITY);
+    item.value = String::from("api.nanocl.internal");
+    assert!(check_conflicts(&item, &records, &entries).is_ok());
+    item.value = String::from("www.nanocl.io");
+    assert!(
+      check_conflicts(&item, &records, &entries).is_ok(),
+      "Expect a CNAME record to a subdomain of a dns entry to be valid"
+    );
+  }
+}
@@ -0,0 +1,97 @@
+//! Validation of the dns settings written in the dnsmasq configuration
+use std::net::IpAddr;
+
+use regex::Regex;
+use ntex::http::StatusCode;
+
+use crate::errors::HttpResponseError;
+use crate::models::DnsSettings;
+
+/// Maximum cache size allowed by dnsmasq
+const MAX_CACHE_SIZE: u32 = 10000;
+
+/// Ensure the dns settings are valid
+/// Values are written as is in the dnsmasq configuration
+/// so the characters that could change the meaning of an option are refused
+///
+/// ## Arguments
+/// - [settings](DnsSettings) The settings to validate
+///
+/// ## Return
+/// - [Result](()) The settings are valid
+/// - [Result](HttpResponseError) An http response error if the settings are not valid
+pub fn validate(settings: &DnsSettings) -> Result<(), HttpResponseError> {
+  let gen_error = |msg: &str| HttpResponseError {
+    msg: msg.to_owned(),
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  };
+  let is_valid_server = |server: &str| {
+    let (addr, port) = match server.split_once('#') {
+      None => (server, None),
+      Some((addr, port)) => (addr, Some(port)),
+    };
+    addr.parse::<IpAddr>().is_ok()
+      && port.map_or(true, |port| port.parse::<u16>().is_ok())
+  };
+  if !settings
+    .servers
+    .iter()
+    .all(|server| is_valid_server(server))
+  {
+    return Err(gen_error(
+      "servers must be ip addresses with an optional port as ip#port",
+    ));
+  }
+  let interface_reg = Regex::new(r"^[a-zA-Z0-9_.-]+$").unwrap();
+  if settings.interfaces.is_empty()
+    || !settings
+      .interfaces
+      .iter()
+      .all(|interface| interface_reg.is_match(interface))
+  {
+    return Err(gen_error("interfaces must be a list of network interfaces"));
+  }
+  if settings.cache_size > MAX_CACHE_SIZE {
+    return Err(gen_error("cache_size must be between 0 and 10000"));
+  }
+  let domain_reg = Regex::new(r"^[a-zA-Z0-9_-]+(\.[a-zA-Z0-9_-]+)*$").unwrap();
+  if !settings
+    .search_domains
+    .iter()
+    .all(|domain| domain_reg.is_match(domain))
+  {
+    return Err(gen_error("search_domains must be valid domains"));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validate_test() {
+    let mut settings = DnsSettings::default();
+    assert!(validate(&settings).is_ok());
+    settings.servers =
+      vec![String::from("10.0.0.53#5353"), String::from("::1")];
+    assert!(validate(&settings).is_ok());
+    settings.servers = vec![String::from("10.0.0.53\nport=0")];
+    assert!(
+      validate(&settings).is_err(),
+      "Expect invalid server to fail"
+    );
+    settings.servers = Vec::new();
+    settings.interfaces = Vec::new();
+    assert!(validate(&settings).is_err(), "Expect no interface to fail");
+    settings.interfaces = vec![String::from("eth0")];
+    settings.cache_size = 20000;
+    assert!(validate(&settings).is_err(), "Expect too big cache to fail");
+    settings.cache_size = 0;
+    settings.search_domains = vec![String::from("corp.internal/")];
+    assert!(
+      validate(&settings).is_err(),
+      "Expect invalid domain to fail"
+    );
+  }
+}
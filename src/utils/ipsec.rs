@@ -8,6 +8,7 @@ use bollard::models::{HostConfig, PortBinding, DeviceMapping, Ipam, IpamConfig};
 use bollard::network::CreateNetworkOptions;
 
 use crate::models::DaemonConfig;
+use crate::utils::metrics::DockerErrorMetric;
 
 use super::utils::{*, self};
 
@@ -112,7 +113,7 @@ async fn create_ipsec_network(docker_api: &Docker) -> Result<(), DockerError> {
     },
     ..Default::default()
   };
-  docker_api.create_network(config).await?;
+  docker_api.create_network(config).await.record_error()?;
   Ok(())
 }
 
@@ -144,7 +145,7 @@ async fn create_ipsec_container(
     domainname: Some(name),
     ..Default::default()
   };
-  docker_api.create_container(options, config).await?;
+  docker_api.create_container(options, config).await.record_error()?;
 
   Ok(())
 }
@@ -0,0 +1,564 @@
+//! Metrics of the daemon and his workloads in the prometheus text format
+use std::sync::Mutex;
+use std::pin::Pin;
+use std::future::Future;
+use std::time::Instant;
+use std::fmt::Display;
+use std::collections::HashMap;
+use std::task::{Context, Poll};
+
+use ntex::web;
+use ntex::service::{Service, Transform};
+use bollard::errors::Error as DockerError;
+use bollard::container::ListContainersOptions;
+
+use crate::{utils, repositories};
+use crate::errors::HttpResponseError;
+use crate::models::Pool;
+
+/// Upper bounds in seconds of the buckets of the http request durations
+const DURATION_BUCKETS: [f64; 10] =
+  [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0];
+
+/// Count and durations of the http requests of a route
+struct HttpRequestMetric {
+  method: String,
+  route: String,
+  status: u16,
+  count: u64,
+  sum: f64,
+  /// Cumulative count of requests for each duration bucket
+  buckets: [u64; DURATION_BUCKETS.len()],
+}
+
+/// Http requests handled since the daemon started
+static HTTP_REQUESTS: Mutex<Vec<HttpRequestMetric>> = Mutex::new(Vec::new());
+
+/// Errors returned by the docker api by status code
+/// Errors without status code are counted with the status 0
+static DOCKER_ERRORS: Mutex<Vec<(u16, u64)>> = Mutex::new(Vec::new());
+
+/// Record an http request handled by the server
+///
+/// ## Arguments
+/// - [method](str) The method of the request
+/// - [route](str) The route pattern that matched the request
+/// - [status](u16) The status of the response
+/// - [duration](f64) The time spent to handle the request in seconds
+pub fn record_http_request(
+  method: &str,
+  route: &str,
+  status: u16,
+  duration: f64,
+) {
+  let mut requests = match HTTP_REQUESTS.lock() {
+    Ok(requests) => requests,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  let index = requests.iter().position(|metric| {
+    metric.method == method && metric.route == route && metric.status == status
+  });
+  let metric = match index {
+    Some(index) => &mut requests[index],
+    None => {
+      requests.push(HttpRequestMetric {
+        method: method.to_owned(),
+        route: route.to_owned(),
+        status,
+        count: 0,
+        sum: 0.0,
+        buckets: [0; DURATION_BUCKETS.len()],
+      });
+      let last = requests.len() - 1;
+      &mut requests[last]
+    }
+  };
+  metric.count += 1;
+  metric.sum += duration;
+  for (bucket, bound) in metric.buckets.iter_mut().zip(DURATION_BUCKETS) {
+    if duration <= bound {
+      *bucket += 1;
+    }
+  }
+}
+
+/// Record an error returned by the docker api
+/// Not found is expected when checking if something exists so it's ignored
+///
+/// ## Arguments
+/// - [err](DockerError) The error returned by the docker api
+pub fn record_docker_error(err: &DockerError) {
+  let status = match err {
+    DockerError::DockerResponseServerError {
+      status_code: 404, ..
+    } => return,
+    DockerError::DockerResponseServerError { status_code, .. } => *status_code,
+    _ => 0,
+  };
+  let mut errors = match DOCKER_ERRORS.lock() {
+    Ok(errors) => errors,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  match errors.iter_mut().find(|(code, _)| *code == status) {
+    Some((_, count)) => *count += 1,
+    None => errors.push((status, 1)),
+  }
+}
+
+/// Record the error of a docker api call before it's propagated
+pub trait DockerErrorMetric {
+  fn record_error(self) -> Self;
+}
+
+impl<T> DockerErrorMetric for Result<T, DockerError> {
+  fn record_error(self) -> Self {
+    if let Err(err) = &self {
+      record_docker_error(err);
+    }
+    self
+  }
+}
+
+/// Escape a label value as required by the prometheus text format
+fn escape_label(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+fn write_header(metrics: &mut String, name: &str, kind: &str, help: &str) {
+  metrics.push_str(&format!("# HELP {} {}\n", name, help));
+  metrics.push_str(&format!("# TYPE {} {}\n", name, kind));
+}
+
+fn write_sample(
+  metrics: &mut String,
+  name: &str,
+  labels: &[(&str, &str)],
+  value: impl Display,
+) {
+  let labels = labels
+    .iter()
+    .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+    .collect::<Vec<String>>()
+    .join(",");
+  if labels.is_empty() {
+    metrics.push_str(&format!("{} {}\n", name, value));
+  } else {
+    metrics.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+  }
+}
+
+fn write_http_requests(metrics: &mut String) {
+  let requests = match HTTP_REQUESTS.lock() {
+    Ok(requests) => requests,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  write_header(
+    metrics,
+    "nanocl_http_requests_total",
+    "counter",
+    "Number of http requests handled",
+  );
+  for metric in requests.iter() {
+    let status = metric.status.to_string();
+    let labels = [
+      ("method", metric.method.as_str()),
+      ("route", metric.route.as_str()),
+      ("status", status.as_str()),
+    ];
+    write_sample(metrics, "nanocl_http_requests_total", &labels, metric.count);
+  }
+  write_header(
+    metrics,
+    "nanocl_http_request_duration_seconds",
+    "histogram",
+    "Time spent to handle http requests",
+  );
+  for metric in requests.iter() {
+    let status = metric.status.to_string();
+    let labels = [
+      ("method", metric.method.as_str()),
+      ("route", metric.route.as_str()),
+      ("status", status.as_str()),
+    ];
+    for (count, bound) in metric.buckets.iter().zip(DURATION_BUCKETS) {
+      let bound = bound.to_string();
+      let labels = [labels[0], labels[1], labels[2], ("le", bound.as_str())];
+      write_sample(
+        metrics,
+        "nanocl_http_request_duration_seconds_bucket",
+        &labels,
+        count,
+      );
+    }
+    let labels = [labels[0], labels[1], labels[2], ("le", "+Inf")];
+    write_sample(
+      metrics,
+      "nanocl_http_request_duration_seconds_bucket",
+      &labels,
+      metric.count,
+    );
+    let labels = [labels[0], labels[1], labels[2]];
+    write_sample(
+      metrics,
+      "nanocl_http_request_duration_seconds_sum",
+      &labels,
+      metric.sum,
+    );
+    write_sample(
+      metrics,
+      "nanocl_http_request_duration_seconds_count",
+      &labels,
+      metric.count,
+    );
+  }
+}
+
+fn write_docker_errors(metrics: &mut String) {
+  let errors = match DOCKER_ERRORS.lock() {
+    Ok(errors) => errors,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  write_header(
+    metrics,
+    "nanocl_docker_errors_total",
+    "counter",
+    "Number of errors returned by the docker api",
+  );
+  for (status, count) in errors.iter() {
+    let status = status.to_string();
+    write_sample(
+      metrics,
+      "nanocl_docker_errors_total",
+      &[("status", status.as_str())],
+      count,
+    );
+  }
+}
+
+fn write_pool(metrics: &mut String, pool: &Pool) {
+  let state = pool.state();
+  write_header(
+    metrics,
+    "nanocl_store_connections",
+    "gauge",
+    "Number of connections to the store",
+  );
+  write_sample(metrics, "nanocl_store_connections", &[], state.connections);
+  write_header(
+    metrics,
+    "nanocl_store_connections_idle",
+    "gauge",
+    "Number of idle connections to the store",
+  );
+  write_sample(
+    metrics,
+    "nanocl_store_connections_idle",
+    &[],
+    state.idle_connections,
+  );
+  write_header(
+    metrics,
+    "nanocl_store_connections_max",
+    "gauge",
+    "Maximum number of connections to the store",
+  );
+  write_sample(
+    metrics,
+    "nanocl_store_connections_max",
+    &[],
+    pool.max_size(),
+  );
+}
+
+/// Write the counts of the resources and the replicas of every cargoes
+async fn write_resources(
+  metrics: &mut String,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let mut filters = HashMap::new();
+  filters.insert(String::from("label"), vec![String::from("cargo")]);
+  let options = Some(ListContainersOptions::<String> {
+    all: true,
+    filters,
+    ..Default::default()
+  });
+  let containers = docker_api.list_containers(options).await.record_error()?;
+
+  let namespaces = repositories::namespace::list(pool).await?;
+  write_header(
+    metrics,
+    "nanocl_namespaces",
+    "gauge",
+    "Number of namespaces",
+  );
+  write_sample(metrics, "nanocl_namespaces", &[], namespaces.len());
+
+  let mut clusters = String::new();
+  let mut cargoes = String::new();
+  let mut instances = String::new();
+  let mut desired = String::new();
+  let mut running = String::new();
+  for namespace in namespaces {
+    let labels = [("namespace", namespace.name.as_str())];
+    let count =
+      repositories::cluster::count(namespace.name.to_owned(), pool).await?;
+    write_sample(&mut clusters, "nanocl_clusters", &labels, count.count);
+    let count = containers
+      .iter()
+      .filter(|container| {
+        utils::cargo_instance::get_container_label(container, "namespace")
+          .as_deref()
+          == Some(namespace.name.as_str())
+      })
+      .count();
+    write_sample(&mut instances, "nanocl_cargo_instances", &labels, count);
+    let items =
+      repositories::cargo::find_by_namespace(namespace.to_owned(), pool)
+        .await?;
+    write_sample(&mut cargoes, "nanocl_cargoes", &labels, items.len());
+    for cargo in items {
+      let labels = [
+        ("namespace", namespace.name.as_str()),
+        ("cargo", cargo.key.as_str()),
+      ];
+      let joined_clusters = repositories::cargo_instance::find_by_cargo_key(
+        cargo.key.to_owned(),
+        pool,
+      )
+      .await?
+      .len() as i64;
+      write_sample(
+        &mut desired,
+        "nanocl_cargo_replicas_desired",
+        &labels,
+        cargo.replicas * joined_clusters,
+      );
+      let count = containers
+        .iter()
+        .filter(|container| {
+          container.state.as_deref() == Some("running")
+            && utils::cargo_instance::get_container_label(container, "cargo")
+              .as_deref()
+              == Some(cargo.key.as_str())
+        })
+        .count();
+      write_sample(
+        &mut running,
+        "nanocl_cargo_replicas_running",
+        &labels,
+        count,
+      );
+    }
+  }
+  write_header(
+    metrics,
+    "nanocl_clusters",
+    "gauge",
+    "Number of clusters by namespace",
+  );
+  metrics.push_str(&clusters);
+  write_header(
+    metrics,
+    "nanocl_cargoes",
+    "gauge",
+    "Number of cargoes by namespace",
+  );
+  metrics.push_str(&cargoes);
+  write_header(
+    metrics,
+    "nanocl_cargo_instances",
+    "gauge",
+    "Number of cargo instances by namespace",
+  );
+  metrics.push_str(&instances);
+  write_header(
+    metrics,
+    "nanocl_cargo_replicas_desired",
+    "gauge",
+    "Number of replicas a cargo should have across his clusters",
+  );
+  metrics.push_str(&desired);
+  write_header(
+    metrics,
+    "nanocl_cargo_replicas_running",
+    "gauge",
+    "Number of running replicas of a cargo",
+  );
+  metrics.push_str(&running);
+  Ok(())
+}
+
+/// Gather the metrics of the daemon and his workloads
+///
+/// ## Arguments
+/// - [docker_api](bollard::Docker) The docker api
+/// - [pool](Pool) The database pool
+///
+/// ## Return
+/// - [Result](String) The metrics in the prometheus text format
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn gather(
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<String, HttpResponseError> {
+  let mut metrics = String::new();
+  write_http_requests(&mut metrics);
+  write_docker_errors(&mut metrics);
+  write_pool(&mut metrics, pool);
+  write_resources(&mut metrics, docker_api, pool).await?;
+  Ok(metrics)
+}
+
+/// Marker of the requests that didn't match any route
+struct UnmatchedRoute;
+
+/// Default service of the server answering the requests that didn't match any route
+/// They are recorded under the `unmatched` route
+pub async fn unmatched_route(req: web::HttpRequest) -> web::HttpResponse {
+  req.extensions_mut().insert(UnmatchedRoute);
+  web::HttpResponse::NotFound().finish()
+}
+
+/// Generate the route pattern of a request from his path
+/// Matched parameters are replaced by their name to keep the number of routes low
+/// eg: `/cargoes/my-cargo/logs` become `/cargoes/{name}/logs`
+/// and requests that didn't match any route become `unmatched`
+fn gen_route(req: &web::HttpRequest) -> String {
+  if req.extensions().contains::<UnmatchedRoute>() {
+    return String::from("unmatched");
+  }
+  let mut segments = req
+    .path()
+    .split('/')
+    .map(String::from)
+    .collect::<Vec<String>>();
+  for (name, value) in req.match_info().iter() {
+    if value.is_empty() {
+      continue;
+    }
+    // A parameter can match several segments
+    let value = value.split('/').collect::<Vec<&str>>();
+    let start = segments.windows(value.len()).position(|window| {
+      window
+        .iter()
+        .zip(&value)
+        .all(|(segment, value)| segment == value)
+    });
+    if let Some(start) = start {
+      segments.splice(start..start + value.len(), [format!("{{{}}}", name)]);
+    }
+  }
+  segments.join("/")
+}
+
+/// Middleware recording the count and duration of the http requests
+pub struct HttpMetrics;
+
+impl<S> Transform<S> for HttpMetrics {
+  type Service = HttpMetricsMiddleware<S>;
+
+  fn new_transform(&self, service: S) -> Self::Service {
+    HttpMetricsMiddleware { service }
+  }
+}
+
+pub struct HttpMetricsMiddleware<S> {
+  service: S,
+}
+
+impl<S, Err> Service<web::WebRequest<Err>> for HttpMetricsMiddleware<S>
+where
+  S: Service<web::WebRequest<Err>, Response = web::WebResponse>,
+  S::Future: 'static,
+{
+  type Response = web::WebResponse;
+  type Error = S::Error;
+  type Future =
+    Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+  fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.service.poll_ready(cx)
+  }
+
+  fn poll_shutdown(&self, cx: &mut Context<'_>, is_error: bool) -> Poll<()> {
+    self.service.poll_shutdown(cx, is_error)
+  }
+
+  fn call(&self, req: web::WebRequest<Err>) -> Self::Future {
+    let start = Instant::now();
+    let fut = self.service.call(req);
+    Box::pin(async move {
+      let res = fut.await?;
+      let req = res.request();
+      record_http_request(
+        req.method().as_str(),
+        &gen_route(req),
+        res.response().status().as_u16(),
+        start.elapsed().as_secs_f64(),
+      );
+      Ok(res)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn http_requests_test() {
+    record_http_request("GET", "/tests/{name}", 200, 0.02);
+    record_http_request("GET", "/tests/{name}", 200, 3.0);
+    record_http_request("GET", "/tests/{name}", 404, 0.02);
+    let mut metrics = String::new();
+    write_http_requests(&mut metrics);
+    let labels = "method=\"GET\",route=\"/tests/{name}\",status=\"200\"";
+    assert!(metrics
+      .contains(&format!("nanocl_http_requests_total{{{}}} 2\n", labels)));
+    assert!(metrics.contains(&format!(
+      "nanocl_http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1\n",
+      labels
+    )));
+    assert!(metrics.contains(&format!(
+      "nanocl_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n",
+      labels
+    )));
+    assert_eq!(
+      metrics
+        .matches(
+          "nanocl_http_request_duration_seconds_count{method=\"GET\",route=\"/tests/{name}\","
+        )
+        .count(),
+      2,
+      "Expect one duration series by status"
+    );
+  }
+
+  #[test]
+  fn docker_errors_test() {
+    let gen_error = |status_code| {
+      Err::<(), _>(DockerError::DockerResponseServerError {
+        status_code,
+        message: String::from("test"),
+      })
+    };
+    assert!(gen_error(418).record_error().is_err());
+    assert!(gen_error(404).record_error().is_err());
+    let mut metrics = String::new();
+    write_docker_errors(&mut metrics);
+    assert!(metrics.contains("nanocl_docker_errors_total{status=\"418\"} 1\n"));
+    assert!(
+      !metrics.contains("status=\"404\""),
+      "Expect not found errors to be ignored"
+    );
+  }
+
+  #[test]
+  fn escape_label_test() {
+    assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+  }
+}
@@ -10,6 +10,17 @@ pub mod cargo_image;
 pub mod cargo_instance;
 pub mod cluster_network;
 pub mod cluster_variable;
+pub mod state_file;
+pub mod reconciler;
+pub mod autoscaler;
+pub mod metrics;
+pub mod nginx_log;
+pub mod proxy_route;
+pub mod proxy_template;
+pub mod acme;
+pub mod certificate;
+pub mod dns_record;
+pub mod dns_setting;
 
 pub mod errors;
 
@@ -90,12 +101,21 @@ pub mod tests {
   }
 
   pub async fn generate_server(config: Config) -> test::TestServer {
-    before();
     // Build a test daemon config
     let daemon_config = DaemonConfig {
       state_dir: String::from("/var/lib/nanocl"),
       ..Default::default()
     };
+    generate_server_with_config(config, daemon_config).await
+  }
+
+  /// Generate a test server with his own daemon config
+  /// Used by the tests that must not change the state of the daemon
+  pub async fn generate_server_with_config(
+    config: Config,
+    daemon_config: DaemonConfig,
+  ) -> test::TestServer {
+    before();
     // Create docker_api
     let docker_api = gen_docker_client();
     // Create postgres pool
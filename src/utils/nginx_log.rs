@@ -0,0 +1,585 @@
+//! Ingestion of the access logs of the proxy into the store
+use std::io::SeekFrom;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use ntex::rt;
+use ntex::http::StatusCode;
+use ntex::time::{sleep, Seconds};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+use crate::{utils, controllers, repositories};
+use crate::errors::HttpResponseError;
+use crate::models::{
+  Pool, DaemonConfig, NginxLogPartial, NginxLogItem, NginxLogQuery,
+  NginxLogFilter, NginxLogGroupBy, NginxLogRequestCount, NginxLogLatency,
+};
+
+/// Interval between two reads of the access logs
+const INGEST_INTERVAL: Seconds = Seconds(5);
+
+/// Number of ingestions between two purges of the old logs
+const PURGE_EVERY: u32 = 720;
+
+/// Maximum number of bytes read from the access logs at once
+const MAX_READ: u64 = 4 * 1024 * 1024;
+
+/// Maximum number of logs inserted in a single query
+const MAX_BATCH: usize = 1000;
+
+/// Default size in seconds of the time buckets
+const DEFAULT_INTERVAL: i64 = 3600;
+
+/// Smallest size in seconds of the time buckets
+const MIN_INTERVAL: i64 = 60;
+
+/// Default and maximum number of items in a top
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 100;
+
+/// Access logs file relative to the state directory
+const LOG_FILE: &str = "nginx/log/access.json.log";
+
+/// Position of the access logs already stored relative to the state directory
+const LOG_TAIL_FILE: &str = "nginx/log_tail.json";
+
+/// Log format configuration relative to the state directory
+/// Included in the http context of the proxy so it apply to every sites
+const LOG_FORMAT_FILE: &str = "nginx/sites-enabled/nanocl-log-format.conf";
+
+/// Nginx configuration writing the access logs as json
+const LOG_FORMAT: &str = r#"log_format nanocl_json escape=json '{'
+  '"date_gmt":"$time_iso8601",'
+  '"uri":"$uri",'
+  '"host":"$host",'
+  '"remote_addr":"$remote_addr",'
+  '"realip_remote_addr":"$realip_remote_addr",'
+  '"server_protocol":"$server_protocol",'
+  '"request_method":"$request_method",'
+  '"content_length":"$content_length",'
+  '"status":"$status",'
+  '"request_time":"$request_time",'
+  '"body_bytes_sent":"$body_bytes_sent",'
+  '"proxy_host":"$proxy_host",'
+  '"upstream_addr":"$upstream_addr",'
+  '"query_string":"$query_string",'
+  '"request_body":"$request_body",'
+  '"content_type":"$content_type",'
+  '"http_user_agent":"$http_user_agent",'
+  '"http_referrer":"$http_referer",'
+  '"http_accept_language":"$http_accept_language"'
+'}';
+
+access_log /var/log/nginx/access.json.log nanocl_json;
+"#;
+
+/// Convert an empty nginx variable to None
+fn optional(value: String) -> Option<String> {
+  if value.is_empty() || value == "-" {
+    return None;
+  }
+  Some(value)
+}
+
+/// Parse an access log line written with the nanocl log format
+///
+/// ## Arguments
+/// - [line](str) The access log line
+///
+/// ## Return
+/// - [Result](NginxLogItem) The parsed nginx log
+/// - [Result](HttpResponseError) An http response error if the line is not valid
+pub fn parse_line(line: &str) -> Result<NginxLogItem, HttpResponseError> {
+  let gen_error = |msg: String| HttpResponseError {
+    msg,
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  };
+  let log = serde_json::from_str::<NginxLogPartial>(line)
+    .map_err(|err| gen_error(format!("invalid nginx log {}", err)))?;
+  let date_gmt = chrono::DateTime::parse_from_rfc3339(&log.date_gmt)
+    .map_err(|err| gen_error(format!("invalid nginx log date {}", err)))?
+    .with_timezone(&chrono::Utc);
+  Ok(NginxLogItem {
+    key: uuid::Uuid::new_v4(),
+    date_gmt,
+    uri: log.uri,
+    host: log.host,
+    remote_addr: log.remote_addr,
+    realip_remote_addr: log.realip_remote_addr,
+    server_protocol: log.server_protocol,
+    request_method: log.request_method,
+    content_length: log.content_length.parse().unwrap_or_default(),
+    status: log.status.parse().unwrap_or_default(),
+    request_time: log.request_time.parse().unwrap_or_default(),
+    body_bytes_sent: log.body_bytes_sent.parse().unwrap_or_default(),
+    proxy_host: optional(log.proxy_host),
+    upstream_addr: optional(log.upstream_addr),
+    query_string: optional(log.query_string),
+    request_body: optional(log.request_body),
+    content_type: optional(log.content_type),
+    http_user_agent: optional(log.http_user_agent),
+    http_referrer: optional(log.http_referrer),
+    http_accept_language: optional(log.http_accept_language),
+  })
+}
+
+/// Position in a log file of the lines already read
+/// The inode of the file is kept to detect a rotation
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct LogTailState {
+  inode: u64,
+  offset: u64,
+}
+
+/// Follow a log file, his position is saved to resume after a restart
+pub struct LogTail {
+  path: PathBuf,
+  state_path: PathBuf,
+  state: LogTailState,
+}
+
+impl LogTail {
+  /// Start to follow a log file from the position saved in `state_path`
+  /// The file is followed from his current end when no position is saved
+  /// and from his start when it has been rotated or truncated since
+  pub async fn new(path: PathBuf, state_path: PathBuf) -> Self {
+    let saved = fs::read(&state_path).await.ok().and_then(|content| {
+      serde_json::from_slice::<LogTailState>(&content).ok()
+    });
+    let state = match (fs::metadata(&path).await, saved) {
+      (Err(_), _) => LogTailState::default(),
+      (Ok(metadata), Some(saved)) => {
+        if saved.inode == metadata.ino() && saved.offset <= metadata.len() {
+          saved
+        } else {
+          LogTailState {
+            inode: metadata.ino(),
+            offset: 0,
+          }
+        }
+      }
+      (Ok(metadata), None) => LogTailState {
+        inode: metadata.ino(),
+        offset: metadata.len(),
+      },
+    };
+    LogTail {
+      path,
+      state_path,
+      state,
+    }
+  }
+
+  /// Read the lines written since the last read
+  /// The file is read from the start again when it has been truncated or rotated
+  /// An incomplete last line is kept for the next read
+  pub async fn read_lines(&mut self) -> std::io::Result<Vec<String>> {
+    let mut file = match fs::File::open(&self.path).await {
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        self.state = LogTailState::default();
+        return Ok(Vec::new());
+      }
+      Err(err) => return Err(err),
+      Ok(file) => file,
+    };
+    let metadata = file.metadata().await?;
+    if metadata.ino() != self.state.inode || metadata.len() < self.state.offset
+    {
+      self.state = LogTailState {
+        inode: metadata.ino(),
+        offset: 0,
+      };
+    }
+    file.seek(SeekFrom::Start(self.state.offset)).await?;
+    let mut buf = Vec::new();
+    file.take(MAX_READ).read_to_end(&mut buf).await?;
+    let end = match buf.iter().rposition(|byte| *byte == b'\n') {
+      None => return Ok(Vec::new()),
+      Some(index) => index + 1,
+    };
+    self.state.offset += end as u64;
+    let lines = String::from_utf8_lossy(&buf[..end])
+      .lines()
+      .filter(|line| !line.is_empty())
+      .map(String::from)
+      .collect();
+    Ok(lines)
+  }
+
+  /// Save the position of the lines already read
+  /// Called once they are stored so they are not lost on a restart
+  pub async fn save(&self) -> std::io::Result<()> {
+    let content = serde_json::to_vec(&self.state)?;
+    fs::write(&self.state_path, content).await
+  }
+}
+
+/// Ensure the proxy write his access logs with the nanocl log format
+/// The proxy is reloaded when the configuration changed
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+pub async fn ensure_log_format(
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+) -> Result<(), HttpResponseError> {
+  let path = Path::new(&config.state_dir).join(LOG_FORMAT_FILE);
+  if let Ok(content) = fs::read_to_string(&path).await {
+    if content == LOG_FORMAT {
+      return Ok(());
+    }
+  }
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)
+      .await
+      .map_err(|err| HttpResponseError {
+        msg: format!("unable to create {}: {}", parent.display(), err),
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+      })?;
+  }
+  fs::write(&path, LOG_FORMAT)
+    .await
+    .map_err(|err| HttpResponseError {
+      msg: format!("unable to write {}: {}", path.display(), err),
+      status: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+  if let Err(err) = controllers::proxy::reload_config(docker_api).await {
+    log::warn!("unable to reload proxy with the log format: {}", err);
+  }
+  Ok(())
+}
+
+/// Store access log lines by batch
+/// Invalid lines are reported in the logs and skipped
+async fn ingest(lines: Vec<String>, pool: &Pool) {
+  let items = lines
+    .iter()
+    .filter_map(|line| match parse_line(line) {
+      Err(err) => {
+        log::warn!("skipping nginx log: {}", err);
+        None
+      }
+      Ok(item) => Some(item),
+    })
+    .collect::<Vec<NginxLogItem>>();
+  for batch in items.chunks(MAX_BATCH) {
+    if let Err(err) =
+      repositories::nginx_log::create_many(batch.to_vec(), pool).await
+    {
+      log::warn!("unable to store nginx logs: {}", err);
+    }
+  }
+}
+
+/// Delete the access logs older than the retention
+async fn purge(config: &DaemonConfig, pool: &Pool) {
+  if config.nginx_log_retention == 0 {
+    return;
+  }
+  let date = chrono::Utc::now()
+    - chrono::Duration::days(config.nginx_log_retention as i64);
+  match repositories::nginx_log::delete_older_than(date, pool).await {
+    Err(err) => log::warn!("unable to purge nginx logs: {}", err),
+    Ok(res) if res.count > 0 => {
+      log::debug!("purged {} nginx logs older than {}", res.count, date)
+    }
+    Ok(_) => {}
+  }
+}
+
+/// Spawn the ingestion loop of the access logs of the proxy
+/// Logs older than the retention of the daemon config are purged periodically
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub fn spawn(config: DaemonConfig, docker_api: bollard::Docker, pool: Pool) {
+  rt::spawn(async move {
+    if let Err(err) = ensure_log_format(&config, &docker_api).await {
+      log::warn!("{}", err);
+    }
+    let path = Path::new(&config.state_dir).join(LOG_FILE);
+    let state_path = Path::new(&config.state_dir).join(LOG_TAIL_FILE);
+    let mut tail = LogTail::new(path, state_path).await;
+    let mut iteration = 0;
+    loop {
+      if iteration % PURGE_EVERY == 0 {
+        purge(&config, &pool).await;
+      }
+      iteration = iteration.wrapping_add(1);
+      sleep(INGEST_INTERVAL).await;
+      match tail.read_lines().await {
+        Err(err) => log::warn!("unable to read nginx logs: {}", err),
+        Ok(lines) if lines.is_empty() => {}
+        Ok(lines) => {
+          ingest(lines, &pool).await;
+          if let Err(err) = tail.save().await {
+            log::warn!("unable to save nginx logs position: {}", err);
+          }
+        }
+      }
+    }
+  });
+}
+
+/// Get the domain of a cargo dns entry formated as `ip:domain`
+fn get_dns_entry_domain(dns_entry: &Option<String>) -> Option<String> {
+  let dns_entry = dns_entry.as_ref()?;
+  match dns_entry.split(':').collect::<Vec<_>>()[..] {
+    [_, domain] => Some(domain.to_owned()),
+    _ => None,
+  }
+}
+
+/// Get the domains of the cargoes of a cluster
+async fn list_cluster_domains(
+  cluster_key: String,
+  pool: &Pool,
+) -> Result<Vec<String>, HttpResponseError> {
+  repositories::cluster::find_by_key(cluster_key.to_owned(), pool).await?;
+  let instances =
+    repositories::cargo_instance::get_by_cluster_key(cluster_key, pool).await?;
+  let mut domains = Vec::new();
+  for instance in instances {
+    let cargo =
+      repositories::cargo::find_by_key(instance.cargo_key, pool).await?;
+    domains.extend(get_dns_entry_domain(&cargo.dns_entry));
+  }
+  Ok(domains)
+}
+
+/// Resolve the filter of the proxied http requests from a query
+/// The cluster and the cargo are converted to the domains of their dns entries
+///
+/// ## Arguments
+/// - [qs](NginxLogQuery) The query
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](NginxLogFilter) The filter of the requests
+/// - [Result](HttpResponseError) An http response error if the cluster or the cargo doesn't exist
+pub async fn gen_filter(
+  qs: &NginxLogQuery,
+  pool: &Pool,
+) -> Result<NginxLogFilter, HttpResponseError> {
+  let gen_date = |timestamp: i64| {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+      .map(|date| chrono::DateTime::from_utc(date, chrono::Utc))
+      .ok_or_else(|| HttpResponseError {
+        msg: format!("invalid timestamp {}", timestamp),
+        status: StatusCode::BAD_REQUEST,
+      })
+  };
+  let until = match qs.until {
+    None => chrono::Utc::now(),
+    Some(until) => gen_date(until)?,
+  };
+  let since = match qs.since {
+    None => until - chrono::Duration::days(1),
+    Some(since) => gen_date(since)?,
+  };
+  let mut hosts: Option<Vec<String>> = None;
+  if let Some(cluster) = &qs.cluster {
+    let key = utils::key::gen_key_from_nsp(&qs.namespace, cluster);
+    hosts = Some(list_cluster_domains(key, pool).await?);
+  }
+  if let Some(cargo) = &qs.cargo {
+    let key = utils::key::gen_key_from_nsp(&qs.namespace, cargo);
+    let cargo = repositories::cargo::find_by_key(key, pool).await?;
+    let domains = get_dns_entry_domain(&cargo.dns_entry)
+      .into_iter()
+      .collect::<Vec<String>>();
+    hosts = Some(match hosts {
+      None => domains,
+      Some(hosts) => hosts
+        .into_iter()
+        .filter(|host| domains.contains(host))
+        .collect(),
+    });
+  }
+  if let Some(host) = &qs.host {
+    hosts = Some(match hosts {
+      None => vec![host.to_owned()],
+      Some(hosts) => hosts.into_iter().filter(|item| item == host).collect(),
+    });
+  }
+  Ok(NginxLogFilter {
+    since,
+    until,
+    hosts,
+  })
+}
+
+/// Get the number of items of a top from a query
+pub fn get_limit(qs: &NginxLogQuery) -> i64 {
+  qs.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// Count requests by time bucket and host or uri
+///
+/// ## Arguments
+/// - [requests](Vec<(DateTime, String, String)>) The date, host and uri of the requests
+/// - [interval](i64) The size of the time buckets in seconds
+/// - [group_by](NginxLogGroupBy) Count by host or by host and uri
+///
+/// ## Return
+/// - [Vec](NginxLogRequestCount) The number of requests by time bucket, the oldest first
+pub fn count_requests(
+  requests: Vec<(chrono::DateTime<chrono::Utc>, String, String)>,
+  interval: i64,
+  group_by: NginxLogGroupBy,
+) -> Vec<NginxLogRequestCount> {
+  let interval = interval.max(MIN_INTERVAL);
+  let mut counts = BTreeMap::new();
+  for (date, host, uri) in requests {
+    let timestamp = date.timestamp();
+    let bucket = timestamp - timestamp.rem_euclid(interval);
+    let uri = match group_by {
+      NginxLogGroupBy::Host => None,
+      NginxLogGroupBy::Uri => Some(uri),
+    };
+    *counts.entry((bucket, host, uri)).or_insert(0) += 1;
+  }
+  counts
+    .into_iter()
+    .filter_map(|((bucket, host, uri), count)| {
+      let date = chrono::NaiveDateTime::from_timestamp_opt(bucket, 0)?;
+      Some(NginxLogRequestCount {
+        date: chrono::DateTime::from_utc(date, chrono::Utc),
+        host,
+        uri,
+        count,
+      })
+    })
+    .collect()
+}
+
+/// Get the interval of the time buckets from a query
+pub fn get_interval(qs: &NginxLogQuery) -> i64 {
+  qs.interval.unwrap_or(DEFAULT_INTERVAL)
+}
+
+/// Compute the percentiles of sorted request times using the nearest rank
+///
+/// ## Arguments
+/// - [times](Vec<f64>) The request times sorted from the fastest
+///
+/// ## Return
+/// - [NginxLogLatency](NginxLogLatency) The p50, p95 and p99 of the request times
+pub fn compute_latency(times: &[f64]) -> NginxLogLatency {
+  if times.is_empty() {
+    return NginxLogLatency::default();
+  }
+  let percentile = |rank: f64| {
+    let index = (rank * times.len() as f64).ceil() as usize;
+    times[index.clamp(1, times.len()) - 1]
+  };
+  NginxLogLatency {
+    count: times.len() as i64,
+    p50: percentile(0.50),
+    p95: percentile(0.95),
+    p99: percentile(0.99),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::utils::tests::*;
+
+  const LINE: &str = r#"{"date_gmt":"2022-12-10T10:00:00+01:00","uri":"/index.html","host":"example.com","remote_addr":"10.0.0.1","realip_remote_addr":"10.0.0.1","server_protocol":"HTTP/1.1","request_method":"GET","content_length":"","status":"200","request_time":"0.012","body_bytes_sent":"512","proxy_host":"","upstream_addr":"10.0.0.2:80","query_string":"","request_body":"","content_type":"","http_user_agent":"curl/7.81.0","http_referrer":"","http_accept_language":""}"#;
+
+  #[test]
+  fn compute_latency_test() {
+    let times = (1..=100).map(|time| time as f64).collect::<Vec<f64>>();
+    let latency = compute_latency(&times);
+    assert_eq!(latency.count, 100);
+    assert_eq!(latency.p50, 50.0);
+    assert_eq!(latency.p95, 95.0);
+    assert_eq!(latency.p99, 99.0);
+    assert_eq!(compute_latency(&[]).count, 0);
+  }
+
+  #[test]
+  fn count_requests_test() {
+    let date = |timestamp: i64| {
+      let date = chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0);
+      chrono::DateTime::from_utc(date.unwrap(), chrono::Utc)
+    };
+    let requests = vec![
+      (date(3600), String::from("a.com"), String::from("/")),
+      (date(3700), String::from("a.com"), String::from("/api")),
+      (date(3800), String::from("b.com"), String::from("/")),
+      (date(7300), String::from("a.com"), String::from("/")),
+    ];
+    let counts =
+      count_requests(requests.to_owned(), 3600, NginxLogGroupBy::Host);
+    let counts = counts
+      .iter()
+      .map(|item| (item.date.timestamp(), item.host.as_str(), item.count))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      counts,
+      vec![(3600, "a.com", 2), (3600, "b.com", 1), (7200, "a.com", 1)]
+    );
+    let counts = count_requests(requests, 3600, NginxLogGroupBy::Uri);
+    assert_eq!(counts.len(), 4);
+  }
+
+  #[test]
+  fn get_dns_entry_domain_test() {
+    let entry = Some(String::from("127.0.0.1:test.internal"));
+    assert_eq!(
+      get_dns_entry_domain(&entry),
+      Some(String::from("test.internal"))
+    );
+    assert_eq!(get_dns_entry_domain(&None), None);
+    assert_eq!(get_dns_entry_domain(&Some(String::from("bad"))), None);
+  }
+
+  #[test]
+  fn parse_line_test() {
+    let log = parse_line(LINE).unwrap();
+    assert_eq!(log.date_gmt.to_rfc3339(), "2022-12-10T09:00:00+00:00");
+    assert_eq!(log.status, 200);
+    assert_eq!(log.content_length, 0);
+    assert_eq!(log.request_time, 0.012);
+    assert_eq!(log.body_bytes_sent, 512);
+    assert_eq!(log.upstream_addr, Some(String::from("10.0.0.2:80")));
+    assert_eq!(log.proxy_host, None);
+    assert!(parse_line("not a json").is_err());
+  }
+
+  #[ntex::test]
+  async fn log_tail_test() -> TestRet {
+    let path = std::env::temp_dir().join("nanocl-log-tail-test.log");
+    let state_path = std::env::temp_dir().join("nanocl-log-tail-test.json");
+    let _ = fs::remove_file(&state_path).await;
+    fs::write(&path, "old\n").await?;
+    let mut tail = LogTail::new(path.to_owned(), state_path.to_owned()).await;
+    assert!(tail.read_lines().await?.is_empty());
+
+    fs::write(&path, "old\nfirst\nsecond\nincomplete").await?;
+    assert_eq!(tail.read_lines().await?, vec!["first", "second"]);
+    tail.save().await?;
+
+    // The position is resumed after a restart
+    fs::write(&path, "old\nfirst\nsecond\nincomplete line\n").await?;
+    let mut tail = LogTail::new(path.to_owned(), state_path.to_owned()).await;
+    assert_eq!(tail.read_lines().await?, vec!["incomplete line"]);
+
+    // The file has been rotated
+    fs::write(&path, "new\n").await?;
+    assert_eq!(tail.read_lines().await?, vec!["new"]);
+    fs::remove_file(&path).await?;
+    fs::remove_file(&state_path).await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,650 @@
+//! Generation of the proxy configuration from the routes
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use ntex::http::StatusCode;
+
+use crate::{utils, controllers, repositories};
+use crate::errors::HttpResponseError;
+use crate::models::{
+  Pool, DaemonConfig, ProxyRoutePartial, ProxyRouteItem, ProxyTemplateModes,
+  CargoInstanceHealth, GenericDelete,
+};
+
+/// Ensure a route is valid
+/// Values are written as is in the proxy configuration
+/// so the characters that could change the meaning of a directive are refused
+///
+/// ## Arguments
+/// - [route](ProxyRoutePartial) The route to validate
+///
+/// ## Return
+/// - [Result](()) The route is valid
+/// - [Result](HttpResponseError) An http response error if the route is not valid
+pub fn validate(route: &ProxyRoutePartial) -> Result<(), HttpResponseError> {
+  let gen_error = |msg: &str| HttpResponseError {
+    msg: msg.to_owned(),
+    status: StatusCode::UNPROCESSABLE_ENTITY,
+  };
+  let is_safe = |value: &str| {
+    !value.is_empty()
+      && !value.chars().any(|c| {
+        c.is_whitespace() || matches!(c, ';' | '{' | '}' | '"' | '\'' | '$')
+      })
+  };
+  let name_reg = Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap();
+  if !name_reg.is_match(&route.name) {
+    return Err(gen_error(
+      "name must only contain alphanumeric characters, - and _",
+    ));
+  }
+  if !(1..=65535).contains(&route.target_port) {
+    return Err(gen_error("target_port must be between 1 and 65535"));
+  }
+  match route.mode {
+    ProxyTemplateModes::Http => {
+      let host_reg = Regex::new(r"^[a-zA-Z0-9*.-]+$").unwrap();
+      match &route.host {
+        Some(host) if host_reg.is_match(host) => {}
+        Some(_) => return Err(gen_error("host is not a valid domain")),
+        None => return Err(gen_error("host is required for http routes")),
+      }
+      if let Some(path) = &route.path {
+        if !path.starts_with('/') || !is_safe(path) {
+          return Err(gen_error("path must be a prefix starting with /"));
+        }
+      }
+      if route.stream_port.is_some() {
+        return Err(gen_error("stream_port is only valid for stream routes"));
+      }
+    }
+    ProxyTemplateModes::Stream => {
+      match route.stream_port {
+        Some(port) if (1..=65535).contains(&port) => {}
+        Some(_) => {
+          return Err(gen_error("stream_port must be between 1 and 65535"))
+        }
+        None => {
+          return Err(gen_error("stream_port is required for stream routes"))
+        }
+      }
+      if route.host.is_some() || route.path.is_some() {
+        return Err(gen_error("host and path are only valid for http routes"));
+      }
+      if route.ssl_redirect.unwrap_or(false) {
+        return Err(gen_error("ssl_redirect is only valid for http routes"));
+      }
+    }
+  }
+  match (&route.ssl_certificate, &route.ssl_certificate_key) {
+    (None, None) => {
+      if route.ssl_redirect.unwrap_or(false) {
+        return Err(gen_error("ssl_redirect require a ssl certificate"));
+      }
+    }
+    (Some(certificate), Some(key)) => {
+      if !is_safe(certificate) || !is_safe(key) {
+        return Err(gen_error("ssl certificate and key must be valid paths"));
+      }
+    }
+    _ => {
+      return Err(gen_error(
+        "ssl_certificate and ssl_certificate_key must be set together",
+      ))
+    }
+  }
+  Ok(())
+}
+
+/// Create a route item from a route partial
+///
+/// ## Arguments
+/// - [namespace](str) The namespace of the route
+/// - [cargo_key](str) The key of the cargo targeted by the route
+/// - [route](ProxyRoutePartial) The route partial
+///
+/// ## Return
+/// - [ProxyRouteItem](ProxyRouteItem) The route item
+pub fn gen_item(
+  namespace: &str,
+  cargo_key: &str,
+  route: ProxyRoutePartial,
+) -> ProxyRouteItem {
+  ProxyRouteItem {
+    key: utils::key::gen_key(namespace, &route.name),
+    name: route.name,
+    namespace_name: namespace.to_owned(),
+    mode: route.mode,
+    host: route.host,
+    path: route.path.unwrap_or_else(|| String::from("/")),
+    cargo_key: cargo_key.to_owned(),
+    target_port: route.target_port,
+    stream_port: route.stream_port,
+    ssl_certificate: route.ssl_certificate,
+    ssl_certificate_key: route.ssl_certificate_key,
+    ssl_redirect: route.ssl_redirect.unwrap_or(false),
+  }
+}
+
+/// Ensure a http route use the same ssl options than the other routes of his host
+/// They share the same server in the proxy configuration
+///
+/// ## Arguments
+/// - [route](ProxyRouteItem) The route to check
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](()) The ssl options are the same
+/// - [Result](HttpResponseError) An http response error if they are not
+pub async fn ensure_host_ssl(
+  route: &ProxyRouteItem,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let host = match &route.host {
+    None => return Ok(()),
+    Some(host) => host,
+  };
+  let routes = repositories::proxy_route::find_by_host(host.to_owned(), pool)
+    .await?
+    .into_iter()
+    .filter(|item| item.key != route.key);
+  for item in routes {
+    if item.ssl_certificate != route.ssl_certificate
+      || item.ssl_certificate_key != route.ssl_certificate_key
+      || item.ssl_redirect != route.ssl_redirect
+    {
+      return Err(HttpResponseError {
+        msg: format!(
+          "route {} of host {} use different ssl options",
+          item.key, host
+        ),
+        status: StatusCode::CONFLICT,
+      });
+    }
+  }
+  Ok(())
+}
+
+/// List the addresses of the running containers of a cargo in every clusters
+/// Unhealthy containers are left out unless none is healthy
+async fn list_target_ips(
+  cargo_key: &str,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<String>, HttpResponseError> {
+  let instances =
+    repositories::cargo_instance::find_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?;
+  let mut targets = Vec::new();
+  for instance in instances {
+    let containers = utils::cluster::list_containers(
+      &instance.cluster_key,
+      cargo_key,
+      docker_api,
+    )
+    .await?;
+    for container in containers {
+      if container.state.as_deref() != Some("running")
+        || utils::cluster::is_draining(&container)
+      {
+        continue;
+      }
+      let ip_address = container
+        .network_settings
+        .as_ref()
+        .and_then(|settings| settings.networks.as_ref())
+        .and_then(|networks| networks.get(&instance.network_key))
+        .and_then(|network| network.ip_address.to_owned())
+        .filter(|ip_address| !ip_address.is_empty());
+      if let Some(ip_address) = ip_address {
+        let is_unhealthy =
+          utils::cargo_instance::get_container_health(&container)
+            == CargoInstanceHealth::Unhealthy;
+        targets.push((ip_address, is_unhealthy));
+      }
+    }
+  }
+  let healthy_ips = targets
+    .iter()
+    .filter(|(_, is_unhealthy)| !is_unhealthy)
+    .map(|(ip_address, _)| ip_address.to_owned())
+    .collect::<Vec<String>>();
+  if healthy_ips.is_empty() {
+    return Ok(
+      targets
+        .into_iter()
+        .map(|(ip_address, _)| ip_address)
+        .collect(),
+    );
+  }
+  Ok(healthy_ips)
+}
+
+/// Name of the upstream of a route in the proxy configuration
+fn gen_upstream_name(route: &ProxyRouteItem) -> String {
+  let key = route
+    .key
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+    .collect::<String>();
+  format!("nanocl_route_{}", key)
+}
+
+fn gen_upstream(route: &ProxyRouteItem, target_ips: &[String]) -> String {
+  let servers = target_ips
+    .iter()
+    .map(|ip_address| {
+      format!("  server {}:{};\n", ip_address, route.target_port)
+    })
+    .collect::<String>();
+  format!(
+    "upstream {} {{\n{}}}\n\n",
+    gen_upstream_name(route),
+    servers
+  )
+}
+
+fn gen_ssl(route: &ProxyRouteItem) -> String {
+  match (&route.ssl_certificate, &route.ssl_certificate_key) {
+    (Some(certificate), Some(key)) => format!(
+      "  ssl_certificate {};\n  ssl_certificate_key {};\n",
+      certificate, key
+    ),
+    _ => String::new(),
+  }
+}
+
+/// Generate the proxy configuration of the http routes of a host
+/// The routes share a server with a location by path prefix,
+/// a route without running container answer with a 503.
+///
+/// ## Arguments
+/// - [host](str) The host of the routes
+/// - [routes](Vec<(ProxyRouteItem, Vec<String>)>) The routes with the addresses of their targets
+///
+/// ## Return
+/// - [String](String) The proxy configuration
+pub fn gen_http_config(
+  host: &str,
+  routes: &[(ProxyRouteItem, Vec<String>)],
+) -> String {
+  let mut upstreams = String::new();
+  let mut locations = String::new();
+  for (route, target_ips) in routes {
+    locations.push_str(&format!("  location {} {{\n", route.path));
+    if target_ips.is_empty() {
+      locations.push_str("    return 503;\n  }\n");
+      continue;
+    }
+    upstreams.push_str(&gen_upstream(route, target_ips));
+    locations.push_str(&format!(
+      "    proxy_set_header upgrade $http_upgrade;
+    proxy_set_header connection \"upgrade\";
+    proxy_http_version 1.1;
+    proxy_set_header x-forwarded-for $proxy_add_x_forwarded_for;
+    proxy_set_header host $host;
+    proxy_pass http://{};
+  }}\n",
+      gen_upstream_name(route)
+    ));
+  }
+  let route = match routes.first() {
+    None => return String::new(),
+    Some((route, _)) => route,
+  };
+  let ssl = gen_ssl(route);
+  let mut listen = String::new();
+  if ssl.is_empty() || !route.ssl_redirect {
+    listen.push_str("  listen 80;\n");
+  }
+  if !ssl.is_empty() {
+    listen.push_str("  listen 443 ssl;\n");
+  }
+  let mut config = format!(
+    "{}server {{\n  server_name {};\n{}{}{}}}\n",
+    upstreams, host, listen, ssl, locations
+  );
+  if !ssl.is_empty() && route.ssl_redirect {
+    config.push_str(&format!(
+      "\nserver {{\n  server_name {};\n  listen 80;\n  return 301 https://$host$request_uri;\n}}\n",
+      host
+    ));
+  }
+  config
+}
+
+/// Generate the proxy configuration of a stream route
+///
+/// ## Arguments
+/// - [route](ProxyRouteItem) The stream route
+/// - [target_ips](Vec<String>) The addresses of the targets
+///
+/// ## Return
+/// - [Option](String) The proxy configuration or none when there is no target
+pub fn gen_stream_config(
+  route: &ProxyRouteItem,
+  target_ips: &[String],
+) -> Option<String> {
+  if target_ips.is_empty() {
+    return None;
+  }
+  let ssl = gen_ssl(route);
+  let listen = match ssl.is_empty() {
+    true => format!("{}", route.stream_port?),
+    false => format!("{} ssl", route.stream_port?),
+  };
+  Some(format!(
+    "{}server {{\n  listen {};\n{}  proxy_pass {};\n}}\n",
+    gen_upstream(route, target_ips),
+    listen,
+    ssl,
+    gen_upstream_name(route)
+  ))
+}
+
+fn gen_http_file_path(config: &DaemonConfig, host: &str) -> PathBuf {
+  Path::new(&config.state_dir)
+    .join("nginx/sites-enabled")
+    .join(format!("route.{}.conf", host))
+}
+
+fn gen_stream_file_path(
+  config: &DaemonConfig,
+  route: &ProxyRouteItem,
+) -> PathBuf {
+  Path::new(&config.state_dir)
+    .join("nginx/streams-enabled")
+    .join(format!("route.{}.conf", route.key))
+}
+
+/// Write the proxy configuration of the http routes of a host
+/// The configuration is removed when the host has no route left
+async fn sync_host(
+  host: &str,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let routes =
+    repositories::proxy_route::find_by_host(host.to_owned(), pool).await?;
+  // Routes without their own certificate use the ACME one of the host
+  let certificate = utils::acme::get_certificate(config, host);
+  let mut items = Vec::new();
+  for mut route in routes {
+    if let (None, Some((certificate, key))) =
+      (&route.ssl_certificate, &certificate)
+    {
+      route.ssl_certificate = Some(certificate.to_owned());
+      route.ssl_certificate_key = Some(key.to_owned());
+    }
+    let target_ips =
+      list_target_ips(&route.cargo_key, docker_api, pool).await?;
+    items.push((route, target_ips));
+  }
+  let content = match items.is_empty() {
+    true => None,
+    false => Some(gen_http_config(host, &items)),
+  };
+  controllers::proxy::apply_config(
+    &gen_http_file_path(config, host),
+    content,
+    docker_api,
+  )
+  .await
+}
+
+/// Write the proxy configuration of a stream route
+async fn sync_stream(
+  route: &ProxyRouteItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let target_ips = list_target_ips(&route.cargo_key, docker_api, pool).await?;
+  let content = gen_stream_config(route, &target_ips);
+  if content.is_none() {
+    log::warn!("stream route {} has no running target", &route.key);
+  }
+  controllers::proxy::apply_config(
+    &gen_stream_file_path(config, route),
+    content,
+    docker_api,
+  )
+  .await
+}
+
+/// Write the proxy configuration of routes and reload the proxy
+/// Routes of a host are written together so the host is synced only once
+///
+/// ## Arguments
+/// - [routes](Vec<ProxyRouteItem>) The routes to sync
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](()) The routes are synced
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn sync_routes(
+  routes: &[ProxyRouteItem],
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  if routes.is_empty() {
+    return Ok(());
+  }
+  let mut hosts: Vec<&str> = Vec::new();
+  for route in routes {
+    match (&route.mode, &route.host) {
+      (ProxyTemplateModes::Http, Some(host)) => {
+        if !hosts.contains(&host.as_str()) {
+          hosts.push(host);
+          sync_host(host, config, docker_api, pool).await?;
+        }
+      }
+      (ProxyTemplateModes::Http, None) => {}
+      (ProxyTemplateModes::Stream, _) => {
+        sync_stream(route, config, docker_api, pool).await?;
+      }
+    }
+  }
+  // Ignore error if we can't reload the proxy config
+  let _ = controllers::proxy::reload_config(docker_api).await;
+  Ok(())
+}
+
+/// Write the proxy configuration of the routes targeting a cargo
+/// Called when the containers of the cargo changed
+///
+/// ## Arguments
+/// - [cargo_key](str) The key of the cargo
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub async fn sync_cargo_routes(
+  cargo_key: &str,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let routes =
+    repositories::proxy_route::find_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?;
+  sync_routes(&routes, config, docker_api, pool).await
+}
+
+/// Remove a route from the proxy configuration
+/// The route must already be deleted or updated in the store
+///
+/// ## Arguments
+/// - [route](ProxyRouteItem) The route as it was configured
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub async fn delete_route_config(
+  route: &ProxyRouteItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  match (&route.mode, &route.host) {
+    (ProxyTemplateModes::Http, Some(host)) => {
+      sync_host(host, config, docker_api, pool).await
+    }
+    (ProxyTemplateModes::Http, None) => Ok(()),
+    (ProxyTemplateModes::Stream, _) => {
+      controllers::proxy::apply_config(
+        &gen_stream_file_path(config, route),
+        None,
+        docker_api,
+      )
+      .await
+    }
+  }
+}
+
+/// Delete a route and remove it from the proxy configuration
+///
+/// ## Arguments
+/// - [route](ProxyRouteItem) The route to delete
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](GenericDelete) The number of deleted routes
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn delete_route(
+  route: &ProxyRouteItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<GenericDelete, HttpResponseError> {
+  let res =
+    repositories::proxy_route::delete_by_key(route.key.to_owned(), pool)
+      .await?;
+  delete_route_config(route, config, docker_api, pool).await?;
+  // Ignore error if we can't reload the proxy config
+  let _ = controllers::proxy::reload_config(docker_api).await;
+  Ok(res)
+}
+
+/// Delete the routes targeting a cargo
+///
+/// ## Arguments
+/// - [cargo_key](str) The key of the cargo
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub async fn delete_cargo_routes(
+  cargo_key: &str,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let routes =
+    repositories::proxy_route::find_by_cargo_key(cargo_key.to_owned(), pool)
+      .await?;
+  for route in routes {
+    delete_route(&route, config, docker_api, pool).await?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn gen_route(mode: ProxyTemplateModes) -> ProxyRoutePartial {
+    ProxyRoutePartial {
+      name: String::from("test"),
+      mode,
+      host: None,
+      path: None,
+      cargo: String::from("test"),
+      target_port: 80,
+      stream_port: None,
+      ssl_certificate: None,
+      ssl_certificate_key: None,
+      ssl_redirect: None,
+    }
+  }
+
+  #[test]
+  fn validate_test() {
+    let mut route = gen_route(ProxyTemplateModes::Http);
+    assert!(validate(&route).is_err(), "Expect host to be required");
+    route.host = Some(String::from("test.internal"));
+    assert!(validate(&route).is_ok());
+    route.path = Some(String::from("/api; return 200"));
+    assert!(validate(&route).is_err(), "Expect path to be refused");
+    route.path = Some(String::from("/api"));
+    route.ssl_certificate = Some(String::from("/etc/nginx/ssl/test.pem"));
+    assert!(validate(&route).is_err(), "Expect ssl key to be required");
+
+    let mut route = gen_route(ProxyTemplateModes::Stream);
+    assert!(
+      validate(&route).is_err(),
+      "Expect stream port to be required"
+    );
+    route.stream_port = Some(5432);
+    assert!(validate(&route).is_ok());
+    route.host = Some(String::from("test.internal"));
+    assert!(validate(&route).is_err(), "Expect host to be refused");
+  }
+
+  #[test]
+  fn gen_http_config_test() {
+    let api = gen_item(
+      "global",
+      "global-api",
+      ProxyRoutePartial {
+        host: Some(String::from("test.internal")),
+        path: Some(String::from("/api")),
+        ssl_certificate: Some(String::from("/etc/nginx/ssl/test.pem")),
+        ssl_certificate_key: Some(String::from("/etc/nginx/ssl/test.key")),
+        ssl_redirect: Some(true),
+        ..gen_route(ProxyTemplateModes::Http)
+      },
+    );
+    let front = ProxyRouteItem {
+      key: String::from("global-front"),
+      path: String::from("/"),
+      ..api.to_owned()
+    };
+    let routes = vec![
+      (
+        api,
+        vec![String::from("10.0.0.2"), String::from("10.0.0.3")],
+      ),
+      (front, vec![]),
+    ];
+    let config = gen_http_config("test.internal", &routes);
+    assert!(config.contains("upstream nanocl_route_global_test {\n  server 10.0.0.2:80;\n  server 10.0.0.3:80;\n}"));
+    assert!(config.contains("location /api {"));
+    assert!(config.contains("proxy_pass http://nanocl_route_global_test;"));
+    assert!(config.contains("location / {\n    return 503;\n  }"));
+    assert!(config.contains("listen 443 ssl;"));
+    assert!(config.contains("ssl_certificate /etc/nginx/ssl/test.pem;"));
+    assert!(config.contains("return 301 https://$host$request_uri;"));
+  }
+
+  #[test]
+  fn gen_stream_config_test() {
+    let route = gen_item(
+      "global",
+      "global-db",
+      ProxyRoutePartial {
+        stream_port: Some(5432),
+        ..gen_route(ProxyTemplateModes::Stream)
+      },
+    );
+    assert!(gen_stream_config(&route, &[]).is_none());
+    let config = gen_stream_config(&route, &[String::from("10.0.0.2")])
+      .expect("Expect a stream config");
+    assert!(config.contains("listen 5432;"));
+    assert!(config.contains("server 10.0.0.2:80;"));
+    assert!(config.contains("proxy_pass nanocl_route_global_test;"));
+  }
+}
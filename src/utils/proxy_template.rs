@@ -0,0 +1,115 @@
+//! Propagation of the proxy templates to the clusters using them
+use crate::{utils, controllers, repositories};
+use crate::errors::HttpResponseError;
+use crate::models::{
+  Pool, DaemonConfig, ProxyTemplateItem, ProxyTemplatePatchPartial,
+};
+
+/// Render a proxy template again for every clusters using it
+/// The proxy is reloaded once when every configs are written.
+/// When the mode of the template changed the config rendered with the previous mode is removed.
+///
+/// ## Arguments
+/// - [template](ProxyTemplateItem) The proxy template as stored
+/// - [prev](ProxyTemplateItem) The proxy template before the change
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](()) The template is rendered for every clusters
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn propagate(
+  template: &ProxyTemplateItem,
+  prev: &ProxyTemplateItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let clusters = repositories::cluster::find_by_proxy_template(
+    template.name.to_owned(),
+    pool,
+  )
+  .await?;
+  for cluster in &clusters {
+    let config_file = utils::cluster::render_proxy_template(
+      cluster,
+      &template.name,
+      config,
+      docker_api,
+      pool,
+    )
+    .await?;
+    if prev.mode != template.mode {
+      controllers::proxy::apply_config(
+        &utils::cluster::gen_template_file_path(config, &cluster.key, prev),
+        None,
+        docker_api,
+      )
+      .await?;
+    }
+    controllers::proxy::apply_config(
+      &utils::cluster::gen_template_file_path(config, &cluster.key, template),
+      Some(config_file),
+      docker_api,
+    )
+    .await?;
+  }
+  if !clusters.is_empty() {
+    // Ignore error if we can't reload the proxy config
+    let _ = controllers::proxy::reload_config(docker_api).await;
+  }
+  Ok(())
+}
+
+/// Update a proxy template in place and propagate it to the clusters using it
+/// A new revision is stored when the proxy accept the new config,
+/// otherwise the previous template is restored.
+///
+/// ## Arguments
+/// - [name](str) The name of the proxy template
+/// - [payload](ProxyTemplatePatchPartial) The fields to update
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+///
+/// ## Return
+/// - [Result](ProxyTemplateItem) The updated proxy template
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn update(
+  name: &str,
+  payload: ProxyTemplatePatchPartial,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<ProxyTemplateItem, HttpResponseError> {
+  let prev =
+    repositories::proxy_template::get_by_name(name.to_owned(), pool).await?;
+  let item = ProxyTemplateItem {
+    name: prev.name.to_owned(),
+    mode: payload.mode.unwrap_or_else(|| prev.mode.to_owned()),
+    content: payload.content.unwrap_or_else(|| prev.content.to_owned()),
+  };
+  repositories::proxy_template::update_by_name(
+    name.to_owned(),
+    item.to_owned(),
+    pool,
+  )
+  .await?;
+  if let Err(err) = propagate(&item, &prev, config, docker_api, pool).await {
+    repositories::proxy_template::update_by_name(
+      name.to_owned(),
+      prev.to_owned(),
+      pool,
+    )
+    .await?;
+    // Clusters already rendered with the new template are rendered again
+    // with the previous one, the error of the update is the one that matters
+    if let Err(err) = propagate(&prev, &item, config, docker_api, pool).await {
+      log::warn!("Unable to restore proxy template {} {}", name, err.msg);
+    }
+    return Err(err);
+  }
+  repositories::proxy_template_revision::create(item.to_owned(), pool).await?;
+  Ok(item)
+}
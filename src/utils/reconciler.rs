@@ -0,0 +1,369 @@
+//! Background reconciliation of docker containers with the store
+use std::sync::Mutex;
+use ntex::rt;
+use ntex::time::{sleep, Seconds};
+
+use crate::{utils, repositories};
+use crate::utils::metrics::DockerErrorMetric;
+use crate::errors::HttpResponseError;
+use crate::utils::cluster::JoinCargoOptions;
+use crate::models::{
+  Pool, DaemonConfig, ClusterItem, CargoInstanceItem, StateResourceKind,
+  StateAction, StateResourceResult, CargoInstanceHealth,
+};
+
+/// Interval between two reconciliations
+const RECONCILE_INTERVAL: Seconds = Seconds(30);
+
+/// Namespace of the controllers, their containers are managed at boot
+const SYSTEM_NAMESPACE: &str = "system";
+
+/// Name of the containers seen unhealthy by the last reconciliation
+static UNHEALTHY_CONTAINERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn gen_result(
+  key: &str,
+  action: StateAction,
+  reason: String,
+) -> StateResourceResult {
+  StateResourceResult {
+    kind: StateResourceKind::Container,
+    key: key.to_owned(),
+    action,
+    reason: Some(reason),
+  }
+}
+
+async fn remove_container(
+  id_or_name: &str,
+  docker_api: &bollard::Docker,
+) -> Result<(), HttpResponseError> {
+  let options = Some(bollard::container::RemoveContainerOptions {
+    force: true,
+    ..Default::default()
+  });
+  docker_api
+    .remove_container(id_or_name, options)
+    .await
+    .record_error()?;
+  Ok(())
+}
+
+/// Converge the containers of a cargo instance to the replicas of his cargo
+/// Unexpected containers are removed, stopped ones are started
+/// and missing replicas are created.
+/// Instances never started with their cluster are left untouched
+/// and a container that can't be removed or started doesn't stop the others.
+async fn reconcile_instance(
+  cluster: &ClusterItem,
+  instance: &CargoInstanceItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let mut results = Vec::new();
+  // Containers of a cargo being updated are managed by the update
+  if !instance.started || utils::cargo::is_updating(&instance.cargo_key) {
+    return Ok(results);
+  }
+  let cargo =
+    repositories::cargo::find_by_key(instance.cargo_key.to_owned(), pool)
+      .await?;
+  let containers =
+    utils::cluster::list_containers(&cluster.key, &cargo.key, docker_api)
+      .await?;
+  let diff = utils::cargo::diff_replicas(&cargo, &cluster.name, &containers);
+
+  for name in diff.unexpected {
+    if let Err(err) = remove_container(&name, docker_api).await {
+      log::warn!("unable to remove container {}: {}", &name, err);
+      continue;
+    }
+    results.push(gen_result(
+      &name,
+      StateAction::Delete,
+      format!(
+        "container is not an expected replica of cargo {}",
+        &cargo.key
+      ),
+    ));
+  }
+
+  for name in diff.stopped {
+    if let Err(err) =
+      utils::cargo_instance::start_cargo_instance(&name, docker_api).await
+    {
+      log::warn!("unable to start container {}: {}", &name, err);
+      continue;
+    }
+    results.push(gen_result(
+      &name,
+      StateAction::Update,
+      String::from("container was not running"),
+    ));
+  }
+
+  if diff.missing.is_empty() {
+    return Ok(results);
+  }
+  let names = diff
+    .missing
+    .iter()
+    .map(|index| utils::cargo::gen_instance_name(&cargo, &cluster.name, *index))
+    .collect::<Vec<String>>();
+  let network = repositories::cluster_network::find_by_key(
+    instance.network_key.to_owned(),
+    pool,
+  )
+  .await?;
+  let opts = JoinCargoOptions {
+    cluster: cluster.to_owned(),
+    cargo,
+    network,
+    is_creating_relation: false,
+    indexes: Some(diff.missing),
+  };
+  utils::cluster::join_cargo(&opts, config, docker_api, pool).await?;
+  for name in names {
+    results.push(gen_result(
+      &name,
+      StateAction::Create,
+      String::from("replica was missing"),
+    ));
+  }
+
+  Ok(results)
+}
+
+/// Track the health of a container between two reconciliations
+/// A result is returned when the container became healthy or unhealthy
+fn track_health(
+  container: &bollard::models::ContainerSummary,
+) -> Option<StateResourceResult> {
+  let name = utils::cargo_instance::get_container_name(container);
+  let is_unhealthy = utils::cargo_instance::get_container_health(container)
+    == CargoInstanceHealth::Unhealthy;
+  let mut unhealthy_containers = match UNHEALTHY_CONTAINERS.lock() {
+    Ok(containers) => containers,
+    Err(poisoned) => poisoned.into_inner(),
+  };
+  let was_unhealthy = unhealthy_containers.contains(&name);
+  match (was_unhealthy, is_unhealthy) {
+    (false, true) => {
+      unhealthy_containers.push(name.to_owned());
+      Some(gen_result(
+        &name,
+        StateAction::Update,
+        String::from("container became unhealthy"),
+      ))
+    }
+    (true, false) => {
+      unhealthy_containers.retain(|item| item != &name);
+      Some(gen_result(
+        &name,
+        StateAction::Update,
+        String::from("container is no longer unhealthy"),
+      ))
+    }
+    _ => None,
+  }
+}
+
+/// Converge the containers of a cluster to his cargo instances
+/// Clusters never started are skipped and
+/// containers that don't belong to a cargo instance are removed.
+/// When something changed, including the health of a container,
+/// the cluster is started to render his proxy templates
+async fn reconcile_cluster(
+  cluster: &ClusterItem,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let mut results = Vec::new();
+  let instances = repositories::cargo_instance::get_by_cluster_key(
+    cluster.key.to_owned(),
+    pool,
+  )
+  .await?;
+  if !instances.iter().any(|instance| instance.started) {
+    return Ok(results);
+  }
+  for instance in &instances {
+    match reconcile_instance(cluster, instance, config, docker_api, pool).await
+    {
+      Err(err) => {
+        log::warn!("unable to reconcile instance {}: {}", &instance.key, err)
+      }
+      Ok(instance_results) => results.extend(instance_results),
+    }
+  }
+
+  let containers =
+    utils::cluster::list_cluster_containers(&cluster.key, docker_api).await?;
+  for container in containers {
+    let cargo_key =
+      utils::cargo_instance::get_container_label(&container, "cargo");
+    let is_owned = instances
+      .iter()
+      .any(|instance| Some(&instance.cargo_key) == cargo_key.as_ref());
+    if is_owned {
+      if let Some(res) = track_health(&container) {
+        results.push(res);
+      }
+      continue;
+    }
+    let name = utils::cargo_instance::get_container_name(&container);
+    let id = container.id.unwrap_or_default();
+    if let Err(err) = remove_container(&id, docker_api).await {
+      log::warn!("unable to remove container {}: {}", &name, err);
+      continue;
+    }
+    results.push(gen_result(
+      &name,
+      StateAction::Delete,
+      format!(
+        "container does not belong to a cargo instance of cluster {}",
+        &cluster.key,
+      ),
+    ));
+  }
+
+  if !results.is_empty() {
+    utils::cluster::start(cluster, config, pool, docker_api).await?;
+  }
+  Ok(results)
+}
+
+/// Reconcile the containers of every clusters with the store
+/// Clusters of the system namespace are skipped
+/// and an error on a cluster doesn't stop the reconciliation of the others.
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config reference
+/// - [docker_api](bollard::Docker) Docker api reference
+/// - [pool](Pool) Database pool reference
+///
+/// ## Return
+/// - [Result](Vec<StateResourceResult>) The action taken on each container
+/// - [Result](HttpResponseError) An http response error if the store can't be read
+pub async fn reconcile(
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let mut results = Vec::new();
+  let namespaces = repositories::namespace::list(pool).await?;
+  for namespace in namespaces {
+    if namespace.name == SYSTEM_NAMESPACE {
+      continue;
+    }
+    let clusters =
+      repositories::cluster::find_by_namespace(namespace.name, pool).await?;
+    for cluster in clusters {
+      match reconcile_cluster(&cluster, config, docker_api, pool).await {
+        Err(err) => {
+          log::warn!("unable to reconcile cluster {}: {}", &cluster.key, err)
+        }
+        Ok(cluster_results) => results.extend(cluster_results),
+      }
+    }
+  }
+  Ok(results)
+}
+
+/// Spawn the reconciliation loop
+/// Every actions taken are reported in the logs
+///
+/// ## Arguments
+/// - [config](DaemonConfig) Daemon config
+/// - [docker_api](bollard::Docker) Docker api
+/// - [pool](Pool) Database pool
+pub fn spawn(config: DaemonConfig, docker_api: bollard::Docker, pool: Pool) {
+  rt::spawn(async move {
+    loop {
+      sleep(RECONCILE_INTERVAL).await;
+      let results = match reconcile(&config, &docker_api, &pool).await {
+        Err(err) => {
+          log::warn!("reconciliation failed: {}", err);
+          continue;
+        }
+        Ok(results) => results,
+      };
+      for res in results {
+        log::info!(
+          "reconciler {:?} container {}: {}",
+          res.action,
+          res.key,
+          res.reason.unwrap_or_default(),
+        );
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::utils;
+  use crate::models::CargoItem;
+
+  fn gen_container(
+    name: &str,
+    state: &str,
+  ) -> bollard::models::ContainerSummary {
+    bollard::models::ContainerSummary {
+      names: Some(vec![format!("/{}", name)]),
+      state: Some(state.to_owned()),
+      ..Default::default()
+    }
+  }
+
+  /// Test to compare the containers of a cargo with his replicas
+  /// Expect missing, stopped and unexpected replicas to be detected
+  #[test]
+  fn diff_replicas() {
+    let cargo = CargoItem {
+      key: String::from("global-utrc"),
+      namespace_name: String::from("global"),
+      name: String::from("utrc"),
+      config: serde_json::Value::Null,
+      replicas: 3,
+      dns_entry: None,
+    };
+    let containers = vec![
+      gen_container("global-utrc-utrc", "running"),
+      gen_container("global-utrc-utrc-2", "exited"),
+      gen_container("utrc-tmp-0", "running"),
+    ];
+    let diff = utils::cargo::diff_replicas(&cargo, "utrc", &containers);
+    assert_eq!(diff.missing, vec![1], "Expect replica 1 to be missing");
+    assert_eq!(
+      diff.stopped,
+      vec![String::from("global-utrc-utrc-2")],
+      "Expect replica 2 to be stopped"
+    );
+    assert_eq!(
+      diff.unexpected,
+      vec![String::from("utrc-tmp-0")],
+      "Expect temporary container to be unexpected"
+    );
+  }
+
+  /// Test to update a cargo twice at the same time
+  /// Expect the second update to conflict until the first guard is dropped
+  #[test]
+  fn updating_guard() {
+    let guard = utils::cargo::lock_updating("global-utrcguard")
+      .expect("Expect first update to be allowed");
+    assert!(utils::cargo::is_updating("global-utrcguard"));
+    let err = utils::cargo::lock_updating("global-utrcguard")
+      .err()
+      .expect("Expect second update to be refused");
+    assert_eq!(err.status, ntex::http::StatusCode::CONFLICT);
+    drop(guard);
+    assert!(
+      !utils::cargo::is_updating("global-utrcguard"),
+      "Expect cargo to be released when the guard is dropped"
+    );
+  }
+}
@@ -0,0 +1,1016 @@
+use std::collections::HashMap;
+
+use ntex::http::StatusCode;
+
+use crate::{utils, repositories};
+use crate::utils::metrics::DockerErrorMetric;
+use crate::errors::{HttpResponseError, StateApplyError};
+use crate::utils::cluster::JoinCargoOptions;
+use crate::models::{
+  Pool, DaemonConfig, StateFile, StateFileCluster, StateResourceKind,
+  StateAction, StateResourceResult, NamespacePartial, ClusterPartial,
+  ClusterNetworkPartial, ClusterVariablePartial, ClusterJoinBody, CargoPartial,
+  CargoPatchPartial, CargoEnvPartial, CargoItem, ClusterItem,
+  CargoUpdateStrategy, ProxyTemplateItem,
+};
+
+/// Parse a state file
+/// Yaml being a superset of json both formats are accepted
+///
+/// ## Arguments
+/// - [data](&[u8]) The raw state file
+///
+/// ## Return
+/// - [Result](StateFile) The parsed state file
+/// - [Result](HttpResponseError) An http response error if the state file is not valid
+pub fn parse(data: &[u8]) -> Result<StateFile, HttpResponseError> {
+  let mut state = serde_yaml::from_slice::<StateFile>(data).map_err(|err| {
+    HttpResponseError {
+      msg: format!("unable to parse state file: {}", err),
+      status: StatusCode::UNPROCESSABLE_ENTITY,
+    }
+  })?;
+  // Health checks are stored in the config of the cargoes
+  for cargo in state.cargoes.iter_mut().flatten() {
+    if let Some(healthcheck) = cargo.healthcheck.take() {
+      cargo.config =
+        utils::cargo::merge_healthcheck(&cargo.config, &healthcheck)?;
+    }
+  }
+  Ok(state)
+}
+
+/// Convert a not found error into None so we can know if a resource exists
+fn not_found_to_none<T>(
+  res: Result<T, HttpResponseError>,
+) -> Result<Option<T>, HttpResponseError> {
+  match res {
+    Ok(item) => Ok(Some(item)),
+    Err(err) if err.status == StatusCode::NOT_FOUND => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+fn gen_result(
+  kind: StateResourceKind,
+  key: &str,
+  action: StateAction,
+  reason: Option<String>,
+) -> StateResourceResult {
+  StateResourceResult {
+    kind,
+    key: key.to_owned(),
+    action,
+    reason,
+  }
+}
+
+/// Ensure the state file is valid before changing anything
+async fn validate(
+  state: &StateFile,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  for cargo in state.cargoes.iter().flatten() {
+    utils::cargo::parse_config(&cargo.config)?;
+    utils::cargo::parse_environnements(
+      &cargo.environnements.to_owned().unwrap_or_default(),
+    )?;
+  }
+  for cluster in state.clusters.iter().flatten() {
+    for template in cluster.proxy_templates.iter().flatten() {
+      // Templates defined in the state file are created before the clusters
+      if state
+        .proxy_templates
+        .iter()
+        .flatten()
+        .any(|item| &item.name == template)
+      {
+        continue;
+      }
+      repositories::proxy_template::get_by_name(template.to_owned(), pool)
+        .await?;
+    }
+  }
+  Ok(())
+}
+
+/// Compare the namespace of a state file with the store
+async fn plan_namespace(
+  name: &str,
+  pool: &Pool,
+) -> Result<StateResourceResult, HttpResponseError> {
+  let item = not_found_to_none(
+    repositories::namespace::find_by_name(name.to_owned(), pool).await,
+  )?;
+  let res = match item {
+    Some(_) => {
+      gen_result(StateResourceKind::Namespace, name, StateAction::Leave, None)
+    }
+    None => gen_result(
+      StateResourceKind::Namespace,
+      name,
+      StateAction::Create,
+      Some(String::from("namespace does not exist")),
+    ),
+  };
+  Ok(res)
+}
+
+async fn apply_namespace(
+  name: &str,
+  pool: &Pool,
+) -> Result<StateResourceResult, HttpResponseError> {
+  let res = plan_namespace(name, pool).await?;
+  if res.action == StateAction::Create {
+    let new_nsp = NamespacePartial {
+      name: name.to_owned(),
+    };
+    repositories::namespace::create(new_nsp, pool).await?;
+  }
+  Ok(res)
+}
+
+/// Compare a proxy template with the store
+async fn plan_proxy_template(
+  template: &ProxyTemplateItem,
+  pool: &Pool,
+) -> Result<StateResourceResult, HttpResponseError> {
+  let item = not_found_to_none(
+    repositories::proxy_template::get_by_name(template.name.to_owned(), pool)
+      .await,
+  )?;
+  let (action, reason) = match item {
+    None => (
+      StateAction::Create,
+      Some(String::from("proxy template does not exist")),
+    ),
+    Some(item) if item.mode != template.mode => (
+      StateAction::Update,
+      Some(format!(
+        "mode changed from {:?} to {:?}",
+        &item.mode, &template.mode,
+      )),
+    ),
+    Some(item) if item.content != template.content => {
+      (StateAction::Update, Some(String::from("content changed")))
+    }
+    Some(_) => (StateAction::Leave, None),
+  };
+  Ok(gen_result(
+    StateResourceKind::ProxyTemplate,
+    &template.name,
+    action,
+    reason,
+  ))
+}
+
+async fn apply_proxy_template(
+  template: ProxyTemplateItem,
+  pool: &Pool,
+) -> Result<StateResourceResult, HttpResponseError> {
+  let res = plan_proxy_template(&template, pool).await?;
+  match res.action {
+    StateAction::Create => {
+      let template =
+        repositories::proxy_template::create(template, pool).await?;
+      repositories::proxy_template_revision::create(template, pool).await?;
+    }
+    StateAction::Update => {
+      let template = repositories::proxy_template::update_by_name(
+        template.name.to_owned(),
+        template,
+        pool,
+      )
+      .await?;
+      repositories::proxy_template_revision::create(template, pool).await?;
+    }
+    _ => {}
+  }
+  Ok(res)
+}
+
+/// Compare a cluster with his variables and networks with the store
+async fn plan_cluster(
+  nsp: &str,
+  cluster: &StateFileCluster,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let mut results = Vec::new();
+  let key = utils::key::gen_key(nsp, &cluster.name);
+  let item = not_found_to_none(
+    repositories::cluster::find_by_key(key.to_owned(), pool).await,
+  )?;
+  let res = match item {
+    None => gen_result(
+      StateResourceKind::Cluster,
+      &key,
+      StateAction::Create,
+      Some(String::from("cluster does not exist")),
+    ),
+    Some(item) => match cluster.proxy_templates {
+      Some(ref proxy_templates) if proxy_templates != &item.proxy_templates => {
+        gen_result(
+          StateResourceKind::Cluster,
+          &key,
+          StateAction::Update,
+          Some(format!(
+            "proxy templates changed from {:?} to {:?}",
+            &item.proxy_templates, proxy_templates,
+          )),
+        )
+      }
+      _ => {
+        gen_result(StateResourceKind::Cluster, &key, StateAction::Leave, None)
+      }
+    },
+  };
+  results.push(res);
+
+  let variables =
+    repositories::cluster_variable::list_by_cluster(key.to_owned(), pool)
+      .await?;
+  let variables = utils::cluster_variable::cluster_vars_to_hashmap(variables);
+  for variable in cluster.variables.iter().flatten() {
+    let var_key = utils::key::gen_key(&key, &variable.name);
+    let (action, reason) = match variables.get(&variable.name) {
+      None => (
+        StateAction::Create,
+        Some(String::from("variable does not exist")),
+      ),
+      Some(value) if value != &variable.value => (
+        StateAction::Update,
+        Some(format!(
+          "value changed from {} to {}",
+          value, &variable.value,
+        )),
+      ),
+      Some(_) => (StateAction::Leave, None),
+    };
+    results.push(gen_result(
+      StateResourceKind::ClusterVariable,
+      &var_key,
+      action,
+      reason,
+    ));
+  }
+
+  for network in cluster.networks.iter().flatten() {
+    let network_key = utils::key::gen_key(&key, &network.name);
+    let item = not_found_to_none(
+      repositories::cluster_network::find_by_key(network_key.to_owned(), pool)
+        .await,
+    )?;
+    let (action, reason) = match item {
+      Some(_) => (StateAction::Leave, None),
+      None => (
+        StateAction::Create,
+        Some(String::from("network does not exist")),
+      ),
+    };
+    results.push(gen_result(
+      StateResourceKind::ClusterNetwork,
+      &network_key,
+      action,
+      reason,
+    ));
+  }
+
+  Ok(results)
+}
+
+/// Create or update a cluster with his variables and networks
+async fn apply_cluster(
+  nsp: &str,
+  cluster: &StateFileCluster,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let results = plan_cluster(nsp, cluster, pool).await?;
+  let key = utils::key::gen_key(nsp, &cluster.name);
+  for res in &results {
+    match (&res.kind, &res.action) {
+      (StateResourceKind::Cluster, StateAction::Create) => {
+        let new_cluster = ClusterPartial {
+          name: cluster.name.to_owned(),
+          proxy_templates: cluster.proxy_templates.to_owned(),
+        };
+        repositories::cluster::create_for_namespace(
+          nsp.to_owned(),
+          new_cluster,
+          pool,
+        )
+        .await?;
+      }
+      (StateResourceKind::Cluster, StateAction::Update) => {
+        repositories::cluster::patch_proxy_templates(
+          key.to_owned(),
+          cluster.proxy_templates.to_owned().unwrap_or_default(),
+          pool,
+        )
+        .await?;
+      }
+      (StateResourceKind::ClusterVariable, StateAction::Create) => {
+        let variable = cluster.variables.iter().flatten().find(|variable| {
+          utils::key::gen_key(&key, &variable.name) == res.key
+        });
+        if let Some(variable) = variable {
+          let new_var = ClusterVariablePartial {
+            name: variable.name.to_owned(),
+            value: variable.value.to_owned(),
+          };
+          repositories::cluster_variable::create(key.to_owned(), new_var, pool)
+            .await?;
+        }
+      }
+      (StateResourceKind::ClusterVariable, StateAction::Update) => {
+        let variable = cluster.variables.iter().flatten().find(|variable| {
+          utils::key::gen_key(&key, &variable.name) == res.key
+        });
+        if let Some(variable) = variable {
+          repositories::cluster_variable::patch_by_key(
+            res.key.to_owned(),
+            variable.value.to_owned(),
+            pool,
+          )
+          .await?;
+        }
+      }
+      (StateResourceKind::ClusterNetwork, StateAction::Create) => {
+        let network =
+          cluster.networks.iter().flatten().find(|network| {
+            utils::key::gen_key(&key, &network.name) == res.key
+          });
+        if let Some(network) = network {
+          let new_network = ClusterNetworkPartial {
+            name: network.name.to_owned(),
+          };
+          utils::cluster_network::create_network(
+            nsp.to_owned(),
+            cluster.name.to_owned(),
+            new_network,
+            docker_api,
+            pool,
+          )
+          .await?;
+        }
+      }
+      _ => {}
+    }
+  }
+  Ok(results)
+}
+
+/// Compare a cargo with his environnements with the store
+async fn plan_cargo(
+  nsp: &str,
+  cargo: &CargoPartial,
+  pool: &Pool,
+) -> Result<StateResourceResult, HttpResponseError> {
+  let key = utils::key::gen_key(nsp, &cargo.name);
+  let item = not_found_to_none(
+    repositories::cargo::find_by_key(key.to_owned(), pool).await,
+  )?;
+  let item = match item {
+    None => {
+      return Ok(gen_result(
+        StateResourceKind::Cargo,
+        &key,
+        StateAction::Create,
+        Some(String::from("cargo does not exist")),
+      ));
+    }
+    Some(item) => item,
+  };
+
+  let current_envs =
+    repositories::cargo_env::list_by_cargo_key(key.to_owned(), pool)
+      .await?
+      .into_iter()
+      .map(|env| (env.name, env.value))
+      .collect::<HashMap<String, String>>();
+  let envs = utils::cargo::parse_environnements(
+    &cargo.environnements.to_owned().unwrap_or_default(),
+  )?
+  .into_iter()
+  .collect::<HashMap<String, String>>();
+
+  let mut changes: Vec<&str> = Vec::new();
+  if item.config != cargo.config {
+    changes.push("config");
+  }
+  if item.replicas != cargo.replicas.unwrap_or(1) {
+    changes.push("replicas");
+  }
+  // A dns_entry not defined in the state file is left untouched
+  if cargo.dns_entry.is_some() && cargo.dns_entry != item.dns_entry {
+    changes.push("dns_entry");
+  }
+  if current_envs != envs {
+    changes.push("environnements");
+  }
+  if changes.is_empty() {
+    return Ok(gen_result(
+      StateResourceKind::Cargo,
+      &key,
+      StateAction::Leave,
+      None,
+    ));
+  }
+  Ok(gen_result(
+    StateResourceKind::Cargo,
+    &key,
+    StateAction::Update,
+    Some(format!("{} changed", changes.join(", "))),
+  ))
+}
+
+/// Create or update a cargo with his environnements
+/// When a cargo is updated his instances are recreated
+async fn apply_cargo(
+  nsp: &str,
+  cargo: CargoPartial,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<StateResourceResult, HttpResponseError> {
+  let res = plan_cargo(nsp, &cargo, pool).await?;
+  let key = res.key.to_owned();
+  let envs = utils::cargo::parse_environnements(
+    &cargo.environnements.to_owned().unwrap_or_default(),
+  )?;
+  match res.action {
+    StateAction::Create => {
+      let item =
+        repositories::cargo::create(nsp.to_owned(), cargo, pool).await?;
+      let envs = envs
+        .into_iter()
+        .map(|(name, value)| CargoEnvPartial {
+          name,
+          value,
+          cargo_key: item.key.to_owned(),
+        })
+        .collect::<Vec<CargoEnvPartial>>();
+      repositories::cargo_env::create_many(envs, pool).await?;
+      utils::cargo::create_revision(&key, pool).await?;
+    }
+    StateAction::Update => {
+      let _guard = utils::cargo::lock_updating(&key)?;
+      let previous = utils::cargo::gen_snapshot(&key, pool).await?;
+      utils::cargo::sync_environnements(&key, envs, pool).await?;
+
+      let patch = CargoPatchPartial {
+        name: None,
+        config: Some(cargo.config),
+        replicas: Some(cargo.replicas.unwrap_or(1)),
+        dns_entry: cargo.dns_entry,
+        environnements: None,
+        healthcheck: None,
+        update_strategy: None,
+      };
+      repositories::cargo::update_by_key(
+        nsp.to_owned(),
+        cargo.name,
+        patch,
+        pool,
+      )
+      .await?;
+      utils::cargo::apply_update(
+        &key,
+        previous,
+        &CargoUpdateStrategy::default(),
+        config,
+        docker_api,
+        pool,
+      )
+      .await?;
+    }
+    _ => {}
+  }
+  Ok(res)
+}
+
+/// Compare the joined cargoes of a cluster with the store and the live containers
+/// - A join without cargo instance is created
+/// - An instance of an updated cargo or with missing containers is recreated
+/// - An instance with stopped containers is updated by starting them
+/// - A container that is not an expected replica of a cargo instance is deleted
+async fn plan_instances(
+  nsp: &str,
+  cluster: &StateFileCluster,
+  updated_cargoes: &[String],
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let mut results = Vec::new();
+  let cluster_key = utils::key::gen_key(nsp, &cluster.name);
+  let instances = repositories::cargo_instance::get_by_cluster_key(
+    cluster_key.to_owned(),
+    pool,
+  )
+  .await?;
+  let containers =
+    utils::cluster::list_cluster_containers(&cluster_key, docker_api).await?;
+
+  for join in cluster.joins.iter().flatten() {
+    let cargo_key = utils::key::gen_key(nsp, &join.cargo);
+    let key = utils::key::gen_key(&cluster_key, &cargo_key);
+    if !instances.iter().any(|instance| instance.key == key) {
+      results.push(gen_result(
+        StateResourceKind::CargoInstance,
+        &key,
+        StateAction::Create,
+        Some(format!(
+          "cargo {} is not joined to cluster {}",
+          &join.cargo, &cluster.name,
+        )),
+      ));
+      continue;
+    }
+    if updated_cargoes.contains(&cargo_key) {
+      results.push(gen_result(
+        StateResourceKind::CargoInstance,
+        &key,
+        StateAction::Recreate,
+        Some(format!("cargo {} changed", &join.cargo)),
+      ));
+      continue;
+    }
+
+    let cargo =
+      repositories::cargo::find_by_key(cargo_key.to_owned(), pool).await?;
+    let cargo_containers = containers
+      .iter()
+      .filter(|container| {
+        utils::cargo_instance::get_container_label(container, "cargo").as_ref()
+          == Some(&cargo_key)
+      })
+      .cloned()
+      .collect::<Vec<_>>();
+    let diff =
+      utils::cargo::diff_replicas(&cargo, &cluster.name, &cargo_containers);
+    for name in &diff.unexpected {
+      results.push(gen_result(
+        StateResourceKind::Container,
+        name,
+        StateAction::Delete,
+        Some(format!(
+          "container is not an expected replica of cargo {}",
+          &join.cargo,
+        )),
+      ));
+    }
+    let missing_names = diff
+      .missing
+      .iter()
+      .map(|index| {
+        utils::cargo::gen_instance_name(&cargo, &cluster.name, *index)
+      })
+      .collect::<Vec<String>>();
+    let stopped_names = diff.stopped;
+    let res = if !missing_names.is_empty() {
+      gen_result(
+        StateResourceKind::CargoInstance,
+        &key,
+        StateAction::Recreate,
+        Some(format!("missing containers {}", missing_names.join(", "))),
+      )
+    } else if !stopped_names.is_empty() {
+      gen_result(
+        StateResourceKind::CargoInstance,
+        &key,
+        StateAction::Update,
+        Some(format!("stopped containers {}", stopped_names.join(", "))),
+      )
+    } else {
+      gen_result(
+        StateResourceKind::CargoInstance,
+        &key,
+        StateAction::Leave,
+        None,
+      )
+    };
+    results.push(res);
+  }
+
+  for container in &containers {
+    let cargo_key =
+      utils::cargo_instance::get_container_label(container, "cargo");
+    let is_owned = instances
+      .iter()
+      .any(|instance| Some(&instance.cargo_key) == cargo_key.as_ref());
+    if !is_owned {
+      results.push(gen_result(
+        StateResourceKind::Container,
+        &utils::cargo_instance::get_container_name(container),
+        StateAction::Delete,
+        Some(format!(
+          "container does not belong to a cargo instance of cluster {}",
+          &cluster.name,
+        )),
+      ));
+    }
+  }
+
+  Ok(results)
+}
+
+/// Join cargoes to a cluster and fix the drift of their containers
+/// Instances of the updated cargoes are already rolled by their update
+/// so they are compared with the live containers like the others
+async fn apply_instances(
+  nsp: &str,
+  cluster: &StateFileCluster,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  let results = plan_instances(nsp, cluster, &[], docker_api, pool).await?;
+  let cluster_key = utils::key::gen_key(nsp, &cluster.name);
+
+  // Containers are deleted first so their names can be reused
+  for res in &results {
+    if res.kind == StateResourceKind::Container
+      && res.action == StateAction::Delete
+    {
+      let options = Some(bollard::container::RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+      });
+      docker_api
+        .remove_container(&res.key, options)
+        .await
+        .record_error()?;
+    }
+  }
+
+  for join in cluster.joins.iter().flatten() {
+    let cargo_key = utils::key::gen_key(nsp, &join.cargo);
+    let key = utils::key::gen_key(&cluster_key, &cargo_key);
+    let res = results.iter().find(|res| {
+      res.kind == StateResourceKind::CargoInstance && res.key == key
+    });
+    let is_creating_relation = match res.map(|res| &res.action) {
+      Some(StateAction::Create) => true,
+      Some(StateAction::Recreate) => {
+        let containers =
+          utils::cluster::list_containers(&cluster_key, &cargo_key, docker_api)
+            .await?;
+        for container in containers {
+          let options = Some(bollard::container::RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+          });
+          docker_api
+            .remove_container(&container.id.unwrap_or_default(), options)
+            .await
+            .record_error()?;
+        }
+        false
+      }
+      _ => continue,
+    };
+    let network_key = utils::key::gen_key(&cluster_key, &join.network);
+    let cluster =
+      repositories::cluster::find_by_key(cluster_key.to_owned(), pool).await?;
+    let cargo = repositories::cargo::find_by_key(cargo_key, pool).await?;
+    let network =
+      repositories::cluster_network::find_by_key(network_key, pool).await?;
+    let opts = JoinCargoOptions {
+      cluster,
+      cargo,
+      network,
+      is_creating_relation,
+      indexes: None,
+    };
+    utils::cluster::join_cargo(&opts, config, docker_api, pool).await?;
+  }
+
+  Ok(results)
+}
+
+/// Plan a state file without changing anything
+/// Each resource of the state file is compared with the store
+/// and the containers of the joined cargoes with the live containers.
+///
+/// ## Arguments
+/// - [state](StateFile) The desired state
+/// - [docker_api](bollard::Docker) Docker api reference
+/// - [pool](Pool) Database pool reference
+///
+/// ## Return
+/// - [Result](Vec<StateResourceResult>) The action that would be taken for each resource
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn plan(
+  state: &StateFile,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, HttpResponseError> {
+  validate(state, pool).await?;
+  let nsp = utils::key::resolve_nsp(&state.namespace);
+  let mut results = vec![plan_namespace(&nsp, pool).await?];
+
+  for template in state.proxy_templates.iter().flatten() {
+    results.push(plan_proxy_template(template, pool).await?);
+  }
+
+  for cluster in state.clusters.iter().flatten() {
+    results.extend(plan_cluster(&nsp, cluster, pool).await?);
+  }
+
+  let mut updated_cargoes: Vec<String> = Vec::new();
+  for cargo in state.cargoes.iter().flatten() {
+    let res = plan_cargo(&nsp, cargo, pool).await?;
+    if res.action == StateAction::Update {
+      updated_cargoes.push(res.key.to_owned());
+    }
+    results.push(res);
+  }
+
+  for cluster in state.clusters.iter().flatten() {
+    let res =
+      plan_instances(&nsp, cluster, &updated_cargoes, docker_api, pool).await?;
+    results.extend(res);
+  }
+
+  Ok(results)
+}
+
+/// Apply the resources of a state file
+/// The result of each resource is pushed once it's applied
+async fn apply_resources(
+  state: StateFile,
+  results: &mut Vec<StateResourceResult>,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<(), HttpResponseError> {
+  let nsp = utils::key::resolve_nsp(&state.namespace);
+  results.push(apply_namespace(&nsp, pool).await?);
+  let mut changed_clusters: Vec<String> = Vec::new();
+
+  let mut updated_templates: Vec<String> = Vec::new();
+  for template in state.proxy_templates.unwrap_or_default() {
+    let res = apply_proxy_template(template, pool).await?;
+    if res.action == StateAction::Update {
+      updated_templates.push(res.key.to_owned());
+    }
+    results.push(res);
+  }
+
+  let clusters = state.clusters.unwrap_or_default();
+  for cluster in &clusters {
+    let cluster_results =
+      apply_cluster(&nsp, cluster, docker_api, pool).await?;
+    let uses_updated_template = cluster
+      .proxy_templates
+      .iter()
+      .flatten()
+      .any(|template| updated_templates.contains(template));
+    if uses_updated_template
+      || cluster_results
+        .iter()
+        .any(|res| res.action != StateAction::Leave)
+    {
+      changed_clusters.push(cluster.name.to_owned());
+    }
+    results.extend(cluster_results);
+  }
+
+  let mut updated_cargoes: Vec<String> = Vec::new();
+  for cargo in state.cargoes.unwrap_or_default() {
+    let res = apply_cargo(&nsp, cargo, config, docker_api, pool).await?;
+    if res.action == StateAction::Update {
+      updated_cargoes.push(res.key.to_owned());
+    }
+    results.push(res);
+  }
+
+  for cluster in &clusters {
+    let instance_results =
+      apply_instances(&nsp, cluster, config, docker_api, pool).await?;
+    let joins_updated_cargo = cluster.joins.iter().flatten().any(|join| {
+      updated_cargoes.contains(&utils::key::gen_key(&nsp, &join.cargo))
+    });
+    if (joins_updated_cargo
+      || instance_results
+        .iter()
+        .any(|res| res.action != StateAction::Leave))
+      && !changed_clusters.contains(&cluster.name)
+    {
+      changed_clusters.push(cluster.name.to_owned());
+    }
+    results.extend(instance_results);
+  }
+
+  for cluster_name in changed_clusters {
+    let key = utils::key::gen_key(&nsp, &cluster_name);
+    let cluster = repositories::cluster::find_by_key(key, pool).await?;
+    utils::cluster::start(&cluster, config, pool, docker_api).await?;
+  }
+
+  Ok(())
+}
+
+/// Apply a state file
+/// Each resource of the state file is created, updated, recreated or left as is
+/// and containers that don't belong to a joined cargo are deleted.
+/// Clusters with changes or using an updated proxy template are started
+/// to render their proxy templates.
+/// Resources are not rolled back on error, the error contains
+/// the results of the resources applied before it.
+///
+/// ## Arguments
+/// - [state](StateFile) The desired state
+/// - [config](DaemonConfig) Daemon config reference
+/// - [docker_api](bollard::Docker) Docker api reference
+/// - [pool](Pool) Database pool reference
+///
+/// ## Return
+/// - [Result](Vec<StateResourceResult>) The action taken for each resource
+/// - [Result](StateApplyError) The error with the resources applied before it
+pub async fn apply(
+  state: StateFile,
+  config: &DaemonConfig,
+  docker_api: &bollard::Docker,
+  pool: &Pool,
+) -> Result<Vec<StateResourceResult>, StateApplyError> {
+  validate(&state, pool).await?;
+  let mut results = Vec::new();
+  match apply_resources(state, &mut results, config, docker_api, pool).await {
+    Ok(_) => Ok(results),
+    Err(err) => Err(StateApplyError {
+      err,
+      applied: results,
+    }),
+  }
+}
+
+/// Convert a cargo from the store into a state file cargo
+async fn export_cargo(
+  item: CargoItem,
+  pool: &Pool,
+) -> Result<CargoPartial, HttpResponseError> {
+  let mut environnements =
+    repositories::cargo_env::list_by_cargo_key(item.key, pool)
+      .await?
+      .into_iter()
+      .map(|env| format!("{}={}", env.name, env.value))
+      .collect::<Vec<String>>();
+  environnements.sort();
+  Ok(CargoPartial {
+    name: item.name,
+    config: item.config,
+    dns_entry: item.dns_entry,
+    replicas: Some(item.replicas),
+    environnements: Some(environnements),
+    healthcheck: None,
+  })
+}
+
+/// Convert a cluster from the store into a state file cluster
+/// The joined cargoes are returned alongside the cluster
+async fn export_cluster_item(
+  item: ClusterItem,
+  pool: &Pool,
+) -> Result<(StateFileCluster, Vec<CargoItem>), HttpResponseError> {
+  let mut variables =
+    repositories::cluster_variable::list_by_cluster(item.key.to_owned(), pool)
+      .await?
+      .into_iter()
+      .map(|variable| ClusterVariablePartial {
+        name: variable.name,
+        value: variable.value,
+      })
+      .collect::<Vec<ClusterVariablePartial>>();
+  variables.sort_by(|a, b| a.name.cmp(&b.name));
+
+  let mut networks =
+    repositories::cluster_network::list_for_cluster(item.to_owned(), pool)
+      .await?
+      .into_iter()
+      .map(|network| ClusterNetworkPartial { name: network.name })
+      .collect::<Vec<ClusterNetworkPartial>>();
+  networks.sort_by(|a, b| a.name.cmp(&b.name));
+
+  let instances =
+    repositories::cargo_instance::get_by_cluster_key(item.key.to_owned(), pool)
+      .await?;
+  let mut joins = Vec::new();
+  let mut cargoes = Vec::new();
+  for instance in instances {
+    let cargo =
+      repositories::cargo::find_by_key(instance.cargo_key, pool).await?;
+    let network =
+      repositories::cluster_network::find_by_key(instance.network_key, pool)
+        .await?;
+    joins.push(ClusterJoinBody {
+      cargo: cargo.name.to_owned(),
+      network: network.name,
+    });
+    cargoes.push(cargo);
+  }
+  joins.sort_by(|a, b| a.cargo.cmp(&b.cargo));
+
+  let cluster = StateFileCluster {
+    name: item.name,
+    proxy_templates: Some(item.proxy_templates),
+    variables: Some(variables),
+    networks: Some(networks),
+    joins: Some(joins),
+  };
+  Ok((cluster, cargoes))
+}
+
+/// Build a state file from clusters and cargoes of the store
+/// The proxy templates used by the clusters are included
+async fn export_state_file(
+  nsp: &str,
+  clusters: Vec<ClusterItem>,
+  mut cargoes: Vec<CargoItem>,
+  pool: &Pool,
+) -> Result<StateFile, HttpResponseError> {
+  let mut template_names: Vec<String> = Vec::new();
+  let mut state_clusters = Vec::new();
+  for cluster in clusters {
+    let (cluster, joined_cargoes) = export_cluster_item(cluster, pool).await?;
+    for template in cluster.proxy_templates.iter().flatten() {
+      if !template_names.contains(template) {
+        template_names.push(template.to_owned());
+      }
+    }
+    for cargo in joined_cargoes {
+      if !cargoes.iter().any(|item| item.key == cargo.key) {
+        cargoes.push(cargo);
+      }
+    }
+    state_clusters.push(cluster);
+  }
+  state_clusters.sort_by(|a, b| a.name.cmp(&b.name));
+
+  template_names.sort();
+  let mut proxy_templates = Vec::new();
+  for name in template_names {
+    let template =
+      repositories::proxy_template::get_by_name(name, pool).await?;
+    proxy_templates.push(template);
+  }
+
+  cargoes.sort_by(|a, b| a.name.cmp(&b.name));
+  let mut state_cargoes = Vec::new();
+  for cargo in cargoes {
+    state_cargoes.push(export_cargo(cargo, pool).await?);
+  }
+
+  Ok(StateFile {
+    namespace: Some(nsp.to_owned()),
+    proxy_templates: Some(proxy_templates),
+    clusters: Some(state_clusters),
+    cargoes: Some(state_cargoes),
+  })
+}
+
+/// Export a cluster as a state file
+/// The state file contains the cluster with his variables, networks
+/// and joined cargoes and the proxy templates it use
+///
+/// ## Arguments
+/// - [nsp](str) The namespace of the cluster
+/// - [name](str) The name of the cluster
+/// - [pool](Pool) Database pool reference
+///
+/// ## Return
+/// - [Result](StateFile) The state file of the cluster
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn export_cluster(
+  nsp: &str,
+  name: &str,
+  pool: &Pool,
+) -> Result<StateFile, HttpResponseError> {
+  let key = utils::key::gen_key(nsp, name);
+  let cluster = repositories::cluster::find_by_key(key, pool).await?;
+  export_state_file(nsp, vec![cluster], Vec::new(), pool).await
+}
+
+/// Export a namespace as a state file
+/// The state file contains every clusters and cargoes of the namespace
+/// and the proxy templates used by the clusters
+///
+/// ## Arguments
+/// - [name](str) The name of the namespace
+/// - [pool](Pool) Database pool reference
+///
+/// ## Return
+/// - [Result](StateFile) The state file of the namespace
+/// - [Result](HttpResponseError) An http response error if something went wrong
+pub async fn export_namespace(
+  name: &str,
+  pool: &Pool,
+) -> Result<StateFile, HttpResponseError> {
+  let nsp =
+    repositories::namespace::inspect_by_name(name.to_owned(), pool).await?;
+  let clusters =
+    repositories::cluster::find_by_namespace(nsp.name.to_owned(), pool).await?;
+  let cargoes =
+    repositories::cargo::find_by_namespace(nsp.to_owned(), pool).await?;
+  export_state_file(&nsp.name, clusters, cargoes, pool).await
+}